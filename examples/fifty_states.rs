@@ -0,0 +1,43 @@
+use oxi_net::cnn::*;
+use oxi_net::fiftystates::load_50states10k;
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::prefetch::Prefetch;
+use oxi_net::activation::Activation;
+
+// Example CNN for 50States10K dataset
+// To run this example, download the 50States10K dataset
+// to the root of the project
+fn main() {
+    // Filter for only 2 states for faster training with 95% train/test split.
+    // Note: to save on memory, the images are loaded on demand. Worker threads
+    // decode them ahead of training, and the most recent ones are kept in a cache.
+    let filter = Some(vec!["Hawaii", "Alaska"]);
+    let data = load_50states10k("./50States10K/", 0.95, filter.clone()).unwrap();
+
+    // Set hyperparameters
+    let hyperparameters = Hyperparameters {
+        batch_size: 10,
+        epochs: 10,
+        optimizer: OptimizerAlg::RMSProp(0.001, 0.9),
+        prefetch: Prefetch { workers: 4, queue_size: 32, cache_mb: 512 },
+        ..Hyperparameters::default()
+    };
+
+    // Create CNN architecture
+    let mut cnn = CNN::new(data, hyperparameters);
+    cnn.set_input_shape(vec![256, 256, 3]);
+    cnn.add_mxpl_layer(2);
+    cnn.add_mxpl_layer(2);
+    cnn.add_mxpl_layer(2);
+    cnn.add_conv_layer(8, 3);
+    cnn.add_mxpl_layer(2);
+    cnn.add_dense_layer(256, Activation::Relu, Some(0.25));
+    cnn.add_dense_layer(128, Activation::Relu, Some(0.25));
+    let output_neurons = match filter {
+        Some(f) => f.len(),
+        None => 50,
+    };
+    cnn.add_dense_layer(output_neurons, Activation::Softmax, None);
+
+    cnn.train().unwrap();
+}
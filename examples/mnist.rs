@@ -0,0 +1,31 @@
+use oxi_net::cnn::*;
+use oxi_net::mnist::load_mnist;
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::activation::Activation;
+
+// Example CNN for the MNIST dataset
+// To run this example, place the MNIST dataset in a folder named `data`
+fn main() {
+    // Load MNIST dataset
+    let data = load_mnist("./data/").unwrap();
+
+    // Set hyperparameters
+    let hyperparameters = Hyperparameters {
+        batch_size: 10,
+        epochs: 10,
+        optimizer: OptimizerAlg::SGD(0.1),
+        ..Hyperparameters::default()
+    };
+
+    // Create CNN architecture
+    let mut cnn = CNN::new(data, hyperparameters);
+    cnn.set_input_shape(vec![28, 28, 1]);
+    cnn.add_conv_layer(8, 3);
+    cnn.add_mxpl_layer(2);
+    cnn.add_dense_layer(128, Activation::Relu, Some(0.25));
+    cnn.add_dense_layer(64, Activation::Relu, Some(0.25));
+    cnn.add_dense_layer(10, Activation::Softmax, None);
+    println!("{:?}", cnn.summary());
+
+    cnn.train().unwrap();
+}
@@ -0,0 +1,299 @@
+use ndarray::{Array1, Array3, Axis};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand_distr::{Beta, Distribution, Normal};
+use serde::{Serialize, Deserialize};
+use crate::dataset::{Dataset, Label};
+
+/// A random transformation applied to training images
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Augmentation {
+    /// Mirrors the image left to right with the given probability
+    HorizontalFlip { probability: f32 },
+    /// Mirrors the image top to bottom with the given probability
+    VerticalFlip { probability: f32 },
+    /// Pads every side with `padding` zeros, then crops a random window of the original size
+    RandomCrop { padding: usize },
+    /// Rotates about the centre by a random angle of up to `max_degrees` either way
+    Rotation { max_degrees: f32 },
+    /// Shifts by up to `max_fraction` of the height and width either way, filling with zeros
+    Translation { max_fraction: f32 },
+    /// Scales the brightness by a random factor in `1 ± brightness`, then the
+    /// contrast about the mean by a random factor in `1 ± contrast`
+    ColorJitter { brightness: f32, contrast: f32 },
+    /// Adds zero-mean Gaussian noise to every value
+    GaussianNoise { std_dev: f32 },
+    /// Zeroes a `size` x `size` square at a random position, which may overhang the edges
+    Cutout { size: usize },
+    /// Blends the image with another random training image, weighting the two
+    /// images and their labels by a ratio drawn from Beta(alpha, alpha)
+    Mixup { alpha: f32 },
+    /// Pastes a box from another random training image, weighting the labels by
+    /// the area of each image, with the area ratio drawn from Beta(alpha, alpha)
+    Cutmix { alpha: f32 },
+}
+
+impl Augmentation {
+    /// Whether this step combines two training images, which needs their labels
+    pub fn mixes(&self) -> bool {
+        matches!(self, Augmentation::Mixup { .. } | Augmentation::Cutmix { .. })
+    }
+
+    fn parameter_errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        match *self {
+            Augmentation::HorizontalFlip { probability } | Augmentation::VerticalFlip { probability } => {
+                if !(0.0..=1.0).contains(&probability) {
+                    errors.push(format!("probability must be in [0, 1], got {}", probability));
+                }
+            }
+            Augmentation::RandomCrop { .. } => {}
+            Augmentation::Rotation { max_degrees } => {
+                if !max_degrees.is_finite() {
+                    errors.push(format!("max_degrees must be finite, got {}", max_degrees));
+                }
+            }
+            Augmentation::Translation { max_fraction } => {
+                if !(0.0..1.0).contains(&max_fraction) {
+                    errors.push(format!("max_fraction must be in [0, 1), got {}", max_fraction));
+                }
+            }
+            Augmentation::ColorJitter { brightness, contrast } => {
+                for (name, value) in [("brightness", brightness), ("contrast", contrast)] {
+                    if !(0.0..=1.0).contains(&value) {
+                        errors.push(format!("{} must be in [0, 1], got {}", name, value));
+                    }
+                }
+            }
+            Augmentation::GaussianNoise { std_dev } => {
+                if std_dev.is_nan() || std_dev < 0.0 {
+                    errors.push(format!("std_dev must not be negative, got {}", std_dev));
+                }
+            }
+            Augmentation::Cutout { size } => {
+                if size == 0 {
+                    errors.push(String::from("size must be at least 1"));
+                }
+            }
+            Augmentation::Mixup { alpha } | Augmentation::Cutmix { alpha } => {
+                if alpha.is_nan() || alpha <= 0.0 {
+                    errors.push(format!("alpha must be positive, got {}", alpha));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Applies a step that transforms a single image; mixing steps return it unchanged
+    fn transform<R: Rng + ?Sized>(&self, image: Array3<f32>, rng: &mut R) -> Array3<f32> {
+        match *self {
+            Augmentation::HorizontalFlip { probability } => {
+                if rng.gen::<f32>() < probability { flip(image, Axis(1)) } else { image }
+            }
+            Augmentation::VerticalFlip { probability } => {
+                if rng.gen::<f32>() < probability { flip(image, Axis(0)) } else { image }
+            }
+            Augmentation::RandomCrop { padding } => {
+                let padding = padding as isize;
+                let dy = rng.gen_range(-padding..=padding);
+                let dx = rng.gen_range(-padding..=padding);
+                shift(&image, dy, dx)
+            }
+            Augmentation::Rotation { max_degrees } => {
+                let degrees = rng.gen_range(-max_degrees.abs()..=max_degrees.abs());
+                rotate(&image, degrees.to_radians())
+            }
+            Augmentation::Translation { max_fraction } => {
+                let (rows, cols, _) = image.dim();
+                let max_dy = (rows as f32 * max_fraction).round() as isize;
+                let max_dx = (cols as f32 * max_fraction).round() as isize;
+                let dy = rng.gen_range(-max_dy..=max_dy);
+                let dx = rng.gen_range(-max_dx..=max_dx);
+                shift(&image, dy, dx)
+            }
+            Augmentation::ColorJitter { brightness, contrast } => {
+                let brightness = rng.gen_range(1.0 - brightness..=1.0 + brightness);
+                let contrast = rng.gen_range(1.0 - contrast..=1.0 + contrast);
+                let image = image * brightness;
+                let mean = image.mean().unwrap_or(0.0);
+                image.mapv(|x| (x - mean) * contrast + mean)
+            }
+            Augmentation::GaussianNoise { std_dev } => {
+                let normal = Normal::new(0.0, std_dev).unwrap();
+                image.mapv(|x| x + normal.sample(rng))
+            }
+            Augmentation::Cutout { size } => {
+                let (rows, cols, _) = image.dim();
+                let (top, left) = random_box(rows, cols, size, size, rng);
+                let mut image = image;
+                image.slice_mut(ndarray::s![top.0..top.1, left.0..left.1, ..]).fill(0.0);
+                image
+            }
+            Augmentation::Mixup { .. } | Augmentation::Cutmix { .. } => image,
+        }
+    }
+}
+
+/// Ordered augmentation steps applied to every training sample.
+/// Testing and evaluation always see the original images.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AugmentationPipeline {
+    pub steps: Vec<Augmentation>,
+    /// Seeds the sampling and augmentation of every call to `CNN::train`,
+    /// so training runs draw the same samples. Without it every run differs.
+    pub seed: Option<u64>,
+}
+
+impl AugmentationPipeline {
+    pub fn new() -> AugmentationPipeline {
+        AugmentationPipeline::default()
+    }
+
+    /// Adds a step after the existing ones
+    pub fn step(mut self, step: Augmentation) -> AugmentationPipeline {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> AugmentationPipeline {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Invalid parameters of every step, each prefixed with its index
+    pub fn errors(&self) -> Vec<String> {
+        self.steps.iter()
+            .enumerate()
+            .flat_map(|(i, step)| step.parameter_errors().into_iter().map(move |e| format!("Augmentation {}: {}", i, e)))
+            .collect()
+    }
+
+    /// The random number generator for a training run, seeded if `seed` is set
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Applies every step that transforms a single image, in order
+    pub fn transform<R: Rng + ?Sized>(&self, image: Array3<f32>, rng: &mut R) -> Array3<f32> {
+        self.steps.iter().fold(image, |image, step| step.transform(image, rng))
+    }
+
+    /// Sample `index` of `data` with every step applied, and its desired output over
+    /// `num_classes` classes. The output is one-hot unless a mixing step blended in
+    /// another random sample, which is transformed the same way first. When the
+    /// pipeline has several mixing steps, one of them is picked at random.
+    pub fn augment<D: Dataset + ?Sized, R: Rng + ?Sized>(&self, data: &D, index: usize, num_classes: usize, rng: &mut R) -> (Array3<f32>, Array1<f32>) {
+        self.augment_sample(data.get(index), data, num_classes, rng)
+    }
+
+    /// Like `augment`, for a sample that was already read from `data`.
+    /// Mixing steps still read their second sample from `data`.
+    pub fn augment_sample<D: Dataset + ?Sized, R: Rng + ?Sized>(&self, sample: (Array3<f32>, Label), data: &D, num_classes: usize, rng: &mut R) -> (Array3<f32>, Array1<f32>) {
+        let (image, label) = sample;
+        let image = self.transform(image, rng);
+        let target = one_hot(label, num_classes);
+
+        let mixing: Vec<&Augmentation> = self.steps.iter().filter(|step| step.mixes()).collect();
+        if mixing.is_empty() {
+            return (image, target);
+        }
+        let (other, other_label) = data.get(rng.gen_range(0..data.len()));
+        let other = self.transform(other, rng);
+        let other_target = one_hot(other_label, num_classes);
+
+        let (image, ratio) = match *mixing[rng.gen_range(0..mixing.len())] {
+            Augmentation::Mixup { alpha } => {
+                let ratio = Beta::new(alpha, alpha).unwrap().sample(rng);
+                (&image * ratio + &other * (1.0 - ratio), ratio)
+            }
+            Augmentation::Cutmix { alpha } => {
+                let (rows, cols, _) = image.dim();
+                let cut = (1.0 - Beta::new(alpha, alpha).unwrap().sample(rng)).sqrt();
+                let height = (rows as f32 * cut).round() as usize;
+                let width = (cols as f32 * cut).round() as usize;
+                let (top, left) = random_box(rows, cols, height, width, rng);
+                let mut image = image;
+                let region = ndarray::s![top.0..top.1, left.0..left.1, ..];
+                image.slice_mut(region).assign(&other.slice(region));
+                // The box may be clipped at the edges, so weight by the area actually pasted
+                let ratio = 1.0 - ((top.1 - top.0) * (left.1 - left.0)) as f32 / (rows * cols) as f32;
+                (image, ratio)
+            }
+            _ => unreachable!(),
+        };
+
+        (image, target * ratio + other_target * (1.0 - ratio))
+    }
+}
+
+fn one_hot(label: usize, num_classes: usize) -> Array1<f32> {
+    Array1::from_shape_fn(num_classes, |i| (i == label) as usize as f32)
+}
+
+fn flip(mut image: Array3<f32>, axis: Axis) -> Array3<f32> {
+    image.invert_axis(axis);
+    image
+}
+
+/// Moves the image down by `dy` and right by `dx`, filling the uncovered area with zeros
+fn shift(image: &Array3<f32>, dy: isize, dx: isize) -> Array3<f32> {
+    let (rows, cols, _) = image.dim();
+    Array3::from_shape_fn(image.dim(), |(row, col, channel)| {
+        let (src_row, src_col) = (row as isize - dy, col as isize - dx);
+        if (0..rows as isize).contains(&src_row) && (0..cols as isize).contains(&src_col) {
+            image[[src_row as usize, src_col as usize, channel]]
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Rotates counterclockwise about the centre with bilinear sampling,
+/// filling areas outside the original image with zeros
+fn rotate(image: &Array3<f32>, radians: f32) -> Array3<f32> {
+    let (rows, cols, _) = image.dim();
+    let (centre_y, centre_x) = ((rows as f32 - 1.0) / 2.0, (cols as f32 - 1.0) / 2.0);
+    let (sin, cos) = radians.sin_cos();
+    let pixel = |row: isize, col: isize, channel: usize| {
+        if (0..rows as isize).contains(&row) && (0..cols as isize).contains(&col) {
+            image[[row as usize, col as usize, channel]]
+        } else {
+            0.0
+        }
+    };
+    Array3::from_shape_fn(image.dim(), |(row, col, channel)| {
+        // Rows grow downwards, which flips the sign of sin from the usual rotation matrix
+        let (y, x) = (row as f32 - centre_y, col as f32 - centre_x);
+        let src_y = sin * x + cos * y + centre_y;
+        let src_x = cos * x - sin * y + centre_x;
+        let (y0, x0) = (src_y.floor(), src_x.floor());
+        let (fy, fx) = (src_y - y0, src_x - x0);
+        let (y0, x0) = (y0 as isize, x0 as isize);
+        pixel(y0, x0, channel) * (1.0 - fy) * (1.0 - fx)
+            + pixel(y0, x0 + 1, channel) * (1.0 - fy) * fx
+            + pixel(y0 + 1, x0, channel) * fy * (1.0 - fx)
+            + pixel(y0 + 1, x0 + 1, channel) * fy * fx
+    })
+}
+
+/// Row and column ranges of a `height` x `width` box centred on a random pixel,
+/// clipped to the image
+fn random_box<R: Rng + ?Sized>(rows: usize, cols: usize, height: usize, width: usize, rng: &mut R) -> ((usize, usize), (usize, usize)) {
+    let clip = |centre: usize, size: usize, len: usize| {
+        let start = centre as isize - size as isize / 2;
+        let end = start + size as isize;
+        (start.clamp(0, len as isize) as usize, end.clamp(0, len as isize) as usize)
+    };
+    let centre_y = rng.gen_range(0..rows);
+    let centre_x = rng.gen_range(0..cols);
+    (clip(centre_y, height, rows), clip(centre_x, width, cols))
+}
@@ -0,0 +1,225 @@
+use crate::activation::Activation;
+use crate::augment::AugmentationPipeline;
+use crate::cnn::{Hyperparameters, CNN};
+use crate::config::{self, LayerSpec, NetworkConfig};
+use crate::dataset::class_counts;
+use crate::loss::{ClassWeights, Loss};
+use crate::normalize::Normalize;
+use crate::prefetch::Prefetch;
+use crate::sampler::Sampler;
+use crate::optimizer::{LrSchedule, OptimizerAlg};
+use crate::util::TrainingData;
+
+/// Collects a network description and builds it in one step.
+/// Unlike the `CNN::add_*` methods, nothing is checked or allocated until
+/// `build()`, which reports every problem in the description at once.
+#[derive(Clone, Default)]
+pub struct CnnBuilder {
+    input_shape: Option<Vec<usize>>,
+    layers: Vec<LayerSpec>,
+    params: Hyperparameters,
+}
+
+impl CnnBuilder {
+    pub fn new() -> CnnBuilder {
+        CnnBuilder::default()
+    }
+
+    /// Builder holding the architecture and hyperparameters of a config
+    pub fn from_config(config: &NetworkConfig) -> CnnBuilder {
+        CnnBuilder {
+            input_shape: Some(config.input_shape.clone()),
+            layers: config.layers.clone(),
+            params: config.hyperparameters.clone(),
+        }
+    }
+
+    /// The config describing the builder's network, if the input shape is set
+    pub fn to_config(&self) -> Option<NetworkConfig> {
+        Some(NetworkConfig {
+            input_shape: self.input_shape.clone()?,
+            layers: self.layers.clone(),
+            hyperparameters: self.params.clone(),
+        })
+    }
+
+    /// [rows, cols, channels], where cols and channels default to 1
+    pub fn input_shape(mut self, input_shape: Vec<usize>) -> CnnBuilder {
+        self.input_shape = Some(input_shape);
+        self
+    }
+
+    pub fn layer(mut self, layer: LayerSpec) -> CnnBuilder {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Adds a convolutional layer, see `CNN::add_conv_layer`
+    pub fn conv(self, num_filters: usize, kernel_size: usize) -> CnnBuilder {
+        self.layer(LayerSpec::Conv { num_filters, kernel_size })
+    }
+
+    /// Adds a max pooling layer, see `CNN::add_mxpl_layer`
+    pub fn mxpl(self, kernel_size: usize) -> CnnBuilder {
+        self.layer(LayerSpec::Mxpl { kernel_size })
+    }
+
+    /// Adds a fully connected layer, see `CNN::add_dense_layer`
+    pub fn dense(self, output_size: usize, activation: Activation, dropout: Option<f32>) -> CnnBuilder {
+        self.layer(LayerSpec::Dense { output_size, activation, dropout })
+    }
+
+    /// Replaces all hyperparameters
+    pub fn hyperparameters(mut self, params: Hyperparameters) -> CnnBuilder {
+        self.params = params;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> CnnBuilder {
+        self.params.batch_size = batch_size;
+        self
+    }
+
+    pub fn epochs(mut self, epochs: usize) -> CnnBuilder {
+        self.params.epochs = epochs;
+        self
+    }
+
+    pub fn optimizer(mut self, optimizer: OptimizerAlg) -> CnnBuilder {
+        self.params.optimizer = optimizer;
+        self
+    }
+
+    pub fn loss(mut self, loss: Loss) -> CnnBuilder {
+        self.params.loss = loss;
+        self
+    }
+
+    pub fn lr_schedule(mut self, lr_schedule: LrSchedule) -> CnnBuilder {
+        self.params.lr_schedule = lr_schedule;
+        self
+    }
+
+    pub fn augmentation(mut self, augmentation: AugmentationPipeline) -> CnnBuilder {
+        self.params.augmentation = augmentation;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: Normalize) -> CnnBuilder {
+        self.params.normalize = normalize;
+        self
+    }
+
+    pub fn prefetch(mut self, prefetch: Prefetch) -> CnnBuilder {
+        self.params.prefetch = prefetch;
+        self
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> CnnBuilder {
+        self.params.sampler = sampler;
+        self
+    }
+
+    pub fn class_weights(mut self, class_weights: ClassWeights) -> CnnBuilder {
+        self.params.class_weights = class_weights;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> CnnBuilder {
+        self.params.name = String::from(name);
+        self
+    }
+
+    pub fn layers(&self) -> &[LayerSpec] {
+        &self.layers
+    }
+
+    pub fn params(&self) -> &Hyperparameters {
+        &self.params
+    }
+
+    /// Output shape of every layer, or every problem with the description, one per line
+    pub fn output_shapes(&self) -> Result<Vec<(usize, usize, usize)>, String> {
+        let mut errors = vec![];
+        let input_shape = match &self.input_shape {
+            None => {
+                errors.push(String::from("Input shape not set, use .input_shape()"));
+                None
+            }
+            Some(input_shape) => match config::parse_input_shape(input_shape) {
+                Ok(input_shape) => Some(input_shape),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            },
+        };
+        let shapes = config::infer_shapes(input_shape, &self.layers);
+        if let Err(e) = &shapes {
+            errors.extend(e.iter().cloned());
+        }
+        errors.extend(config::hyperparameter_errors(&self.params));
+        errors.extend(config::normalization_errors(input_shape, &self.params.normalize));
+        errors.extend(config::class_errors(&self.layers, &self.params));
+        errors.extend(config::loss_errors(&self.layers, &self.params));
+
+        match shapes {
+            Ok(shapes) if errors.is_empty() => Ok(shapes),
+            _ => Err(errors.join("\n")),
+        }
+    }
+
+    /// Checks the description against `data`: the number of classes, the
+    /// sampler weights and the shape of the images
+    pub fn data_errors(&self, data: &TrainingData) -> Vec<String> {
+        self.data_errors_with(data, self.output_shapes().is_ok())
+    }
+
+    fn data_errors_with(&self, data: &TrainingData, shapes_ok: bool) -> Vec<String> {
+        let mut errors = vec![];
+        if let Some(LayerSpec::Dense { output_size, .. }) = self.layers.last() {
+            if *output_size < data.classes.len() {
+                errors.push(format!(
+                    "Layer {}: has {} outputs but the training data has {} classes",
+                    self.layers.len() - 1, output_size, data.classes.len()
+                ));
+            }
+            if let Ok(counts) = class_counts(&data.train_set(), *output_size) {
+                errors.extend(self.params.sampler.data_errors(&counts));
+            }
+        }
+        // Output shapes are only inferred from a valid input shape
+        if shapes_ok {
+            let input_shape = config::parse_input_shape(self.input_shape.as_ref().unwrap()).unwrap();
+            if let Err(e) = data.check_input_shape(input_shape) {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    /// Validates the whole description and only then allocates the network
+    pub fn build(&self, data: TrainingData) -> Result<CNN, String> {
+        let shapes = self.output_shapes();
+        let mut errors: Vec<String> = match &shapes {
+            Ok(_) => vec![],
+            Err(e) => e.lines().map(String::from).collect(),
+        };
+        errors.extend(self.data_errors_with(&data, shapes.is_ok()));
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+
+        let mut cnn = CNN::new(data, self.params.clone());
+        cnn.set_input_shape(self.input_shape.clone().unwrap());
+        for layer in &self.layers {
+            match *layer {
+                LayerSpec::Conv { num_filters, kernel_size } => cnn.add_conv_layer(num_filters, kernel_size),
+                LayerSpec::Mxpl { kernel_size } => cnn.add_mxpl_layer(kernel_size),
+                LayerSpec::Dense { output_size, activation, dropout } => cnn.add_dense_layer(output_size, activation, dropout),
+            }
+        }
+
+        Ok(cnn)
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+use ndarray::Array3;
+use crate::util::{ImageOptions, TrainImage, TrainingData};
+
+/// Rows and columns of every CIFAR image
+const SIZE: usize = 32;
+/// Bytes of pixel data per image: the red, green and blue planes in turn, each row-major
+const IMAGE_BYTES: usize = SIZE * SIZE * 3;
+
+const CIFAR10_TRAIN_FILES: [&str; 5] = ["data_batch_1.bin", "data_batch_2.bin", "data_batch_3.bin", "data_batch_4.bin", "data_batch_5.bin"];
+const CIFAR10_TEST_FILE: &str = "test_batch.bin";
+const CIFAR100_TRAIN_FILE: &str = "train.bin";
+const CIFAR100_TEST_FILE: &str = "test.bin";
+
+pub const CIFAR10_LABELS: [&str; 10] = [
+    "airplane", "automobile", "bird", "cat", "deer", "dog", "frog", "horse", "ship", "truck",
+];
+
+pub const CIFAR100_COARSE_LABELS: [&str; 20] = [
+    "aquatic_mammals", "fish", "flowers", "food_containers", "fruit_and_vegetables",
+    "household_electrical_devices", "household_furniture", "insects", "large_carnivores",
+    "large_man-made_outdoor_things", "large_natural_outdoor_scenes", "large_omnivores_and_herbivores",
+    "medium_mammals", "non-insect_invertebrates", "people", "reptiles", "small_mammals", "trees",
+    "vehicles_1", "vehicles_2",
+];
+
+pub const CIFAR100_FINE_LABELS: [&str; 100] = [
+    "apple", "aquarium_fish", "baby", "bear", "beaver", "bed", "bee", "beetle", "bicycle", "bottle",
+    "bowl", "boy", "bridge", "bus", "butterfly", "camel", "can", "castle", "caterpillar", "cattle",
+    "chair", "chimpanzee", "clock", "cloud", "cockroach", "couch", "crab", "crocodile", "cup", "dinosaur",
+    "dolphin", "elephant", "flatfish", "forest", "fox", "girl", "hamster", "house", "kangaroo", "keyboard",
+    "lamp", "lawn_mower", "leopard", "lion", "lizard", "lobster", "man", "maple_tree", "motorcycle", "mountain",
+    "mouse", "mushroom", "oak_tree", "orange", "orchid", "otter", "palm_tree", "pear", "pickup_truck", "pine_tree",
+    "plain", "plate", "poppy", "porcupine", "possum", "rabbit", "raccoon", "ray", "road", "rocket",
+    "rose", "sea", "seal", "shark", "shrew", "skunk", "skyscraper", "snail", "snake", "spider",
+    "squirrel", "streetcar", "sunflower", "sweet_pepper", "table", "tank", "telephone", "television", "tiger", "tractor",
+    "train", "trout", "tulip", "turtle", "wardrobe", "whale", "willow_tree", "wolf", "woman", "worm",
+];
+
+/// Which of the two CIFAR-100 labels is used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cifar100Labels {
+    /// The 20 superclasses
+    Coarse,
+    /// The 100 classes
+    Fine,
+}
+
+/// Loads the CIFAR-10 binary version from the folder holding `data_batch_1.bin`
+/// to `data_batch_5.bin` and `test_batch.bin`, as 32x32x3 images
+pub fn load_cifar10<P: AsRef<Path>>(path: P) -> Result<TrainingData, String> {
+    let path = path.as_ref();
+    let mut trn = vec![];
+    for file in CIFAR10_TRAIN_FILES {
+        trn.extend(read_batch(&path.join(file), 1, 0)?);
+    }
+    let tst = read_batch(&path.join(CIFAR10_TEST_FILE), 1, 0)?;
+
+    training_data(trn, tst, &CIFAR10_LABELS)
+}
+
+/// Loads the CIFAR-100 binary version from the folder holding `train.bin`
+/// and `test.bin`, as 32x32x3 images
+pub fn load_cifar100<P: AsRef<Path>>(path: P, labels: Cifar100Labels) -> Result<TrainingData, String> {
+    let path = path.as_ref();
+    // Every record starts with the coarse label, then the fine label
+    let (label_offset, names): (usize, &[&str]) = match labels {
+        Cifar100Labels::Coarse => (0, &CIFAR100_COARSE_LABELS),
+        Cifar100Labels::Fine => (1, &CIFAR100_FINE_LABELS),
+    };
+    let trn = read_batch(&path.join(CIFAR100_TRAIN_FILE), 2, label_offset)?;
+    let tst = read_batch(&path.join(CIFAR100_TEST_FILE), 2, label_offset)?;
+
+    training_data(trn, tst, names)
+}
+
+fn read_batch(path: &Path, label_bytes: usize, label_offset: usize) -> Result<Vec<(Array3<f32>, usize)>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_batch(&bytes, label_bytes, label_offset).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Parses a CIFAR binary batch, where every record is `label_bytes` label
+/// bytes followed by the pixels, using the label at `label_offset`
+pub fn parse_batch(bytes: &[u8], label_bytes: usize, label_offset: usize) -> Result<Vec<(Array3<f32>, usize)>, String> {
+    let record_size = label_bytes + IMAGE_BYTES;
+    if bytes.len() % record_size != 0 {
+        return Err(format!(
+            "File size {} is not a multiple of the {} byte record size",
+            bytes.len(), record_size
+        ));
+    }
+
+    Ok(bytes.chunks_exact(record_size).map(|record| {
+        let label = record[label_offset] as usize;
+        let pixels = &record[label_bytes..];
+        let image = Array3::from_shape_fn((SIZE, SIZE, 3), |(row, col, channel)| {
+            pixels[channel * SIZE * SIZE + row * SIZE + col] as f32 / 255.0
+        });
+        (image, label)
+    }).collect())
+}
+
+fn training_data(trn: Vec<(Array3<f32>, usize)>, tst: Vec<(Array3<f32>, usize)>, names: &[&str]) -> Result<TrainingData, String> {
+    if let Some((_, label)) = trn.iter().chain(tst.iter()).find(|(_, label)| *label >= names.len()) {
+        return Err(format!("Label {} is out of range for {} classes", label, names.len()));
+    }
+    let (trn_img, trn_lbl): (Vec<TrainImage>, Vec<usize>) = trn.into_iter()
+        .map(|(image, label)| (TrainImage::Image(image), label))
+        .unzip();
+    let (tst_img, tst_lbl): (Vec<TrainImage>, Vec<usize>) = tst.into_iter()
+        .map(|(image, label)| (TrainImage::Image(image), label))
+        .unzip();
+
+    Ok(TrainingData {
+        trn_size: trn_img.len(),
+        tst_size: tst_img.len(),
+        trn_img,
+        trn_lbl,
+        tst_img,
+        tst_lbl,
+        rows: SIZE,
+        cols: SIZE,
+        classes: (0..names.len()).map(|i| (i, i)).collect(),
+        label_names: names.iter().enumerate().map(|(i, name)| (i, String::from(*name))).collect(),
+        image_options: ImageOptions::default(),
+    })
+}
@@ -1,30 +1,70 @@
 use core::panic;
-use std::io::Write;
-use std::fs::File;
 use std::fmt::{Debug, Formatter};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::default::Default;
 use indicatif::{ProgressBar, ProgressStyle};
-use ndarray::{Array1, Array3};
+use ndarray::{Array1, Array3, ArrayD};
+use rand::Rng;
 use serde::{Serialize, Deserialize};
 use crate::activation::Activation;
+use crate::augment::AugmentationPipeline;
+use crate::builder::CnnBuilder;
+use crate::config::{LayerSpec, NetworkConfig};
+use crate::dataset::Dataset;
+use crate::metrics::{Metrics, TopNAccuracy};
+use crate::model_file::{self, ModelFormat};
+use crate::numpy::{self, Param, WeightLayout};
+use crate::onnx;
 use crate::util::*;
-use crate::optimizer::OptimizerAlg;
-// use crate::fiftystates::*;
-use crate::mnist::*;
+use crate::loss::{self, ClassWeights, Loss};
+use crate::normalize::{Normalization, Normalize};
+use crate::prefetch::{prefetch, CachedDataset, Prefetch};
+use crate::sampler::{Sampler, WeightedSampler};
+use crate::optimizer::{LrSchedule, OptimizerAlg};
+use crate::predictor::Predictor;
+use crate::summary::ModelSummary;
 
 use crate::{
     conv_layer::ConvLayer, dense_layer::DenseLayer, layer::Layer,
     mxpl_layer::MxplLayer,
 };
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct Hyperparameters {
     pub batch_size: usize,
     pub epochs: usize,
     pub optimizer: OptimizerAlg,
+    pub loss: Loss,
+    /// Scales the optimizer's learning rate at the start of every epoch
+    pub lr_schedule: LrSchedule,
+    /// Random transformations applied to training samples, never to testing ones
+    pub augmentation: AugmentationPipeline,
+    /// Per-channel normalization applied to every input, during training and inference
+    pub normalize: Normalize,
+    /// Decoding samples ahead on worker threads and caching them between epochs
+    pub prefetch: Prefetch,
+    /// How training samples are drawn, such as balanced across classes
+    pub sampler: Sampler,
+    /// Per-class weights of the loss
+    pub class_weights: ClassWeights,
     pub saving_strategy: SavingStrategy,
     pub name: String,
     pub verbose: bool,
+    pub model_format: ModelFormat,
+    /// Directory that models are saved to
+    pub save_dir: PathBuf,
+    /// File name for saved models, without an extension. `{name}` is replaced with
+    /// the model name, `{time}` with the creation time, `{epoch}` with the number of
+    /// completed epochs and `{timestamp}` with the time of the save, in milliseconds.
+    pub file_name: String,
+    /// If set, only the most recent N full saves in the save directory that match
+    /// `file_name` are kept, and older saves are deleted with their metadata.
+    /// Must be at least 1.
+    pub keep_last: Option<usize>,
 }
 
 impl Default for Hyperparameters {
@@ -33,9 +73,20 @@ impl Default for Hyperparameters {
             batch_size: 32,
             epochs: 10,
             optimizer: OptimizerAlg::Adam(0.9, 0.999, 1e-8),
+            loss: Loss::CrossEntropy,
+            lr_schedule: LrSchedule::Constant,
+            augmentation: AugmentationPipeline::default(),
+            normalize: Normalize::None,
+            prefetch: Prefetch::default(),
+            sampler: Sampler::Uniform,
+            class_weights: ClassWeights::None,
             saving_strategy: SavingStrategy::Never,
             name: String::from("model"),
             verbose: true,
+            model_format: ModelFormat::Json,
+            save_dir: PathBuf::from("models"),
+            file_name: String::from("{name}_{time}"),
+            keep_last: None,
         }
     }
 }
@@ -44,7 +95,8 @@ impl Default for Hyperparameters {
 pub struct CNN {
     layers: Vec<Layer>,
     layer_order: Vec<String>,
-    data: TrainingData,
+    /// Shared so that `train` can read it while updating the network
+    data: Arc<TrainingData>,
     minibatch_size: usize,
     creation_time: SystemTime,
     saving_strategy: SavingStrategy,
@@ -54,20 +106,40 @@ pub struct CNN {
     name: String,
     verbose: bool,
     optimizer: OptimizerAlg,
+    loss: Loss,
+    lr_schedule: LrSchedule,
+    augmentation: AugmentationPipeline,
+    normalize: Normalize,
+    /// Statistics applied to every input, set from `normalize`
+    normalization: Option<Normalization>,
+    prefetch: Prefetch,
+    sampler: Sampler,
+    class_weights: ClassWeights,
+    /// Weight of every class in the loss, set from `class_weights` when training starts
+    #[serde(skip)]
+    loss_weights: Option<Array1<f32>>,
     epochs: usize,
     input_shape: (usize, usize, usize),
+    model_format: ModelFormat,
+    save_dir: PathBuf,
+    file_name: String,
+    keep_last: Option<usize>,
+    /// Path of the last full save, without an extension
+    #[serde(skip)]
+    last_checkpoint: Option<PathBuf>,
 }
 
 impl Debug for CNN {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
         let time = self.creation_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
-        s.push_str(&format!("File: models/{}_{}.json\n", self.name, time));
+        let checkpoint = self.last_checkpoint.clone().unwrap_or_else(|| self.checkpoint_stem());
+        s.push_str(&format!("File: {}\n", with_extension(&checkpoint, self.model_format.extension()).display()));
         s.push_str(&format!("Time: {}\n", time));
         s.push_str(&format!("Minibatch size: {}\n", self.minibatch_size));
         s.push_str(&format!("Training size: {}\n", self.data.trn_size));
         s.push_str(&format!("Testing size: {}\n", self.data.tst_size));
-        s.push_str(&format!("\nLayers:\n"));
+        s.push_str("\nLayers:\n");
         
         for layer in &self.layers {
             s.push_str(&format!("{:?}\n", layer));
@@ -88,7 +160,7 @@ impl CNN {
         let cnn: CNN = CNN {
             layers: vec![],
             layer_order: vec![],
-            data,
+            data: Arc::new(data),
             minibatch_size: params.batch_size,
             creation_time,
             saving_strategy: params.saving_strategy,
@@ -98,18 +170,97 @@ impl CNN {
             name: params.name,
             verbose: params.verbose,
             optimizer: params.optimizer,
+            loss: params.loss,
+            lr_schedule: params.lr_schedule,
+            augmentation: params.augmentation,
+            normalization: match &params.normalize {
+                Normalize::Fixed { mean, std } => Some(Normalization { mean: mean.clone(), std: std.clone() }),
+                _ => None,
+            },
+            normalize: params.normalize,
+            prefetch: params.prefetch,
+            sampler: params.sampler,
+            class_weights: params.class_weights,
+            loss_weights: None,
             epochs: params.epochs,
             input_shape: (0, 0, 0),
+            model_format: params.model_format,
+            save_dir: params.save_dir,
+            file_name: params.file_name,
+            keep_last: params.keep_last,
+            last_checkpoint: None,
         };
 
         cnn
     }
 
+    /// Builds the network described by a JSON or TOML config file, see `NetworkConfig`
+    pub fn from_config<P: AsRef<Path>>(path: P, data: TrainingData) -> Result<CNN, String> {
+        let path = path.as_ref();
+        let config = NetworkConfig::load(path)?;
+        CNN::from_network_config(&config, data).map_err(|e| format!("Invalid config {}:\n{}", path.display(), e))
+    }
+
+    /// Validates the config and builds the network it describes
+    pub fn from_network_config(config: &NetworkConfig, data: TrainingData) -> Result<CNN, String> {
+        CnnBuilder::from_config(config).build(data)
+    }
+
+    /// Loads a model saved in any `ModelFormat` and any older schema version
     pub fn load(model_file_name: &str) -> CNN {
-        let model_file = File::open(model_file_name).unwrap();
-        let cnn: CNN = serde_json::from_reader(model_file).unwrap();
+        CNN::load_from(model_file_name).unwrap()
+    }
 
-        cnn
+    /// Loads a model saved in any `ModelFormat` and any older schema version
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<CNN, String> {
+        let path = path.as_ref();
+        model_file::read_model(path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+    }
+
+    /// Saves the full model to `path` in the model's `ModelFormat`
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        model_file::write_model(self, path, self.model_format)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn model_format(&self) -> ModelFormat {
+        self.model_format
+    }
+
+    /// Names of the network outputs in class index order, if the training data has them
+    pub fn class_names(&self) -> Option<Vec<String>> {
+        if self.data.label_names.is_empty() {
+            return None;
+        }
+        (0..self.data.classes.len()).map(|class| self.data.class_name(class)).collect()
+    }
+
+    /// Per-channel statistics applied to every input, if any
+    pub fn normalization(&self) -> Option<&Normalization> {
+        self.normalization.as_ref()
+    }
+
+    /// Replaces the statistics applied to every input, such as ones computed
+    /// with `Normalization::from_dataset` before training
+    pub fn set_normalization(&mut self, normalization: Option<Normalization>) {
+        self.normalization = normalization;
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn input_shape(&self) -> (usize, usize, usize) {
+        self.input_shape
+    }
+
+    /// Output shape, parameter count, FLOPs and memory of every layer, with totals.
+    /// Print it with `{:?}`, or serialize it to log it elsewhere.
+    pub fn summary(&self) -> ModelSummary {
+        ModelSummary::new(&self.layers, self.input_shape)
     }
 
     pub fn set_input_shape(&mut self, input_shape: Vec<usize>) {
@@ -126,16 +277,8 @@ impl CNN {
         num_filters: usize,
         kernel_size: usize,
     ) {
-        if self.input_shape.0 == 0 {
-            panic!("Input shape not set, use cnn.set_input_shape()");
-        }
-        let input_size: (usize, usize, usize) = match self.layers.last() {
-            Some(Layer::Conv(conv_layer)) => conv_layer.output_size,
-            Some(Layer::Mxpl(mxpl_layer)) => mxpl_layer.output_size,
-            Some(Layer::Dense(_)) => panic!("Convolutional Layer cannot follow a Dense Layer"),
-            None => self.input_shape,
-        };
-        let conv_layer: ConvLayer = ConvLayer::new(input_size, kernel_size, 1, num_filters, self.optimizer.clone());
+        let input_size = self.next_input_size(&LayerSpec::Conv { num_filters, kernel_size });
+        let conv_layer: ConvLayer = ConvLayer::new(input_size, kernel_size, 1, num_filters, self.optimizer);
         self.layers.push(Layer::Conv(conv_layer));
         self.layer_order.push(String::from("conv"));
     }
@@ -144,39 +287,46 @@ impl CNN {
         &mut self,
         kernel_size: usize,
     ) {
-        if self.input_shape.0 == 0 {
-            panic!("Input shape not set, use cnn.set_input_shape()");
-        }
-        let input_size: (usize, usize, usize) = match self.layers.last() {
-            Some(Layer::Conv(conv_layer)) => conv_layer.output_size,
-            Some(Layer::Mxpl(mxpl_layer)) => mxpl_layer.output_size,
-            Some(Layer::Dense(_)) => panic!("Max Pooling Layer cannot follow a Dense Layer"),
-            None => self.input_shape,
-        };
+        let input_size = self.next_input_size(&LayerSpec::Mxpl { kernel_size });
         let mxpl_layer: MxplLayer = MxplLayer::new(input_size, kernel_size, 2);
         self.layers.push(Layer::Mxpl(mxpl_layer));
         self.layer_order.push(String::from("mxpl"));
     }
 
     pub fn add_dense_layer(&mut self, output_size: usize, activation: Activation, dropout: Option<f32>) {
-        if self.input_shape.0 == 0 {
-            panic!("Input shape not set, use cnn.set_input_shape()");
-        }
         // Find last layer's output size
-        let transition_shape: (usize, usize, usize) = match self.layers.last() {
-            Some(Layer::Conv(conv_layer)) => conv_layer.output_size,
-            Some(Layer::Mxpl(mxpl_layer)) => mxpl_layer.output_size,
-            Some(Layer::Dense(dense_layer)) => (dense_layer.output_size, 1, 1),
-            None => self.input_shape,
-        };
+        let transition_shape = self.next_input_size(&LayerSpec::Dense { output_size, activation, dropout });
         let input_size = transition_shape.0 * transition_shape.1 * transition_shape.2;
         let fcl_layer: DenseLayer = DenseLayer::new(input_size, output_size, activation, self.optimizer, dropout, transition_shape);
         self.layers.push(Layer::Dense(fcl_layer));
         self.layer_order.push(String::from("dense"));
     }
 
+    /// Input shape of a layer added next, panicking if the layer cannot be added.
+    /// Use `CnnBuilder` to get these errors as a `Result` instead.
+    fn next_input_size(&self, layer: &LayerSpec) -> (usize, usize, usize) {
+        if self.input_shape.0 == 0 {
+            panic!("Input shape not set, use cnn.set_input_shape()");
+        }
+        let (input_size, flat) = match self.layers.last() {
+            Some(Layer::Conv(conv_layer)) => (conv_layer.output_size, false),
+            Some(Layer::Mxpl(mxpl_layer)) => (mxpl_layer.output_size, false),
+            Some(Layer::Dense(dense_layer)) => ((dense_layer.output_size, 1, 1), true),
+            None => (self.input_shape, false),
+        };
+        if let Err(e) = layer.output_shape(input_size, flat) {
+            panic!("Layer {}: {}", self.layers.len(), e);
+        }
+
+        input_size
+    }
+
+    /// Normalizes the image if the network has a normalization, then runs every layer
     pub fn forward_propagate(&mut self, image: Array3<f32>, training: bool) -> Array1<f32> {
-        let mut output: Array3<f32> = image;
+        let mut output: Array3<f32> = match &self.normalization {
+            Some(normalization) => normalization.apply(image),
+            None => image,
+        };
         let mut flat_output: Array1<f32> = output.clone().into_shape(output.len()).unwrap();
         for layer in &mut self.layers {
             match layer {
@@ -203,11 +353,31 @@ impl CNN {
             _ => panic!("Last layer is not a DenseLayer"),
         };
         let desired = Array1::<f32>::from_shape_fn(size, |i| (label == i) as usize as f32);
-        self.output() - desired
+        loss::gradient(&self.output(), &desired, self.loss)
     }
 
+    /// Back propagates towards the one-hot output of `label`, weighted by the
+    /// class weights like `back_propagate_target`
     pub fn back_propagate(&mut self, label: usize, training: bool) {
-        let mut flat_error: Array1<f32> = self.last_layer_error(label);
+        let size: usize = match self.layers.last().unwrap() {
+            Layer::Dense(dense_layer) => dense_layer.output_size,
+            _ => panic!("Last layer is not a DenseLayer"),
+        };
+        let desired = Array1::<f32>::from_shape_fn(size, |i| (label == i) as usize as f32);
+        self.back_propagate_target(&desired, training);
+    }
+
+    /// Back propagates towards a desired output that need not be one-hot,
+    /// such as the blended labels of mixup
+    pub fn back_propagate_target(&mut self, desired: &Array1<f32>, training: bool) {
+        let mut error = loss::gradient(&self.output(), desired, self.loss);
+        if let Some(weights) = &self.loss_weights {
+            error *= loss::sample_weight(desired, weights);
+        }
+        self.back_propagate_error(error, training);
+    }
+
+    fn back_propagate_error(&mut self, mut flat_error: Array1<f32>, training: bool) {
         let mut error: Array3<f32> = flat_error.clone().into_shape((1, 1, flat_error.len())).unwrap();
         for layer in self.layers.iter_mut().rev() {
             match layer {
@@ -260,11 +430,50 @@ impl CNN {
         (max_idx == label) as usize as f32
     }
 
-    pub fn train(&mut self) {
-        let mut best_train_acc: f32 = self.training_history.last().unwrap_or(&0.0).clone();
-        let mut best_test_acc: f32 = self.testing_history.last().unwrap_or(&0.0).clone();
+    /// Trains on the training set of the data the network was built with,
+    /// testing on its testing set after every epoch
+    pub fn train(&mut self) -> Result<(), String> {
+        let data = Arc::clone(&self.data);
+        self.train_on(&data.train_set(), Some(&data.test_set()))
+    }
+
+    /// Trains for the configured number of epochs, each drawing `train.len()`
+    /// random samples from `train` and augmenting them. If `test` is given,
+    /// the network is tested on all of it after every epoch.
+    /// Fails before training if the class weights or the sampler cannot be set
+    /// up for `train`. Training carries on when a save fails, so that the network
+    /// is not lost, and the first failure is returned once every epoch has run.
+    pub fn train_on<T: Dataset + Sync + ?Sized, U: Dataset + Sync + ?Sized>(&mut self, train: &T, test: Option<&U>) -> Result<(), String> {
+        if train.is_empty() {
+            panic!("The training set is empty");
+        }
+        let num_classes = match self.layers.last() {
+            Some(Layer::Dense(dense_layer)) => dense_layer.output_size,
+            _ => panic!("Last layer is not a DenseLayer"),
+        };
+        if self.normalize == Normalize::FromTrainingSet && self.normalization.is_none() {
+            let normalization = Normalization::from_dataset(train)
+                .unwrap_or_else(|e| panic!("Failed to compute the normalization: {}", e));
+            self.normalization = Some(normalization);
+        }
+        self.loss_weights = self.class_weights.weights(train, num_classes)
+            .map_err(|e| format!("Failed to compute the class weights: {}", e))?;
+        let sampler = self.sampler.sample_weights(train, num_classes)
+            .and_then(|weights| weights.map(|weights| WeightedSampler::new(&weights)).transpose())
+            .map_err(|e| format!("Failed to set up the sampler: {}", e))?;
+        let options = self.prefetch.clone();
+        let (train_budget, test_budget) = options.cache_budgets(train.len(), test.map_or(0, |test| test.len()));
+        let train = CachedDataset::new(train, train_budget);
+        let test = test.map(|test| CachedDataset::new(test, test_budget));
+        let mut rng = self.augmentation.rng();
+        let mut best_train_acc: f32 = *self.training_history.last().unwrap_or(&0.0);
+        let mut best_test_acc: f32 = *self.testing_history.last().unwrap_or(&0.0);
+        let mut failed_saves: Vec<String> = vec![];
         for epoch in 0..self.epochs {
-            let pb = ProgressBar::new((self.data.trn_size / self.minibatch_size) as u64);
+            let lr = self.lr_schedule.learning_rate(self.optimizer.learning_rate(), epoch, self.epochs);
+            self.set_learning_rate(lr);
+
+            let pb = ProgressBar::new((train.len() / self.minibatch_size) as u64);
             if self.verbose {
                 pb.set_style(ProgressStyle::default_bar()
                     .template(&format!("Epoch {}: [{{bar:.cyan/blue}}] {{pos}}/{{len}} - ETA: {{eta}} - acc: {{msg}}", epoch))
@@ -272,54 +481,60 @@ impl CNN {
                     .progress_chars("#>-"));
             }
 
+            // Drawn up front so that workers can decode the samples ahead
+            let indices: Vec<usize> = (0..train.len()).map(|_| match &sampler {
+                Some(sampler) => sampler.sample(&mut rng),
+                None => rng.gen_range(0..train.len()),
+            }).collect();
             let mut avg_acc = 0.0;
-            for i in 0..self.data.trn_size {
-                let (image, label) = get_random_image(&self.data);
-                let label = *self.data.classes.get(&label).unwrap();
-                self.forward_propagate(image, true);
-                self.back_propagate(label, true);
-
-                avg_acc += self.get_accuracy(label);
-
-                if i % self.minibatch_size == self.minibatch_size - 1 {
-                    self.update(self.minibatch_size);
+            prefetch(&train, &indices, &options, |samples| {
+                for (i, sample) in samples.enumerate() {
+                    let (image, desired) = self.augmentation.augment_sample(sample, &train, num_classes, &mut rng);
+                    // Mixed samples count as their larger share
+                    let label = desired.iter().enumerate().fold(0, |best, (j, &d)| if d > desired[best] { j } else { best });
+                    self.forward_propagate(image, true);
+                    self.back_propagate_target(&desired, true);
+
+                    avg_acc += self.get_accuracy(label);
+
+                    if i % self.minibatch_size == self.minibatch_size - 1 {
+                        self.update(self.minibatch_size);
+
+                        if self.verbose {
+                            pb.inc(1);
+                            pb.set_message(format!("{:.1}%", avg_acc / (i + 1) as f32 * 100.0));
+                        }
 
-                    if self.verbose {
-                        pb.inc(1);
-                        pb.set_message(format!("{:.1}%", avg_acc / (i + 1) as f32 * 100.0));
                     }
-
-                }
-                match self.saving_strategy {
-                    SavingStrategy::EveryNthEpoch(full_save, n) => {
+                    if let SavingStrategy::EveryNthEpoch(full_save, n) = self.saving_strategy {
                         // n is an f32, so save every trn_size / minibatch_size * n iterations
-                        let every_n = (self.data.trn_size as f32 * n) as usize;
+                        let every_n = (train.len() as f32 * n) as usize;
                         if i % every_n == every_n - 1 {
-                            self.save(full_save);
+                            failed_saves.extend(self.save(full_save).err());
                         }
                     }
-                    _ => {}
                 }
-
-            }
+            });
             
-            avg_acc /= self.data.trn_size as f32;
+            avg_acc /= train.len() as f32;
             if self.verbose {
                 pb.set_message(format!("{:.1}% - Testing...", avg_acc));
             }
             
             // Testing
             let mut avg_test_acc = 0.0;
-            for _i in 0..self.data.tst_size {
-                // let image: Array3<f32> = self.data.tst_img[i].clone();
-                let (image, label) = get_random_test_image(&self.data);
-                let label = *self.data.classes.get(&label).unwrap();
-                self.forward_propagate(image, false);
-                
-                avg_test_acc += self.get_accuracy(label);//self.data.tst_lbl[i] as usize);
+            if let Some(test) = test.as_ref().filter(|test| !test.is_empty()) {
+                let indices: Vec<usize> = (0..test.len()).collect();
+                prefetch(test, &indices, &options, |samples| {
+                    for (image, label) in samples {
+                        self.forward_propagate(image, false);
+
+                        avg_test_acc += self.get_accuracy(label);
+                    }
+                });
+
+                avg_test_acc /= test.len() as f32;
             }
-            
-            avg_test_acc /= self.data.tst_size as f32;
             if self.verbose {
                 pb.finish_with_message(format!("{:.1}% - Test: {:.1}%", avg_acc * 100.0, avg_test_acc * 100.0));
             }
@@ -329,29 +544,49 @@ impl CNN {
             let duration = SystemTime::now().duration_since(self.creation_time).unwrap();
             self.time_history.push(duration.as_secs() as usize);
             
-            match self.saving_strategy {
-                SavingStrategy::EveryEpoch(full_save) => {
-                    self.save(full_save);
-                }
+            let saved = match self.saving_strategy {
+                SavingStrategy::EveryEpoch(full_save) => self.save(full_save),
                 SavingStrategy::BestTrainingAccuracy(full_save) => {
                     if avg_acc > best_train_acc {
                         best_train_acc = avg_acc;
-                        self.save(full_save);
+                        self.save(full_save)
                     } else {
                         // If the accuracy is not improving, save the metadata anyway
-                        self.save(false);
+                        self.save(false)
                     }
                 }
                 SavingStrategy::BestTestingAccuracy(full_save) => {
                     if avg_test_acc > best_test_acc {
                         best_test_acc = avg_test_acc;
-                        self.save(full_save);
+                        self.save(full_save)
                     } else {
                         // If the accuracy is not improving, save the metadata anyway
-                        self.save(false);
+                        self.save(false)
                     }
                 }
-                _ => {}
+                _ => Ok(()),
+            };
+            if let Err(e) = saved {
+                if self.verbose {
+                    eprintln!("Epoch {}: {}", epoch, e);
+                }
+                failed_saves.push(e);
+            }
+        }
+
+        match failed_saves.first() {
+            Some(e) => Err(format!("{} of the saves during training failed, the first with: {}", failed_saves.len(), e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the learning rate of every layer's optimizer
+    fn set_learning_rate(&mut self, lr: f32) {
+        for layer in &mut self.layers {
+            match layer {
+                Layer::Conv(conv_layer) => { conv_layer.set_learning_rate(lr) }
+                Layer::Mxpl(_) => { }
+                Layer::Dense(dense_layer) => { dense_layer.set_learning_rate(lr) }
             }
         }
     }
@@ -366,53 +601,178 @@ impl CNN {
         }
     }
 
-    pub fn save(&self, full_save: bool) {
-        std::fs::create_dir_all("models").unwrap();
-        let time_str = self.creation_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
+    /// Exports the network as an ONNX model, see `onnx::export_onnx`
+    pub fn export_onnx<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        onnx::export_onnx(&self.layers, self.input_shape, self.normalization.as_ref(), path)
+    }
+
+    /// Fills the network parameters from a `.npz` archive whose arrays are named
+    /// `{layer}.weight` and `{layer}.bias` by layer index
+    pub fn import_npz<P: AsRef<Path>>(&mut self, path: P, layout: WeightLayout) -> Result<(), String> {
+        let arrays = numpy::read_npz(path)?;
+        let mapping = numpy::default_param_names(&self.layers);
+        numpy::import_weights(&mut self.layers, &arrays, &mapping, layout)
+    }
+
+    /// Fills the network parameters from named arrays, where `mapping` gives the
+    /// array name, layer index and parameter of every array
+    pub fn import_weights(&mut self, arrays: &HashMap<String, ArrayD<f32>>, mapping: &[(String, usize, Param)], layout: WeightLayout) -> Result<(), String> {
+        numpy::import_weights(&mut self.layers, arrays, mapping, layout)
+    }
+
+    /// Replaces a single parameter of the layer at the given index
+    pub fn set_param(&mut self, layer: usize, param: Param, array: ArrayD<f32>, layout: WeightLayout) -> Result<(), String> {
+        numpy::set_param(&mut self.layers, layer, param, array, layout)
+    }
+
+    /// Writes the network parameters to a `.npz` archive in the given layout,
+    /// with the array names `import_npz` expects
+    pub fn export_npz<P: AsRef<Path>>(&self, path: P, layout: WeightLayout) -> Result<(), String> {
+        numpy::write_npz(path, &numpy::export_weights(&self.layers, layout))
+    }
+
+    /// Saves the model metadata, and the full model if `full_save` is set,
+    /// to the save directory using the file name template
+    pub fn save(&mut self, full_save: bool) -> Result<(), String> {
+        std::fs::create_dir_all(&self.save_dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.save_dir.display(), e))?;
+        let checkpoint = self.checkpoint_stem();
         if full_save {
-            let model_file_name = format!("models/{}_{}.json", self.name, time_str);
-            let model_file = std::fs::File::create(&model_file_name).unwrap();
-            serde_json::to_writer(model_file, &self).unwrap();
+            self.save_to(with_extension(&checkpoint, self.model_format.extension()))?;
+            self.last_checkpoint = Some(checkpoint.clone());
         }
 
-        // Write metadata to models/model_{}.txt
-        let metadata_file_name = format!("models/{}_{}.txt", self.name, time_str);
-        let mut metadata_file = std::fs::File::create(&metadata_file_name).unwrap();
-        write!(metadata_file, "{:?}", self).unwrap();
+        let metadata_file_name = with_extension(&checkpoint, "txt");
+        model_file::write_atomic(metadata_file_name, format!("{:?}", self).as_bytes())?;
+
+        if full_save {
+            self.remove_old_checkpoints();
+        }
+        Ok(())
     }
 
-    // Top N accuracy with only one model
-    pub fn top_n_accuracy(&mut self, data: TrainingData) -> (Vec<usize>, usize) {
-        let mut corrects: Vec<usize> = vec![0; 10];
-        let mut total: usize = 0;
+    /// Path of the next save, without an extension
+    fn checkpoint_stem(&self) -> PathBuf {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let file_name = self.expand_fixed_placeholders(&self.file_name)
+            .replace("{epoch}", &self.training_history.len().to_string())
+            .replace("{timestamp}", &timestamp.to_string());
 
-        for i in 0..data.tst_size {
-            println!("{} / {} | Top {} Accuracy: {}/{} = {}", i, data.tst_size, (i%10) + 1, corrects[i%10], total, corrects[i%10] as f64 / total as f64);
-            let image = match data.tst_img[i].clone() {
-                TrainImage::Path(p) => load_image(&p).unwrap(),
-                TrainImage::Image(a) => a,
-            };
-            let state_name = idx_to_state(data.tst_lbl[i]);
-            let output = self.forward_propagate(image.clone(), false);
-
-            // Sort the state output
-            let mut state_output_vec: Vec<(usize, f32)> = output.iter().enumerate().map(|(i, v)| (i, *v)).collect();
-            state_output_vec.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-
-            // Check if the correct state is in the top n
-            // for i in 0..10 {
-            for i in 0..state_output_vec.len() {
-                if state_output_vec[i].0 == state_to_idx(state_name) {
-                    for j in i..10 {
-                        corrects[j] += 1;
-                    }
-                    break;
+        self.save_dir.join(file_name)
+    }
+
+    /// Deletes the oldest full saves of this model beyond `keep_last`, with their metadata.
+    /// Saves are found by matching the files in the save directory against the file name
+    /// template, so saves from before the model was loaded are pruned as well. Metadata
+    /// written without a full save is deleted once it is older than every kept save.
+    fn remove_old_checkpoints(&self) {
+        // The save just written is always kept, even if a loaded model has a keep_last of 0
+        let keep_last = match self.keep_last {
+            Some(n) => n.max(1),
+            None => return,
+        };
+        let stem = self.checkpoint_stem();
+        let (dir, template) = match (stem.parent(), Path::new(&self.file_name).file_name()) {
+            (Some(dir), Some(template)) => (dir, self.expand_fixed_placeholders(&template.to_string_lossy())),
+            _ => return,
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        // Numbers filling the template and whether there is a full save, by file stem
+        let mut saves: HashMap<String, (Vec<u128>, bool)> = HashMap::new();
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            for extension in [self.model_format.extension(), "txt"] {
+                let file_stem = match file_name.strip_suffix(&format!(".{}", extension)) {
+                    Some(file_stem) => file_stem,
+                    None => continue,
+                };
+                if let Some(numbers) = match_template(&template, file_stem) {
+                    let save = saves.entry(file_stem.to_string()).or_insert((numbers, false));
+                    save.1 |= extension != "txt";
+                }
+            }
+        }
+
+        let mut checkpoints: Vec<&Vec<u128>> = saves.values().filter(|(_, full)| *full).map(|(numbers, _)| numbers).collect();
+        if checkpoints.len() <= keep_last {
+            return;
+        }
+        checkpoints.sort();
+        let oldest_kept = checkpoints[checkpoints.len() - keep_last].clone();
+        for (file_stem, (numbers, _)) in &saves {
+            if *numbers < oldest_kept {
+                for extension in [self.model_format.extension(), "txt"] {
+                    // The file may already have been removed by hand
+                    let _ = std::fs::remove_file(dir.join(format!("{}.{}", file_stem, extension)));
                 }
             }
+        }
+    }
 
-            total += 1;
+    /// Replaces the placeholders of a file name template that are the same for every save
+    fn expand_fixed_placeholders(&self, template: &str) -> String {
+        let time = self.creation_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        template.replace("{name}", &self.name).replace("{time}", &time.to_string())
+    }
+
+    /// Evaluates the network on every sample in `data`, such as `TrainingData::test_set`
+    pub fn evaluate<D: Dataset + ?Sized>(&mut self, data: &D) -> Metrics {
+        let num_classes = match self.layers.last() {
+            Some(Layer::Dense(dense_layer)) => dense_layer.output_size,
+            _ => panic!("Last layer is not a DenseLayer"),
+        };
+        let mut metrics = Metrics::new(num_classes);
+
+        for i in 0..data.len() {
+            let (image, label) = data.get(i);
+            let output = self.forward_propagate(image, false);
+            metrics.record(label, &output);
         }
 
-        (corrects, total)
+        metrics
+    }
+
+    /// Converts the trained network into a `Predictor` for inference
+    pub fn into_predictor(self) -> Predictor {
+        Predictor::new(self.layers, self.input_shape, self.normalization)
     }
+
+    /// Top-k accuracy on every sample in `data`, for every k from 1 to n
+    pub fn top_n_accuracy<D: Dataset + ?Sized>(&mut self, data: &D, n: usize) -> TopNAccuracy {
+        self.evaluate(data).top_n(n)
+    }
+}
+
+/// Numbers that fill the `{epoch}` and `{timestamp}` placeholders of `template`
+/// in order to give `file_stem`, or None if the stem does not match the template
+fn match_template(template: &str, file_stem: &str) -> Option<Vec<u128>> {
+    let mut numbers = vec![];
+    let (mut template, mut file_stem) = (template, file_stem);
+    loop {
+        let next = ["{epoch}", "{timestamp}"].iter()
+            .filter_map(|placeholder| template.find(placeholder).map(|i| (i, placeholder.len())))
+            .min();
+        let (start, len) = match next {
+            Some(next) => next,
+            None => return (template == file_stem).then_some(numbers),
+        };
+        file_stem = file_stem.strip_prefix(&template[..start])?;
+        let digits = file_stem.bytes().take_while(|b| b.is_ascii_digit()).count();
+        numbers.push(file_stem[..digits].parse().ok()?);
+        file_stem = &file_stem[digits..];
+        template = &template[start + len..];
+    }
+}
+
+/// Appends an extension to a path, unlike `Path::with_extension`
+/// which would replace anything after a dot in the file name
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
 }
\ No newline at end of file
@@ -0,0 +1,312 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::activation::Activation;
+use crate::cnn::Hyperparameters;
+use crate::loss::Loss;
+use crate::normalize::Normalize;
+use crate::optimizer::OptimizerAlg;
+
+/// A layer of a network described in a config file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LayerSpec {
+    /// See `CNN::add_conv_layer`
+    Conv { num_filters: usize, kernel_size: usize },
+    /// See `CNN::add_mxpl_layer`
+    Mxpl { kernel_size: usize },
+    /// See `CNN::add_dense_layer`
+    Dense {
+        output_size: usize,
+        activation: Activation,
+        #[serde(default)]
+        dropout: Option<f32>,
+    },
+}
+
+impl LayerSpec {
+    /// Output shape of the layer for the given input shape, checking that the
+    /// layer can follow the previous layer and that its parameters fit the input.
+    /// `flat` is whether the input is the output of a dense layer.
+    pub fn output_shape(&self, input_shape: (usize, usize, usize), flat: bool) -> Result<(usize, usize, usize), String> {
+        if let Some(e) = self.parameter_errors().into_iter().next() {
+            return Err(e);
+        }
+        if let Some(e) = self.order_error(flat) {
+            return Err(e);
+        }
+
+        match *self {
+            LayerSpec::Conv { num_filters, kernel_size } => {
+                let (rows, cols) = window_output(input_shape, kernel_size, 1)?;
+                Ok((rows, cols, num_filters))
+            }
+            LayerSpec::Mxpl { kernel_size } => {
+                let (rows, cols) = window_output(input_shape, kernel_size, 2)?;
+                Ok((rows, cols, input_shape.2))
+            }
+            LayerSpec::Dense { output_size, .. } => Ok((output_size, 1, 1)),
+        }
+    }
+
+    /// Problems with the layer's own parameters, which do not depend on its input
+    fn parameter_errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        match *self {
+            LayerSpec::Conv { num_filters, kernel_size } => {
+                if num_filters == 0 {
+                    errors.push(String::from("num_filters must be at least 1"));
+                }
+                if kernel_size == 0 {
+                    errors.push(String::from("kernel_size must be at least 1"));
+                }
+            }
+            LayerSpec::Mxpl { kernel_size } => {
+                if kernel_size == 0 {
+                    errors.push(String::from("kernel_size must be at least 1"));
+                }
+            }
+            LayerSpec::Dense { output_size, dropout, .. } => {
+                if output_size == 0 {
+                    errors.push(String::from("output_size must be at least 1"));
+                }
+                if let Some(dropout) = dropout {
+                    if !(0.0..1.0).contains(&dropout) {
+                        errors.push(format!("dropout must be in [0, 1), got {}", dropout));
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+
+    fn order_error(&self, flat: bool) -> Option<String> {
+        match self {
+            LayerSpec::Conv { .. } if flat => Some(String::from("Convolutional Layer cannot follow a Dense Layer")),
+            LayerSpec::Mxpl { .. } if flat => Some(String::from("Max Pooling Layer cannot follow a Dense Layer")),
+            _ => None,
+        }
+    }
+}
+
+/// Output rows and cols of a sliding window layer
+fn window_output(input_shape: (usize, usize, usize), kernel_size: usize, stride: usize) -> Result<(usize, usize), String> {
+    if kernel_size > input_shape.0 || kernel_size > input_shape.1 {
+        return Err(format!(
+            "kernel_size {} is larger than the {}x{} input",
+            kernel_size, input_shape.0, input_shape.1
+        ));
+    }
+
+    Ok(((input_shape.0 - kernel_size) / stride + 1, (input_shape.1 - kernel_size) / stride + 1))
+}
+
+/// Converts an input shape of 1 to 3 dimensions to (rows, cols, channels),
+/// where cols and channels default to 1
+pub fn parse_input_shape(input_shape: &[usize]) -> Result<(usize, usize, usize), String> {
+    if input_shape.is_empty() || input_shape.len() > 3 {
+        return Err(format!("input_shape must have 1 to 3 dimensions, got {:?}", input_shape));
+    }
+    if input_shape.contains(&0) {
+        return Err(format!("input_shape cannot contain 0, got {:?}", input_shape));
+    }
+    let mut iter = input_shape.iter().copied();
+
+    Ok((iter.next().unwrap(), iter.next().unwrap_or(1), iter.next().unwrap_or(1)))
+}
+
+/// Checks that fixed normalization statistics have one value per input channel
+pub fn normalization_errors(input_shape: Option<(usize, usize, usize)>, normalize: &Normalize) -> Vec<String> {
+    match (input_shape, normalize) {
+        (Some((_, _, channels)), Normalize::Fixed { mean, .. }) if mean.len() != channels => vec![format!(
+            "Normalization has {} channels but the input has {}", mean.len(), channels
+        )],
+        _ => vec![],
+    }
+}
+
+/// Checks that per-class sampler and loss weights match the output size of the last layer
+pub fn class_errors(layers: &[LayerSpec], params: &Hyperparameters) -> Vec<String> {
+    match layers.last() {
+        Some(&LayerSpec::Dense { output_size, .. }) => {
+            let mut errors = params.sampler.class_errors(output_size);
+            errors.extend(params.class_weights.class_errors(output_size));
+            errors
+        }
+        _ => vec![],
+    }
+}
+
+/// Checks that the loss can be back propagated through the output layer. The mean
+/// squared error gradient is passed through the softmax as if its Jacobian were the
+/// identity, which only cross entropy, fused with the softmax, makes exact.
+pub fn loss_errors(layers: &[LayerSpec], params: &Hyperparameters) -> Vec<String> {
+    match (layers.last(), params.loss) {
+        (Some(LayerSpec::Dense { activation: Activation::Softmax, .. }), Loss::MeanSquaredError) => vec![String::from(
+            "Mean squared error loss cannot be used with a Softmax output layer, use cross entropy or a Sigmoid output layer"
+        )],
+        _ => vec![],
+    }
+}
+
+/// Runs shape inference over a layer stack without allocating any layers.
+/// Returns the output shape of every layer, or every problem found, prefixed
+/// with the index of the layer. Inference carries on past a broken layer
+/// wherever the following shapes are still known, such as after a dense layer.
+pub fn infer_shapes(input_shape: Option<(usize, usize, usize)>, layers: &[LayerSpec]) -> Result<Vec<(usize, usize, usize)>, Vec<String>> {
+    let mut errors = vec![];
+    let mut shapes = vec![];
+    let mut shape = input_shape;
+    let mut flat = false;
+
+    for (i, layer) in layers.iter().enumerate() {
+        let mut layer_errors = layer.parameter_errors();
+        layer_errors.extend(layer.order_error(flat));
+        let output_shape = match (shape, layer_errors.is_empty()) {
+            (Some(input), true) => match layer.output_shape(input, flat) {
+                Ok(output) => Some(output),
+                Err(e) => {
+                    layer_errors.push(e);
+                    None
+                }
+            },
+            _ => None,
+        };
+        errors.extend(layer_errors.into_iter().map(|e| format!("Layer {}: {}", i, e)));
+
+        shape = match *layer {
+            LayerSpec::Dense { output_size, .. } if output_size > 0 => Some((output_size, 1, 1)),
+            _ => output_shape,
+        };
+        flat = matches!(layer, LayerSpec::Dense { .. });
+        shapes.push(shape.unwrap_or((0, 0, 0)));
+    }
+    match layers.last() {
+        None => errors.push(String::from("The network has no layers")),
+        Some(LayerSpec::Dense { .. }) => {}
+        Some(_) => errors.push(String::from("The last layer must be a dense layer")),
+    }
+
+    if errors.is_empty() {
+        Ok(shapes)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Problems with the training settings
+pub fn hyperparameter_errors(params: &Hyperparameters) -> Vec<String> {
+    let mut errors = vec![];
+    if params.batch_size == 0 {
+        errors.push(String::from("batch_size must be at least 1"));
+    }
+    if params.keep_last == Some(0) {
+        errors.push(String::from("keep_last must be at least 1, or unset to keep every save"));
+    }
+    let lr = params.optimizer.learning_rate();
+    if !lr.is_finite() || lr <= 0.0 {
+        errors.push(format!("Learning rate must be positive and finite, got {}", lr));
+    }
+    let in_unit_interval = |x: f32| (0.0..1.0).contains(&x);
+    match params.optimizer {
+        OptimizerAlg::Momentum(_, mu) if !in_unit_interval(mu) => {
+            errors.push(format!("Momentum must be in [0, 1), got {}", mu));
+        }
+        OptimizerAlg::RMSProp(_, rho) if !in_unit_interval(rho) => {
+            errors.push(format!("RMSProp rho must be in [0, 1), got {}", rho));
+        }
+        OptimizerAlg::Adam(_, beta1, beta2) if !in_unit_interval(beta1) || !in_unit_interval(beta2) => {
+            errors.push(format!("Adam betas must be in [0, 1), got {} and {}", beta1, beta2));
+        }
+        _ => {}
+    }
+    if let Err(e) = params.lr_schedule.validate() {
+        errors.push(e);
+    }
+    errors.extend(params.augmentation.errors());
+    if let Err(e) = params.normalize.validate() {
+        errors.push(e);
+    }
+    if let Err(e) = params.prefetch.validate() {
+        errors.push(e);
+    }
+    if let Err(e) = params.sampler.validate() {
+        errors.push(e);
+    }
+    if let Err(e) = params.class_weights.validate() {
+        errors.push(e);
+    }
+
+    errors
+}
+
+/// Architecture and training settings of a network, read from a JSON or TOML file.
+/// Every enum in the file is written in snake_case: a plain string such as
+/// `activation = "relu"` when it carries no settings, and otherwise a table tagged
+/// with its `type`, such as `optimizer = { type = "sgd", lr = 0.1 }`.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// [rows, cols, channels], where cols and channels default to 1
+    pub input_shape: Vec<usize>,
+    pub layers: Vec<LayerSpec>,
+    /// Optimizer, loss, learning rate schedule and other training settings
+    #[serde(default)]
+    pub hyperparameters: Hyperparameters,
+}
+
+impl NetworkConfig {
+    /// Reads a config file, using TOML for `.toml` files and JSON otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<NetworkConfig, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let config = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => NetworkConfig::from_toml(&contents),
+            _ => NetworkConfig::from_json(&contents),
+        };
+
+        config.map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn from_json(json: &str) -> Result<NetworkConfig, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    pub fn from_toml(toml: &str) -> Result<NetworkConfig, String> {
+        toml::from_str(toml).map_err(|e| e.to_string())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    /// Checks the whole config, returning every problem found, one per line
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = vec![];
+        let input_shape = match parse_input_shape(&self.input_shape) {
+            Ok(input_shape) => Some(input_shape),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        };
+        if let Err(e) = infer_shapes(input_shape, &self.layers) {
+            errors.extend(e);
+        }
+        errors.extend(hyperparameter_errors(&self.hyperparameters));
+        errors.extend(normalization_errors(input_shape, &self.hyperparameters.normalize));
+        errors.extend(class_errors(&self.layers, &self.hyperparameters));
+        errors.extend(loss_errors(&self.layers, &self.hyperparameters));
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
@@ -7,16 +7,16 @@ use crate::optimizer::{Optimizer4D, OptimizerAlg};
 
 #[derive(Serialize, Deserialize)]
 pub struct ConvLayer {
-    input_size: (usize, usize, usize),
-    kernel_size: usize,
+    pub input_size: (usize, usize, usize),
+    pub kernel_size: usize,
     pub output_size: (usize, usize, usize),
     #[serde(skip)]
     input: Array3<f32>,
     #[serde(skip)]
     output: Array3<f32>,
-    stride: usize,
-    num_filters: usize,
-    kernels: Array4<f32>,
+    pub stride: usize,
+    pub num_filters: usize,
+    pub kernels: Array4<f32>,
     #[serde(skip)]
     kernel_changes: Array4<f32>,
     optimizer: Optimizer4D,
@@ -50,8 +50,11 @@ impl ConvLayer {
         num_filters: usize,
         optimizer_alg: OptimizerAlg,
     ) -> ConvLayer {
-        let output_width: usize = ((input_size.0 - kernel_size) / stride) + 1;
-        let output_size = (output_width, output_width, num_filters);
+        let output_size = (
+            (input_size.0 - kernel_size) / stride + 1,
+            (input_size.1 - kernel_size) / stride + 1,
+            num_filters,
+        );
         let mut kernels = Array4::<f32>::zeros((num_filters, kernel_size, kernel_size, input_size.2));
         let normal = Normal::new(0.0, 1.0).unwrap();
 
@@ -83,18 +86,26 @@ impl ConvLayer {
         layer
     }
 
-    pub fn forward_propagate(&mut self, input: Array3<f32>) -> Array3<f32> {
-        self.input = input;
+    /// Forward pass that does not cache anything for back propagation
+    pub fn infer(&self, input: &Array3<f32>) -> Array3<f32> {
+        let mut output = Array3::<f32>::zeros(self.output_size);
         for f in 0..self.output_size.2 {
             let kernel_slice = self.kernels.slice(s![f, .., .., ..]);
             for y in 0..self.output_size.1 {
                 for x in 0..self.output_size.0 {
-                    let input_slice = self.input.slice(s![x..x+self.kernel_size, y..y+self.kernel_size, ..]);
-                    self.output[[x, y, f]] = (&input_slice * &kernel_slice).sum().max(0.0);
+                    let input_slice = input.slice(s![x..x+self.kernel_size, y..y+self.kernel_size, ..]);
+                    output[[x, y, f]] = (&input_slice * &kernel_slice).sum().max(0.0);
                 }
             }
         }
 
+        output
+    }
+
+    pub fn forward_propagate(&mut self, input: Array3<f32>) -> Array3<f32> {
+        self.output = self.infer(&input);
+        self.input = input;
+
         self.output.clone()
     }
 
@@ -117,6 +128,11 @@ impl ConvLayer {
         prev_error
     }
 
+    /// Changes the learning rate used by future updates
+    pub fn set_learning_rate(&mut self, lr: f32) {
+        self.optimizer.alg = self.optimizer.alg.with_learning_rate(lr);
+    }
+
     pub fn update(&mut self, minibatch_size: usize) {
         self.kernel_changes /= minibatch_size as f32;
         self.kernels += &self.optimizer.weight_changes(&self.kernel_changes);
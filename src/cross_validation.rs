@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use crate::builder::CnnBuilder;
+use crate::dataset::{Dataset, Subset, TrainingDataSplit};
+use crate::metrics::Metrics;
+use crate::util::TrainingData;
+
+/// Mean and sample standard deviation of a metric over the folds
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub mean: f32,
+    pub std: f32,
+}
+
+impl Summary {
+    pub fn of(values: &[f32]) -> Summary {
+        if values.is_empty() {
+            return Summary { mean: 0.0, std: 0.0 };
+        }
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let std = if values.len() < 2 {
+            0.0
+        } else {
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / (n - 1.0)).sqrt()
+        };
+
+        Summary { mean, std }
+    }
+}
+
+/// Metrics of a network trained on every fold but one and tested on that one
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FoldResult {
+    pub fold: usize,
+    pub train_size: usize,
+    pub test_size: usize,
+    pub metrics: Metrics,
+}
+
+/// Results of `cross_validate`, per fold and aggregated over the folds
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CrossValidation {
+    pub folds: Vec<FoldResult>,
+    pub accuracy: Summary,
+    pub balanced_accuracy: Summary,
+    pub macro_f1: Summary,
+}
+
+impl CrossValidation {
+    pub fn new(folds: Vec<FoldResult>) -> CrossValidation {
+        let summary = |metric: fn(&Metrics) -> f32| {
+            Summary::of(&folds.iter().map(|fold| metric(&fold.metrics)).collect::<Vec<_>>())
+        };
+        CrossValidation {
+            accuracy: summary(Metrics::accuracy),
+            balanced_accuracy: summary(Metrics::balanced_accuracy),
+            macro_f1: summary(Metrics::macro_f1),
+            folds,
+        }
+    }
+}
+
+impl Debug for CrossValidation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        s.push_str(&format!("{:>6} {:>8} {:>8} {:>10} {:>10} {:>10}\n", "Fold", "Train", "Test", "Accuracy", "Balanced", "Macro F1"));
+        for fold in &self.folds {
+            s.push_str(&format!(
+                "{:>6} {:>8} {:>8} {:>10.4} {:>10.4} {:>10.4}\n",
+                fold.fold, fold.train_size, fold.test_size,
+                fold.metrics.accuracy(), fold.metrics.balanced_accuracy(), fold.metrics.macro_f1()
+            ));
+        }
+        s.push('\n');
+        s.push_str(&format!("Accuracy: {:.4} ± {:.4}\n", self.accuracy.mean, self.accuracy.std));
+        s.push_str(&format!("Balanced Accuracy: {:.4} ± {:.4}\n", self.balanced_accuracy.mean, self.balanced_accuracy.std));
+        s.push_str(&format!("Macro F1: {:.4} ± {:.4}\n", self.macro_f1.mean, self.macro_f1.std));
+
+        write!(f, "{}", s)
+    }
+}
+
+/// Splits the samples of `data` into `k` folds with the same share of every class,
+/// as far as the class sizes allow. Each class is shuffled with `seed` and dealt out
+/// in turn, so fold sizes differ by at most one. The indices of each fold are sorted.
+pub fn stratified_folds<D: Dataset + ?Sized>(data: &D, k: usize, seed: u64) -> Result<Vec<Vec<usize>>, String> {
+    if k < 2 {
+        return Err(format!("Cross-validation needs at least 2 folds, got {}", k));
+    }
+    if data.len() < k {
+        return Err(format!("Cannot split {} samples into {} folds", data.len(), k));
+    }
+    let mut classes: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..data.len() {
+        classes.entry(data.label(i)).or_default().push(i);
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut folds = vec![vec![]; k];
+    let mut next = 0;
+    for indices in classes.values_mut() {
+        indices.shuffle(&mut rng);
+        for &i in indices.iter() {
+            folds[next % k].push(i);
+            next += 1;
+        }
+    }
+    for fold in &mut folds {
+        fold.sort_unstable();
+    }
+
+    Ok(folds)
+}
+
+/// Stratified k-fold cross-validation over the training split of `data`, whose test
+/// split is left untouched. The description is checked against `data` before any
+/// training, then for every fold a fresh network is built from `builder`,
+/// trained on the other folds and evaluated on that fold. The folds are shuffled with
+/// the augmentation seed of the builder, or 0 if it has none, so runs are repeatable.
+pub fn cross_validate(builder: &CnnBuilder, data: &TrainingData, k: usize) -> Result<CrossValidation, String> {
+    let errors = builder.data_errors(data);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    let samples = data.train_set();
+    let seed = builder.params().augmentation.seed.unwrap_or(0);
+    let folds = stratified_folds(&samples, k, seed)?;
+
+    let mut results = vec![];
+    for (fold, test_indices) in folds.iter().enumerate() {
+        let train_indices: Vec<usize> = folds.iter()
+            .enumerate()
+            .filter(|&(other, _)| other != fold)
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .collect();
+        let train = Subset::new(&samples, train_indices)?;
+        let test = Subset::new(&samples, test_indices.clone())?;
+
+        let mut cnn = builder.build(TrainingData::default())?;
+        cnn.train_on(&train, None::<&Subset<&TrainingDataSplit>>).map_err(|e| format!("Fold {}: {}", fold, e))?;
+        results.push(FoldResult {
+            fold,
+            train_size: train.len(),
+            test_size: test.len(),
+            metrics: cnn.evaluate(&test),
+        });
+    }
+
+    Ok(CrossValidation::new(results))
+}
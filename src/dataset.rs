@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use ndarray::Array3;
+use crate::util::{ImageOptions, TrainImage, TrainingData};
+
+/// Index of a class in the network output
+pub type Label = usize;
+
+/// A sequence of labelled images that can be read in any order.
+/// Images may be stored in memory, loaded from disk, generated or
+/// transformed on the fly; training and evaluation only see `get`.
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    /// The image and label of sample `i`, for `i < len()`
+    fn get(&self, i: usize) -> (Array3<f32>, Label);
+
+    /// The label of sample `i`. Override it when labels can be read
+    /// without decoding the image, as samplers read every label.
+    fn label(&self, i: usize) -> Label {
+        self.get(i).1
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for &D {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        (**self).get(i)
+    }
+
+    fn label(&self, i: usize) -> Label {
+        (**self).label(i)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Box<D> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        (**self).get(i)
+    }
+
+    fn label(&self, i: usize) -> Label {
+        (**self).label(i)
+    }
+}
+
+/// Number of samples of every class in `data`
+pub fn class_counts<D: Dataset + ?Sized>(data: &D, num_classes: usize) -> Result<Vec<usize>, String> {
+    let mut counts = vec![0; num_classes];
+    for i in 0..data.len() {
+        let label = data.label(i);
+        match counts.get_mut(label) {
+            Some(count) => *count += 1,
+            None => return Err(format!("Sample {} has label {} but there are only {} classes", i, label, num_classes)),
+        }
+    }
+    Ok(counts)
+}
+
+/// Images and labels held in memory
+#[derive(Clone, Default)]
+pub struct InMemoryDataset {
+    pub images: Vec<Array3<f32>>,
+    pub labels: Vec<Label>,
+}
+
+impl InMemoryDataset {
+    pub fn new(images: Vec<Array3<f32>>, labels: Vec<Label>) -> Result<InMemoryDataset, String> {
+        if images.len() != labels.len() {
+            return Err(format!("Got {} images but {} labels", images.len(), labels.len()));
+        }
+        Ok(InMemoryDataset { images, labels })
+    }
+}
+
+impl Dataset for InMemoryDataset {
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        (self.images[i].clone(), self.labels[i])
+    }
+
+    fn label(&self, i: usize) -> Label {
+        self.labels[i]
+    }
+}
+
+/// The samples of another dataset at the given indices, in that order
+pub struct Subset<D> {
+    data: D,
+    indices: Vec<usize>,
+}
+
+impl<D: Dataset> Subset<D> {
+    pub fn new(data: D, indices: Vec<usize>) -> Result<Subset<D>, String> {
+        if let Some(&i) = indices.iter().find(|&&i| i >= data.len()) {
+            return Err(format!("Index {} is out of bounds for a dataset of {} samples", i, data.len()));
+        }
+        Ok(Subset { data, indices })
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl<D: Dataset> Dataset for Subset<D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        self.data.get(self.indices[i])
+    }
+
+    fn label(&self, i: usize) -> Label {
+        self.data.label(self.indices[i])
+    }
+}
+
+/// One split of a `TrainingData`, with its labels mapped to classes.
+/// Samples whose label is not one of the `classes` are left out.
+pub struct TrainingDataSplit<'a> {
+    images: &'a [TrainImage],
+    labels: &'a [usize],
+    classes: &'a HashMap<usize, usize>,
+    options: &'a ImageOptions,
+    /// Indices of the samples in `images` that belong to a class
+    indices: Vec<usize>,
+}
+
+impl<'a> TrainingDataSplit<'a> {
+    fn new(images: &'a [TrainImage], labels: &'a [usize], classes: &'a HashMap<usize, usize>, options: &'a ImageOptions) -> TrainingDataSplit<'a> {
+        let indices = labels.iter()
+            .enumerate()
+            .filter(|(_, label)| classes.contains_key(label))
+            .map(|(i, _)| i)
+            .take(images.len())
+            .collect();
+        TrainingDataSplit { images, labels, classes, options, indices }
+    }
+}
+
+impl Dataset for TrainingDataSplit<'_> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Loads the image from disk if it was not preloaded, and panics if that fails
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        let index = self.indices[i];
+        let image = self.images[index].load_with(self.options)
+            .unwrap_or_else(|e| panic!("Failed to load sample {}: {}", index, e));
+        (image, self.classes[&self.labels[index]])
+    }
+
+    fn label(&self, i: usize) -> Label {
+        self.classes[&self.labels[self.indices[i]]]
+    }
+}
+
+impl TrainingData {
+    /// The training images as a `Dataset`
+    pub fn train_set(&self) -> TrainingDataSplit<'_> {
+        TrainingDataSplit::new(&self.trn_img, &self.trn_lbl, &self.classes, &self.image_options)
+    }
+
+    /// The testing images as a `Dataset`
+    pub fn test_set(&self) -> TrainingDataSplit<'_> {
+        TrainingDataSplit::new(&self.tst_img, &self.tst_lbl, &self.classes, &self.image_options)
+    }
+}
@@ -9,22 +9,22 @@ use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct DenseLayer {
-    input_size: usize,
+    pub input_size: usize,
     pub output_size: usize,
     #[serde(skip)]
     input: Array1<f32>,
     #[serde(skip)]
     pub output: Array1<f32>,
-    biases: Array1<f32>,
-    weights: Array2<f32>,
+    pub biases: Array1<f32>,
+    pub weights: Array2<f32>,
     #[serde(skip)]
     bias_changes: Array1<f32>,
     #[serde(skip)]
     weight_changes: Array2<f32>,
-    activation: Activation,
+    pub activation: Activation,
     pub transition_shape: (usize, usize, usize),
     optimizer: Optimizer2D,
-    dropout: Option<f32>,
+    pub dropout: Option<f32>,
     #[serde(skip)]
     dropout_mask: Array1<f32>,
 }
@@ -83,8 +83,7 @@ impl DenseLayer {
     }
 
     pub fn forward_propagate(&mut self, input: Array1<f32>, training: bool) -> Array1<f32> {
-        if training && self.dropout.is_some() {
-            let dropout = self.dropout.unwrap();
+        if let (true, Some(dropout)) = (training, self.dropout) {
             let mut rng = rand::thread_rng();
             self.dropout_mask = Array1::<f32>::from_shape_fn((self.output_size,), |_| rng.gen::<f32>());
             self.dropout_mask = self.dropout_mask.mapv(|x| if x < dropout { 0.0 } else { 1.0 });
@@ -94,13 +93,18 @@ impl DenseLayer {
             self.input = input;
             self.output.clone()
         } else {
-            let logits: Array1<f32> = self.weights.dot(&input) + &self.biases;
-            self.output = forward(logits, self.activation);
+            self.output = self.infer(&input);
             self.input = input;
             self.output.clone()
         }
     }
 
+    /// Forward pass without dropout that does not cache anything for back propagation
+    pub fn infer(&self, input: &Array1<f32>) -> Array1<f32> {
+        let logits: Array1<f32> = self.weights.dot(input) + &self.biases;
+        forward(logits, self.activation)
+    }
+
     pub fn back_propagate(&mut self, error: Array1<f32>, training: bool) -> Array1<f32> {
         let mut error = error;
         if self.dropout.is_some() && training {
@@ -114,6 +118,11 @@ impl DenseLayer {
         prev_error
     }
 
+    /// Changes the learning rate used by future updates
+    pub fn set_learning_rate(&mut self, lr: f32) {
+        self.optimizer.alg = self.optimizer.alg.with_learning_rate(lr);
+    }
+
     pub fn update(&mut self, minibatch_size: usize) {
         self.weight_changes /= minibatch_size as f32;
         self.bias_changes /= minibatch_size as f32;
@@ -1,12 +1,47 @@
-use crate::util::{TrainingData, idx_to_state, load_image};
-use image::io::Reader as ImageReader;
-use ndarray::Array3;
+use crate::util::{fnv1a, ImageOptions, TrainingData, TrainImage};
 use std::path::Path;
 use std::collections::HashMap;
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use walkdir::WalkDir;
-use rand::seq::IteratorRandom;
+
+/// Converts a state name to an index, based on
+/// alphabetical order of the states
+pub fn state_to_idx<T>(state: T) -> usize
+where T: Into<&'static str> {
+    let state_map: HashMap<&'static str, usize> = [
+        ("Alabama", 0), ("Alaska", 1), ("Arizona", 2), ("Arkansas", 3), ("California", 4),
+        ("Colorado", 5), ("Connecticut", 6), ("Delaware", 7), ("Florida", 8), ("Georgia", 9),
+        ("Hawaii", 10), ("Idaho", 11), ("Illinois", 12), ("Indiana", 13), ("Iowa", 14),
+        ("Kansas", 15), ("Kentucky", 16), ("Louisiana", 17), ("Maine", 18), ("Maryland", 19),
+        ("Massachusetts", 20), ("Michigan", 21), ("Minnesota", 22), ("Mississippi", 23), ("Missouri", 24),
+        ("Montana", 25), ("Nebraska", 26), ("Nevada", 27), ("New Hampshire", 28), ("New Jersey", 29),
+        ("New Mexico", 30), ("New York", 31), ("North Carolina", 32), ("North Dakota", 33), ("Ohio", 34),
+        ("Oklahoma", 35), ("Oregon", 36), ("Pennsylvania", 37), ("Rhode Island", 38), ("South Carolina", 39),
+        ("South Dakota", 40), ("Tennessee", 41), ("Texas", 42), ("Utah", 43), ("Vermont", 44),
+        ("Virginia", 45), ("Washington", 46), ("West Virginia", 47), ("Wisconsin", 48), ("Wyoming", 49),
+    ].iter().cloned().collect();
+
+    *state_map.get(state.into()).unwrap()
+}
+
+
+/// Converts a state index to a name, based on
+/// alphabetical order of the states
+pub fn idx_to_state(idx: usize) -> &'static str {
+    let cluster_map: HashMap<usize, &'static str> = [
+        (0, "Alabama"), (1, "Alaska"), (2, "Arizona"), (3, "Arkansas"), (4, "California"),
+        (5, "Colorado"), (6, "Connecticut"), (7, "Delaware"), (8, "Florida"), (9, "Georgia"),
+        (10, "Hawaii"), (11, "Idaho"), (12, "Illinois"), (13, "Indiana"), (14, "Iowa"),
+        (15, "Kansas"), (16, "Kentucky"), (17, "Louisiana"), (18, "Maine"), (19, "Maryland"),
+        (20, "Massachusetts"), (21, "Michigan"), (22, "Minnesota"), (23, "Mississippi"), (24, "Missouri"),
+        (25, "Montana"), (26, "Nebraska"), (27, "Nevada"), (28, "New Hampshire"), (29, "New Jersey"),
+        (30, "New Mexico"), (31, "New York"), (32, "North Carolina"), (33, "North Dakota"), (34, "Ohio"),
+        (35, "Oklahoma"), (36, "Oregon"), (37, "Pennsylvania"), (38, "Rhode Island"), (39, "South Carolina"),
+        (40, "South Dakota"), (41, "Tennessee"), (42, "Texas"), (43, "Utah"), (44, "Vermont"),
+        (45, "Virginia"), (46, "Washington"), (47, "West Virginia"), (48, "Wisconsin"), (49, "Wyoming"),
+    ].iter().cloned().collect();
+
+    cluster_map.get(&idx).unwrap()
+}
 
 pub fn load_50states10k<T>(base_path: T, train_prob: f64, filter: Option<Vec<&str>>) -> Result<TrainingData, String>
 where T: AsRef<Path>
@@ -43,20 +78,14 @@ where T: AsRef<Path>
             // both in training and testing
             let rotations = vec!["_0.jpg", "_90.jpg", "_180.jpg", "_270.jpg"];
             for rotation in rotations {
-                base_file_name = (&base_file_name).replace(rotation, "");
+                base_file_name = base_file_name.replace(rotation, "");
             }
             // Hash base_file_name to decide whether to put in training or testing
-            let hash: u64 = {
-                let mut hasher = DefaultHasher::new();
-                base_file_name.hash(&mut hasher);
-                hasher.finish()
-            };
-
-            if hash < train_cutoff {
-                trn_img.push(entry.path().to_path_buf());
+            if fnv1a(base_file_name.as_bytes()) < train_cutoff {
+                trn_img.push(TrainImage::Path(entry.path().to_path_buf()));
                 trn_lbl.push(i);
             } else {
-                tst_img.push(entry.path().to_path_buf());
+                tst_img.push(TrainImage::Path(entry.path().to_path_buf()));
                 tst_lbl.push(i);
             }
         }
@@ -75,6 +104,8 @@ where T: AsRef<Path>
         tst_size,
         rows,
         cols,
+        label_names: classes.keys().map(|&i| (i, String::from(idx_to_state(i)))).collect(),
+        image_options: ImageOptions::default(),
         classes,
     })
 }
@@ -106,7 +137,7 @@ where T: AsRef<Path>
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file() && e.path().extension().map(|s| s == "jpg").unwrap_or(false))
         {
-            img.push(entry.path().to_path_buf());
+            img.push(TrainImage::Path(entry.path().to_path_buf()));
             lbl.push(i);
         }
     }
@@ -122,20 +153,8 @@ where T: AsRef<Path>
         tst_size,
         rows,
         cols,
+        label_names: classes.keys().map(|&i| (i, String::from(idx_to_state(i)))).collect(),
+        image_options: ImageOptions::default(),
         classes,
     })
 }
-
-pub fn get_random_image(data: &TrainingData) -> (Array3<f32>, usize) {
-    let mut rng = rand::thread_rng();
-    let (img, label) = data.trn_img.iter().zip(data.trn_lbl.iter()).choose(&mut rng).unwrap();
-    let img = load_image(img).unwrap();
-    (img, *label)
-}
-
-pub fn get_random_test_image(data: &TrainingData) -> (Array3<f32>, usize) {
-    let mut rng = rand::thread_rng();
-    let (img, label) = data.tst_img.iter().zip(data.tst_lbl.iter()).choose(&mut rng).unwrap();
-    let img = load_image(img).unwrap();
-    (img, *label)
-}
\ No newline at end of file
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use ndarray::Array3;
+use crate::util::{ImageOptions, TrainImage, TrainingData};
+
+/// First two bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Element type of an IDX file, given by the third byte of its magic number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdxType {
+    UnsignedByte,
+    SignedByte,
+    Short,
+    Int,
+    Float,
+    Double,
+}
+
+impl IdxType {
+    fn from_code(code: u8) -> Result<IdxType, String> {
+        match code {
+            0x08 => Ok(IdxType::UnsignedByte),
+            0x09 => Ok(IdxType::SignedByte),
+            0x0B => Ok(IdxType::Short),
+            0x0C => Ok(IdxType::Int),
+            0x0D => Ok(IdxType::Float),
+            0x0E => Ok(IdxType::Double),
+            _ => Err(format!("Unknown IDX data type 0x{:02x}", code)),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            IdxType::UnsignedByte | IdxType::SignedByte => 1,
+            IdxType::Short => 2,
+            IdxType::Int | IdxType::Float => 4,
+            IdxType::Double => 8,
+        }
+    }
+}
+
+/// The contents of an IDX file, with its values in row-major order
+pub struct IdxArray {
+    pub data_type: IdxType,
+    pub dims: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+/// Reads an IDX file, decompressing it first if it is gzipped
+pub fn read_idx<P: AsRef<Path>>(path: P) -> Result<IdxArray, String> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let bytes = if bytes.starts_with(&GZIP_MAGIC) {
+        let mut decoded = vec![];
+        GzDecoder::new(&bytes[..]).read_to_end(&mut decoded)
+            .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+        decoded
+    } else {
+        bytes
+    };
+
+    parse_idx(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Parses an uncompressed IDX file. Counts and dimensions come from the header.
+pub fn parse_idx(bytes: &[u8]) -> Result<IdxArray, String> {
+    if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+        return Err(String::from("Not an IDX file"));
+    }
+    let data_type = IdxType::from_code(bytes[2])?;
+    let num_dims = bytes[3] as usize;
+    let header_size = 4 + 4 * num_dims;
+    if bytes.len() < header_size {
+        return Err(String::from("Truncated IDX header"));
+    }
+    let dims: Vec<usize> = bytes[4..header_size]
+        .chunks_exact(4)
+        .map(|d| u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize)
+        .collect();
+
+    if dims.contains(&0) {
+        return Err(format!("IDX file has dimensions {:?}, which cannot contain 0", dims));
+    }
+    let expected = dims.iter()
+        .try_fold(data_type.size(), |len, &d| len.checked_mul(d))
+        .and_then(|len| len.checked_add(header_size))
+        .ok_or_else(|| format!("IDX file with dimensions {:?} is too large", dims))?;
+    if bytes.len() != expected {
+        return Err(format!(
+            "IDX file with dimensions {:?} should be {} bytes but is {}",
+            dims, expected, bytes.len()
+        ));
+    }
+
+    let body = &bytes[header_size..];
+    let data = match data_type {
+        IdxType::UnsignedByte => body.iter().map(|&b| b as f32).collect(),
+        IdxType::SignedByte => body.iter().map(|&b| b as i8 as f32).collect(),
+        IdxType::Short => body.chunks_exact(2).map(|b| i16::from_be_bytes([b[0], b[1]]) as f32).collect(),
+        IdxType::Int => body.chunks_exact(4).map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32).collect(),
+        IdxType::Float => body.chunks_exact(4).map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        IdxType::Double => body.chunks_exact(8).map(|b| {
+            f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+        }).collect(),
+    };
+
+    Ok(IdxArray { data_type, dims, data })
+}
+
+/// Names of the four files of an IDX dataset, without any `.gz` extension
+pub struct IdxFiles {
+    pub train_images: String,
+    pub train_labels: String,
+    pub test_images: String,
+    pub test_labels: String,
+}
+
+impl IdxFiles {
+    /// The file names used by MNIST and the datasets that copy its layout,
+    /// such as Fashion-MNIST and KMNIST
+    pub fn mnist() -> IdxFiles {
+        IdxFiles {
+            train_images: String::from("train-images-idx3-ubyte"),
+            train_labels: String::from("train-labels-idx1-ubyte"),
+            test_images: String::from("t10k-images-idx3-ubyte"),
+            test_labels: String::from("t10k-labels-idx1-ubyte"),
+        }
+    }
+
+    /// The file names of an EMNIST split, such as `emnist-letters-train-images-idx3-ubyte`
+    pub fn emnist(split: &str) -> IdxFiles {
+        IdxFiles {
+            train_images: format!("emnist-{}-train-images-idx3-ubyte", split),
+            train_labels: format!("emnist-{}-train-labels-idx1-ubyte", split),
+            test_images: format!("emnist-{}-test-images-idx3-ubyte", split),
+            test_labels: format!("emnist-{}-test-labels-idx1-ubyte", split),
+        }
+    }
+}
+
+/// Loads an IDX dataset from `dir`, where each file may also be gzipped with a
+/// `.gz` extension. `label_names` gives every label and its name, in class order.
+/// With `transpose`, rows and columns of every image are swapped, which EMNIST needs.
+pub fn load_idx_dataset<P: AsRef<Path>>(dir: P, files: &IdxFiles, label_names: &[(usize, String)], transpose: bool) -> Result<TrainingData, String> {
+    let dir = dir.as_ref();
+    if !dir.is_dir() {
+        return Err(format!("Folder {} does not exist.", dir.display()));
+    }
+    let (trn_img, rows, cols) = read_images(&find_file(dir, &files.train_images)?, transpose)?;
+    let trn_lbl = read_labels(&find_file(dir, &files.train_labels)?)?;
+    let (tst_img, tst_rows, tst_cols) = read_images(&find_file(dir, &files.test_images)?, transpose)?;
+    let tst_lbl = read_labels(&find_file(dir, &files.test_labels)?)?;
+
+    if (tst_rows, tst_cols) != (rows, cols) {
+        return Err(format!(
+            "Training images are {}x{} but testing images are {}x{}",
+            rows, cols, tst_rows, tst_cols
+        ));
+    }
+    if trn_img.len() != trn_lbl.len() || tst_img.len() != tst_lbl.len() {
+        return Err(format!(
+            "Got {} training images with {} labels and {} testing images with {} labels",
+            trn_img.len(), trn_lbl.len(), tst_img.len(), tst_lbl.len()
+        ));
+    }
+    let classes: HashMap<usize, usize> = label_names.iter().enumerate().map(|(i, (label, _))| (*label, i)).collect();
+    if let Some(label) = trn_lbl.iter().chain(tst_lbl.iter()).find(|label| !classes.contains_key(label)) {
+        return Err(format!("Label {} is not one of the {} known labels", label, classes.len()));
+    }
+
+    Ok(TrainingData {
+        trn_size: trn_img.len(),
+        tst_size: tst_img.len(),
+        trn_img,
+        trn_lbl,
+        tst_img,
+        tst_lbl,
+        rows,
+        cols,
+        classes,
+        label_names: label_names.iter().cloned().collect(),
+        image_options: ImageOptions::default(),
+    })
+}
+
+/// `dir/name`, or `dir/name.gz` if only the compressed file exists
+fn find_file(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let path = dir.join(name);
+    if path.is_file() {
+        return Ok(path);
+    }
+    let gzipped = dir.join(format!("{}.gz", name));
+    if gzipped.is_file() {
+        return Ok(gzipped);
+    }
+
+    Err(format!("{} has neither {} nor {}.gz", dir.display(), name, name))
+}
+
+/// Images from a [count, rows, cols] IDX file, scaled to [0, 1] if stored as bytes
+fn read_images(path: &Path, transpose: bool) -> Result<(Vec<TrainImage>, usize, usize), String> {
+    let array = read_idx(path)?;
+    let (count, rows, cols) = match array.dims[..] {
+        [count, rows, cols] => (count, rows, cols),
+        _ => return Err(format!("{} should hold [count, rows, cols] images, got dimensions {:?}", path.display(), array.dims)),
+    };
+    let scale = if array.data_type == IdxType::UnsignedByte { 1.0 / 255.0 } else { 1.0 };
+    let (rows, cols) = if transpose { (cols, rows) } else { (rows, cols) };
+
+    let images = array.data.chunks_exact(rows * cols).take(count).map(|pixels| {
+        let image = Array3::from_shape_fn((rows, cols, 1), |(row, col, _)| {
+            let index = if transpose { col * rows + row } else { row * cols + col };
+            pixels[index] * scale
+        });
+        TrainImage::Image(image)
+    }).collect();
+
+    Ok((images, rows, cols))
+}
+
+fn read_labels(path: &Path) -> Result<Vec<usize>, String> {
+    let array = read_idx(path)?;
+    if array.dims.len() != 1 {
+        return Err(format!("{} should hold a list of labels, got dimensions {:?}", path.display(), array.dims));
+    }
+    if array.data.iter().any(|&label| label < 0.0) {
+        return Err(format!("{} has negative labels", path.display()));
+    }
+
+    Ok(array.data.iter().map(|&label| label as usize).collect())
+}
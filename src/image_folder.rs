@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use walkdir::WalkDir;
+use crate::util::{fnv1a, load_image_with, ImageOptions, TrainImage, TrainingData};
+
+/// How images are divided between the training and testing sets
+#[derive(Clone, Debug, PartialEq)]
+pub enum Split {
+    /// A file goes to the training set when the FNV-1a hash of its path within
+    /// its class folder falls in the first `train_fraction` of the hash range.
+    /// Files keep their split when others are added or removed.
+    Hash(f64),
+    /// Each class is shuffled with `seed`, and its first `train_fraction`
+    /// of files go to the training set
+    Ratio { train_fraction: f64, seed: u64 },
+    /// The dataset is already split into `root/train/<class>` and `root/test/<class>`
+    Subfolders,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageFolderOptions {
+    pub split: Split,
+    /// File extensions that are loaded, compared case-insensitively
+    pub extensions: Vec<String>,
+    /// Only load these classes, by folder name
+    pub classes: Option<Vec<String>>,
+    /// Decode every image up front rather than during training
+    pub preload: bool,
+    /// How images are decoded. With a size set, images of any dimensions are accepted.
+    pub image: ImageOptions,
+}
+
+impl Default for ImageFolderOptions {
+    fn default() -> Self {
+        ImageFolderOptions {
+            split: Split::Hash(0.8),
+            extensions: ["png", "jpg", "jpeg", "bmp"].iter().map(|e| String::from(*e)).collect(),
+            classes: None,
+            preload: false,
+            image: ImageOptions::default(),
+        }
+    }
+}
+
+/// Loads a dataset laid out as `root/<class>/<image>`, where every subfolder of
+/// `root` is a class. Images may be nested further within their class folder.
+/// Classes are numbered in alphabetical order of their folder names, which are
+/// kept as the label names. Unless `options.image` resizes them, every image
+/// must have the same dimensions.
+pub fn load_image_folder<P: AsRef<Path>>(root: P, options: &ImageFolderOptions) -> Result<TrainingData, String> {
+    let root = root.as_ref();
+    if !root.is_dir() {
+        return Err(format!("Folder {} does not exist.", root.display()));
+    }
+    if let Split::Hash(fraction) | Split::Ratio { train_fraction: fraction, .. } = options.split {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(format!("Train fraction must be between 0 and 1, got {}", fraction));
+        }
+    }
+
+    let (trn_root, tst_root) = match options.split {
+        Split::Subfolders => {
+            for folder in ["train", "test"] {
+                if !root.join(folder).is_dir() {
+                    return Err(format!("Folder {} has no {} folder", root.display(), folder));
+                }
+            }
+            (root.join("train"), Some(root.join("test")))
+        }
+        _ => (root.to_path_buf(), None),
+    };
+    let class_names = class_folders(&trn_root, options)?;
+    if class_names.is_empty() {
+        return Err(format!("Folder {} has no class folders", trn_root.display()));
+    }
+
+    let mut trn = vec![];
+    let mut tst = vec![];
+    for (label, class_name) in class_names.iter().enumerate() {
+        let files = image_files(&trn_root.join(class_name), options);
+        match &options.split {
+            Split::Hash(train_fraction) => {
+                let cutoff = (train_fraction * u64::MAX as f64) as u64;
+                for file in files {
+                    if path_hash(&file, &trn_root.join(class_name)) < cutoff {
+                        trn.push((file, label));
+                    } else {
+                        tst.push((file, label));
+                    }
+                }
+            }
+            Split::Ratio { train_fraction, seed } => {
+                let mut files = files;
+                // Each class gets its own stream, so adding a class does not reshuffle the others
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(label as u64));
+                files.shuffle(&mut rng);
+                let trn_count = (files.len() as f64 * train_fraction).round() as usize;
+                for (i, file) in files.into_iter().enumerate() {
+                    if i < trn_count {
+                        trn.push((file, label));
+                    } else {
+                        tst.push((file, label));
+                    }
+                }
+            }
+            Split::Subfolders => {
+                trn.extend(files.into_iter().map(|file| (file, label)));
+                let tst_folder = tst_root.as_ref().unwrap().join(class_name);
+                tst.extend(image_files(&tst_folder, options).into_iter().map(|file| (file, label)));
+            }
+        }
+    }
+
+    let (rows, cols) = match options.image.size {
+        Some(size) => size,
+        None => check_dimensions(trn.iter().chain(tst.iter()).map(|(file, _)| file))?,
+    };
+    let (trn_img, trn_lbl) = to_images(trn, options)?;
+    let (tst_img, tst_lbl) = to_images(tst, options)?;
+
+    Ok(TrainingData {
+        trn_size: trn_img.len(),
+        tst_size: tst_img.len(),
+        trn_img,
+        trn_lbl,
+        tst_img,
+        tst_lbl,
+        rows,
+        cols,
+        classes: (0..class_names.len()).map(|i| (i, i)).collect(),
+        label_names: class_names.into_iter().enumerate().collect(),
+        image_options: options.image.clone(),
+    })
+}
+
+/// Sorted names of the class folders in `root`, skipping hidden folders
+fn class_folders(root: &Path, options: &ImageFolderOptions) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(root).map_err(|e| format!("Failed to read {}: {}", root.display(), e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().to_str().map(String::from))
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+
+    if let Some(classes) = &options.classes {
+        let missing: Vec<&String> = classes.iter().filter(|c| !names.contains(c)).collect();
+        if !missing.is_empty() {
+            return Err(format!("Folder {} has no class folders named {:?}", root.display(), missing));
+        }
+        names.retain(|name| classes.contains(name));
+    }
+
+    Ok(names)
+}
+
+/// Sorted paths of every image below `folder`
+fn image_files(folder: &Path, options: &ImageFolderOptions) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(folder)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| e.into_path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .map(|e| options.extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+                .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+
+    files
+}
+
+/// FNV-1a hash of the path within its class folder, with `/` between components
+fn path_hash(file: &Path, class_folder: &Path) -> u64 {
+    let relative = file.strip_prefix(class_folder).unwrap_or(file);
+    let components: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+    fnv1a(components.join("/").as_bytes())
+}
+
+/// Reads the dimensions of every image from its header, returning the shared (rows, cols)
+fn check_dimensions<'a, I: Iterator<Item = &'a PathBuf>>(files: I) -> Result<(usize, usize), String> {
+    let mut dimensions: Option<((u32, u32), &Path)> = None;
+    for file in files {
+        let (width, height) = image::image_dimensions(file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        match dimensions {
+            None => dimensions = Some(((width, height), file)),
+            Some((expected, first)) if expected != (width, height) => {
+                return Err(format!(
+                    "{} is {}x{} but {} is {}x{}, every image must have the same dimensions",
+                    file.display(), width, height, first.display(), expected.0, expected.1
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    match dimensions {
+        Some(((width, height), _)) => Ok((height as usize, width as usize)),
+        None => Err(String::from("No images found")),
+    }
+}
+
+fn to_images(files: Vec<(PathBuf, usize)>, options: &ImageFolderOptions) -> Result<(Vec<TrainImage>, Vec<usize>), String> {
+    let mut images = Vec::with_capacity(files.len());
+    let mut labels = Vec::with_capacity(files.len());
+    for (file, label) in files {
+        let image = if options.preload {
+            TrainImage::Image(load_image_with(&file, &options.image).map_err(|e| format!("Failed to load {}: {}", file.display(), e))?)
+        } else {
+            TrainImage::Path(file)
+        };
+        images.push(image);
+        labels.push(label);
+    }
+
+    Ok((images, labels))
+}
@@ -3,8 +3,28 @@ pub mod mxpl_layer;
 pub mod dense_layer;
 pub mod layer;
 pub mod cnn;
+pub mod builder;
+pub mod config;
 pub mod util;
+pub mod dataset;
+pub mod augment;
+pub mod normalize;
+pub mod prefetch;
+pub mod sampler;
 pub mod activation;
-// pub mod fiftystates;
+pub mod loss;
+pub mod fiftystates;
 pub mod optimizer;
-pub mod mnist;
\ No newline at end of file
+pub mod mnist;
+pub mod idx;
+pub mod image_folder;
+pub mod cifar;
+pub mod metrics;
+pub mod cross_validation;
+pub mod search;
+pub mod predictor;
+pub mod summary;
+pub mod server;
+pub mod model_file;
+pub mod onnx;
+pub mod numpy;
\ No newline at end of file
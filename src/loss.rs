@@ -0,0 +1,93 @@
+use ndarray::Array1;
+use serde::{Serialize, Deserialize};
+use crate::dataset::{class_counts, Dataset};
+use crate::sampler::check_weights;
+
+/// Loss minimised during training
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Loss {
+    /// Cross entropy, fused with a softmax output layer
+    #[default]
+    CrossEntropy,
+    MeanSquaredError,
+}
+
+/// Per-class weights that the loss of each sample is multiplied by
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ClassWeights {
+    /// Every class counts the same
+    #[default]
+    None,
+    /// Inversely proportional to the frequency of each class in the training set,
+    /// as `samples / (classes * class_samples)`
+    Balanced,
+    Fixed { weights: Vec<f32> },
+}
+
+impl ClassWeights {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ClassWeights::Fixed { weights } => check_weights("Class weights", weights),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that fixed weights have one weight per class
+    pub fn class_errors(&self, num_classes: usize) -> Vec<String> {
+        match self {
+            ClassWeights::Fixed { weights } if weights.len() != num_classes => vec![format!(
+                "Class weights have {} values but the network has {} classes", weights.len(), num_classes
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// The weight of every class, or `None` if the loss is unweighted.
+    /// Balanced weights are computed from the labels of `data`, and classes
+    /// without samples get a weight of 1.
+    pub fn weights<D: Dataset + ?Sized>(&self, data: &D, num_classes: usize) -> Result<Option<Array1<f32>>, String> {
+        match self {
+            ClassWeights::None => Ok(None),
+            ClassWeights::Balanced => {
+                let counts = class_counts(data, num_classes)?;
+                let total = data.len() as f32;
+                Ok(Some(counts.iter().map(|&count| match count {
+                    0 => 1.0,
+                    count => total / (num_classes * count) as f32,
+                }).collect()))
+            }
+            ClassWeights::Fixed { weights } => match self.class_errors(num_classes).pop() {
+                Some(e) => Err(e),
+                None => Ok(Some(Array1::from(weights.clone()))),
+            },
+        }
+    }
+}
+
+/// Weight of a sample with the given target under per-class weights.
+/// Mixed targets get the mix of the weights of their classes.
+pub fn sample_weight(desired: &Array1<f32>, class_weights: &Array1<f32>) -> f32 {
+    desired.dot(class_weights)
+}
+
+/// Loss of a single output against the one-hot `desired` output
+pub fn loss(output: &Array1<f32>, desired: &Array1<f32>, loss: Loss) -> f32 {
+    match loss {
+        Loss::CrossEntropy => -desired.iter().zip(output.iter())
+            .map(|(d, o)| d * o.max(1e-7).ln())
+            .sum::<f32>(),
+        Loss::MeanSquaredError => (output - desired).mapv(|x| x.powi(2)).mean().unwrap_or(0.0),
+    }
+}
+
+/// Gradient of the loss with respect to the output of the last layer.
+/// The cross entropy gradient assumes a softmax output layer, whose derivative
+/// is then treated as one by `activation::backward`.
+pub fn gradient(output: &Array1<f32>, desired: &Array1<f32>, loss: Loss) -> Array1<f32> {
+    match loss {
+        Loss::CrossEntropy => output - desired,
+        Loss::MeanSquaredError => (output - desired) * (2.0 / output.len() as f32),
+    }
+}
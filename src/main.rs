@@ -1,69 +1,470 @@
-use oxi_net::cnn::*;
-// use oxi_net::fiftystates::load_50states10k;
-use oxi_net::mnist::load_mnist;
-use oxi_net::optimizer::OptimizerAlg;
-use oxi_net::activation::Activation;
-
-
-fn main() {
-    // Load MNIST dataset
-    let data = load_mnist("./data/");
-
-    // Set hyperparameters
-    let hyperparameters = Hyperparameters {
-        batch_size: 10,
-        epochs: 10,
-        optimizer: OptimizerAlg::SGD(0.1),
-        ..Hyperparameters::default()
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, Subcommand, ValueEnum};
+use walkdir::WalkDir;
+use oxi_net::cnn::CNN;
+use oxi_net::config::NetworkConfig;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cifar::{load_cifar10, load_cifar100, Cifar100Labels};
+use oxi_net::cross_validation;
+use oxi_net::fiftystates::load_50states10k;
+use oxi_net::image_folder::{load_image_folder, ImageFolderOptions};
+use oxi_net::mnist::{load_emnist, load_fashion_mnist, load_kmnist, load_mnist, EmnistSplit};
+use oxi_net::model_file::{self, ModelFormat};
+use oxi_net::search::Search;
+use oxi_net::server::InferenceServer;
+use oxi_net::util::{load_image_with, ImageOptions, ResizeFilter, ResizeMode, TrainingData};
+
+/// Exit code for commands that ran but failed, such as on a missing file.
+/// Invalid arguments exit with 2.
+const EXIT_FAILURE: u8 = 1;
+
+/// Image extensions picked up when predicting on a directory
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "bmp"];
+
+#[derive(Parser)]
+#[command(name = "oxi_net", version, about = "Train and run convolutional neural networks")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Train a network described by a config file
+    Train {
+        /// JSON or TOML network config, see `configs/`
+        #[arg(short, long)]
+        config: PathBuf,
+        /// Dataset directory
+        #[arg(short, long)]
+        data: PathBuf,
+        #[arg(long, value_enum, default_value_t = DatasetKind::Mnist)]
+        dataset: DatasetKind,
+        /// Where to save the trained model, in addition to the config's saving strategy
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Cross-validate a network described by a config file on stratified folds
+    /// of the training split of a dataset
+    CrossValidate {
+        /// JSON or TOML network config, see `configs/`
+        #[arg(short, long)]
+        config: PathBuf,
+        /// Dataset directory
+        #[arg(short, long)]
+        data: PathBuf,
+        #[arg(long, value_enum, default_value_t = DatasetKind::Mnist)]
+        dataset: DatasetKind,
+        /// Number of folds
+        #[arg(short = 'k', long, default_value_t = 5)]
+        folds: usize,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Search over settings of a config file, ranking trials on a validation fold of the
+    /// training split and testing only the best network on the test split
+    Search {
+        /// JSON or TOML network config that the search starts from
+        #[arg(short, long)]
+        config: PathBuf,
+        /// JSON or TOML search file, see `Search`
+        #[arg(short, long)]
+        search: PathBuf,
+        /// Dataset directory
+        #[arg(short, long)]
+        data: PathBuf,
+        #[arg(long, value_enum, default_value_t = DatasetKind::Mnist)]
+        dataset: DatasetKind,
+        /// Directory for the leaderboard and the best model
+        #[arg(short, long, default_value = "search")]
+        output: PathBuf,
+    },
+    /// Evaluate a saved model on the test split of a dataset
+    Eval {
+        #[arg(short, long)]
+        model: PathBuf,
+        #[arg(short, long)]
+        data: PathBuf,
+        #[arg(long, value_enum, default_value_t = DatasetKind::Mnist)]
+        dataset: DatasetKind,
+        /// Print the metrics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Predict the classes of image files, or of every image in a directory
+    Predict {
+        #[arg(short, long)]
+        model: PathBuf,
+        #[arg(required = true)]
+        images: Vec<PathBuf>,
+        /// Number of most likely classes to print for every image
+        #[arg(short = 'k', long, default_value_t = 1)]
+        top_k: usize,
+        /// Print the predictions as JSON
+        #[arg(long)]
+        json: bool,
+        /// Resize images to the model's input size. Without it, images of any other size are rejected.
+        #[arg(long, value_enum)]
+        resize: Option<ResizeArg>,
+        /// Interpolation used by --resize
+        #[arg(long, value_enum, default_value_t = FilterArg::Bilinear)]
+        filter: FilterArg,
+    },
+    /// Print the layers, parameter counts and FLOPs of a saved model or config file
+    Summary {
+        path: PathBuf,
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Serve predictions from a saved model over HTTP, see `InferenceServer`
+    Serve {
+        #[arg(short, long)]
+        model: PathBuf,
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// File with one class name per line, in class index order.
+        /// Defaults to the label names saved with the model, if any.
+        #[arg(short, long)]
+        labels: Option<PathBuf>,
+    },
+    /// Convert a saved model between formats
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Output format, guessed from the output extension if not given
+        #[arg(short, long, value_enum)]
+        format: Option<FormatArg>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DatasetKind {
+    /// MNIST IDX files, optionally gzipped
+    Mnist,
+    /// Fashion-MNIST IDX files, optionally gzipped
+    FashionMnist,
+    /// Kuzushiji-MNIST IDX files, optionally gzipped
+    Kmnist,
+    /// EMNIST Letters IDX files, with 26 classes
+    EmnistLetters,
+    /// EMNIST Balanced IDX files, with 47 classes
+    EmnistBalanced,
+    /// 50States10K folders, split 95/5
+    FiftyStates,
+    /// One folder of images per class, split 80/20, see `load_image_folder`
+    ImageFolder,
+    /// CIFAR-10 binary batches
+    Cifar10,
+    /// CIFAR-100 binary batches, with the 100 fine labels
+    Cifar100,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ResizeArg {
+    /// Scale each axis independently
+    Stretch,
+    /// Scale to cover the input, cutting off the overhang
+    CenterCrop,
+    /// Scale to fit inside the input, padding with zeros
+    Letterbox,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FilterArg {
+    Nearest,
+    Bilinear,
+    Area,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Json,
+    Binary,
+    BinaryF16,
+}
+
+impl From<FormatArg> for ModelFormat {
+    fn from(format: FormatArg) -> ModelFormat {
+        match format {
+            FormatArg::Json => ModelFormat::Json,
+            FormatArg::Binary => ModelFormat::Binary,
+            FormatArg::BinaryF16 => ModelFormat::BinaryF16,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Train { config, data, dataset, output } => train(&config, &data, dataset, output.as_deref()),
+        Command::CrossValidate { config, data, dataset, folds, json } => cross_validate(&config, &data, dataset, folds, json),
+        Command::Search { config, search, data, dataset, output } => run_search(&config, &search, &data, dataset, &output),
+        Command::Eval { model, data, dataset, json } => eval(&model, &data, dataset, json),
+        Command::Predict { model, images, top_k, json, resize, filter } => predict(&model, &images, top_k, json, resize, filter),
+        Command::Summary { path, json } => summary(&path, json),
+        Command::Serve { model, host, port, labels } => serve(&model, &host, port, labels.as_deref()),
+        Command::Convert { input, output, format } => convert(&input, &output, format),
     };
 
-    // Create CNN architecture
-    let mut cnn = CNN::new(data, hyperparameters);
-    cnn.set_input_shape(vec![28, 28, 3]);
-    cnn.add_conv_layer(8, 3);
-    cnn.add_mxpl_layer(2);
-    cnn.add_dense_layer(128, Activation::Relu, Some(0.25));
-    cnn.add_dense_layer(64, Activation::Relu, Some(0.25));
-    cnn.add_dense_layer(10, Activation::Softmax, None);
-
-    cnn.train();
-
-}
-
-// Example CNN for 50States10K dataset
-// To run this example, download the 50States10K dataset 
-// to the root of the project
-/*
-fn main() {
-    // Filter for only 2 states for faster training with 95% train/test split.
-    // Note: to save on memory, the images are loaded on demand, which slows
-    // down training. Smaller datasets, loaded into memory, will train faster.
-    let filter = Some(vec!["Hawaii", "Alaska"]);
-    let data = load_50states10k("../oxi_net/50States10K/", 0.95, filter.clone()).unwrap();
-
-    // Set hyperparameters
-    let hyperparameters = Hyperparameters {
-        batch_size: 10,
-        epochs: 10,
-        optimizer: OptimizerAlg::RMSProp(0.001, 0.9),
-        ..Hyperparameters::default()
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn load_dataset(path: &Path, dataset: DatasetKind) -> Result<TrainingData, String> {
+    if !path.is_dir() {
+        return Err(format!("Dataset directory {} does not exist", path.display()));
+    }
+    match dataset {
+        DatasetKind::Mnist => load_mnist(path),
+        DatasetKind::FashionMnist => load_fashion_mnist(path),
+        DatasetKind::Kmnist => load_kmnist(path),
+        DatasetKind::EmnistLetters => load_emnist(path, EmnistSplit::Letters),
+        DatasetKind::EmnistBalanced => load_emnist(path, EmnistSplit::Balanced),
+        DatasetKind::FiftyStates => load_50states10k(path, 0.95, None),
+        DatasetKind::ImageFolder => load_image_folder(path, &ImageFolderOptions::default()),
+        DatasetKind::Cifar10 => load_cifar10(path),
+        DatasetKind::Cifar100 => load_cifar100(path, Cifar100Labels::Fine),
+    }
+}
+
+fn train(config: &Path, data: &Path, dataset: DatasetKind, output: Option<&Path>) -> Result<(), String> {
+    // Check the config before spending time on the dataset
+    let network_config = NetworkConfig::load(config)?;
+    network_config.validate().map_err(|e| format!("Invalid config {}:\n{}", config.display(), e))?;
+    let data = load_dataset(data, dataset)?;
+
+    let mut cnn = CNN::from_network_config(&network_config, data)?;
+    println!("{:?}", cnn.summary());
+    // The trained network is still written to the output if a save during training failed
+    let trained = cnn.train();
+
+    if let Some(output) = output {
+        cnn.save_to(output)?;
+        println!("Saved model to {}", output.display());
+    }
+
+    trained
+}
+
+fn cross_validate(config: &Path, data: &Path, dataset: DatasetKind, folds: usize, json: bool) -> Result<(), String> {
+    let network_config = NetworkConfig::load(config)?;
+    network_config.validate().map_err(|e| format!("Invalid config {}:\n{}", config.display(), e))?;
+    let data = load_dataset(data, dataset)?;
+
+    let results = cross_validation::cross_validate(&CnnBuilder::from_config(&network_config), &data, folds)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results).unwrap());
+    } else {
+        println!("{:?}", results);
+    }
+
+    Ok(())
+}
+
+fn run_search(config: &Path, search_file: &Path, data: &Path, dataset: DatasetKind, output: &Path) -> Result<(), String> {
+    let network_config = NetworkConfig::load(config)?;
+    network_config.validate().map_err(|e| format!("Invalid config {}:\n{}", config.display(), e))?;
+    let search = Search::load(search_file)?;
+    search.validate(&network_config).map_err(|e| format!("Invalid search {}:\n{}", search_file.display(), e))?;
+    let data = load_dataset(data, dataset)?;
+
+    let results = search.run(&network_config, &data)?;
+    for trial in results.leaderboard() {
+        match (trial.score, &trial.error) {
+            (Some(score), _) => println!("Trial {}: {:.4} {}", trial.number, score, serde_json::to_string(&trial.values).unwrap()),
+            (None, error) => println!("Trial {}: failed {}: {}", trial.number, serde_json::to_string(&trial.values).unwrap(), error.as_deref().unwrap_or("")),
+        }
+    }
+    if let Some(metrics) = &results.test_metrics {
+        println!("Best model on the test split:\n{:?}", metrics);
+    }
+    match results.save(output)? {
+        Some(best) => println!("Saved the leaderboard to {} and the best model to {}", output.display(), best.display()),
+        None => return Err(format!("Every trial failed, see {}", output.join("leaderboard.csv").display())),
+    }
+
+    Ok(())
+}
+
+fn eval(model: &Path, data: &Path, dataset: DatasetKind, json: bool) -> Result<(), String> {
+    let mut cnn = CNN::load_from(model)?;
+    let data = load_dataset(data, dataset)?;
+    data.check_input_shape(cnn.input_shape())?;
+    let metrics = cnn.evaluate(&data.test_set());
+    if json {
+        println!("{}", serde_json::to_string_pretty(&metrics).unwrap());
+    } else {
+        println!("{:?}", metrics);
+    }
+
+    Ok(())
+}
+
+/// Every image under the given paths, with directories searched recursively
+fn image_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut images = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = WalkDir::new(path)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .map(|e| e.into_path())
+                .filter(|p| p.is_file() && is_image(p))
+                .collect();
+            found.sort();
+            images.extend(found);
+        } else if path.is_file() {
+            images.push(path.clone());
+        } else {
+            return Err(format!("{} does not exist", path.display()));
+        }
+    }
+
+    Ok(images)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn predict(model: &Path, images: &[PathBuf], top_k: usize, json: bool, resize: Option<ResizeArg>, filter: FilterArg) -> Result<(), String> {
+    let cnn = CNN::load_from(model)?;
+    let class_names = cnn.class_names();
+    let predictor = cnn.into_predictor();
+    let mut options = ImageOptions::for_input_shape(predictor.input_shape())?;
+    options.filter = match filter {
+        FilterArg::Nearest => ResizeFilter::Nearest,
+        FilterArg::Bilinear => ResizeFilter::Bilinear,
+        FilterArg::Area => ResizeFilter::Area,
     };
+    match resize {
+        None => options.size = None,
+        Some(ResizeArg::Stretch) => options.mode = ResizeMode::Stretch,
+        Some(ResizeArg::CenterCrop) => options.mode = ResizeMode::CenterCrop,
+        Some(ResizeArg::Letterbox) => options.mode = ResizeMode::Letterbox,
+    }
+    let paths = image_paths(images)?;
+    if paths.is_empty() {
+        return Err(String::from("No images found"));
+    }
+
+    let mut failures = 0;
+    let mut predictions = vec![];
+    for path in &paths {
+        let image = load_image_with(path, &options).and_then(|image| {
+            if image.dim() == predictor.input_shape() {
+                Ok(image)
+            } else {
+                Err(format!("image has shape {:?} but the model expects {:?}", image.dim(), predictor.input_shape()))
+            }
+        });
+        match image {
+            Ok(image) => {
+                let top = predictor.predict_top_k(&image, top_k);
+                if json {
+                    let classes: Vec<_> = top.iter()
+                        .map(|&(class, probability)| {
+                            let mut value = serde_json::json!({"class": class, "probability": probability});
+                            // Outputs past the classes of the training data have no name
+                            if let Some(name) = class_names.as_ref().and_then(|names| names.get(class)) {
+                                value["label"] = serde_json::json!(name);
+                            }
+                            value
+                        })
+                        .collect();
+                    predictions.push(serde_json::json!({"path": path, "top_k": classes}));
+                } else {
+                    let classes: Vec<String> = top.iter()
+                        .map(|&(class, probability)| match class_names.as_ref().and_then(|names| names.get(class)) {
+                            Some(name) => format!("{} ({:.4})", name, probability),
+                            None => format!("{} ({:.4})", class, probability),
+                        })
+                        .collect();
+                    println!("{}: {}", path.display(), classes.join(", "));
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                failures += 1;
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&predictions).unwrap());
+    }
 
-    // Create CNN architecture
-    let mut cnn = CNN::new(data, hyperparameters);
-    cnn.add_mxpl_layer((256, 256, 3), 2);
-    cnn.add_mxpl_layer((128, 128, 3), 2);
-    cnn.add_mxpl_layer((64, 64, 3), 2);
-    cnn.add_conv_layer((32, 32, 3), 8, 3);
-    cnn.add_mxpl_layer((30, 30, 8), 2);
-    cnn.add_dense_layer(15 * 15 * 8, 256, Activation::Relu, Some(0.25));
-    cnn.add_dense_layer(256, 128, Activation::Relu, Some(0.25));
-    let output_neurons = match filter {
-        Some(f) => f.len(),
-        None => 50,
+    if failures > 0 {
+        Err(format!("{} of {} images could not be classified", failures, paths.len()))
+    } else {
+        Ok(())
+    }
+}
+
+fn summary(path: &Path, json: bool) -> Result<(), String> {
+    // Model files never parse as configs, which reject unknown fields
+    let config = match NetworkConfig::load(path) {
+        Ok(config) => Some(config),
+        Err(e) if path.extension().map(|e| e == "toml").unwrap_or(false) => return Err(e),
+        Err(_) => None,
+    };
+    let summary = match config {
+        Some(config) => CnnBuilder::from_config(&config)
+            .build(TrainingData::default())
+            .map_err(|e| format!("Invalid config {}:\n{}", path.display(), e))?
+            .summary(),
+        None => CNN::load_from(path)?.summary(),
     };
-    cnn.add_dense_layer(128, output_neurons, Activation::Softmax, None);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+    } else {
+        print!("{:?}", summary);
+    }
 
-    cnn.train();
+    Ok(())
+}
+
+fn serve(model: &Path, host: &str, port: u16, labels: Option<&Path>) -> Result<(), String> {
+    let cnn = CNN::load_from(model)?;
+    let mut server = InferenceServer::bind(cnn, &format!("{}:{}", host, port))?;
+    if let Some(labels) = labels {
+        let contents = std::fs::read_to_string(labels)
+            .map_err(|e| format!("Failed to read {}: {}", labels.display(), e))?;
+        server = server.with_labels(contents.lines().map(String::from).collect())?;
+    }
+    match server.local_addr() {
+        Some(addr) => println!("Serving {} on http://{}", model.display(), addr),
+        None => println!("Serving {}", model.display()),
+    }
+    server.run();
+
+    Ok(())
+}
+
+fn convert(input: &Path, output: &Path, format: Option<FormatArg>) -> Result<(), String> {
+    let format = match format {
+        Some(format) => ModelFormat::from(format),
+        None => match output.extension().and_then(|e| e.to_str()) {
+            Some("json") => ModelFormat::Json,
+            Some("bin") => ModelFormat::Binary,
+            _ => return Err(format!(
+                "Cannot guess the format of {}, use --format or a .json or .bin extension",
+                output.display()
+            )),
+        },
+    };
+    model_file::convert(input, output, format)
+        .map_err(|e| format!("Failed to convert {}: {}", input.display(), e))
 }
-*/
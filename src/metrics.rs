@@ -0,0 +1,210 @@
+use ndarray::{Array1, Array2};
+use serde::{Serialize, Deserialize};
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+
+/// Classification metrics accumulated over a set of predictions.
+/// Rows of the confusion matrix are the true classes and columns
+/// are the predicted classes.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Metrics {
+    pub num_classes: usize,
+    pub confusion: Array2<usize>,
+    /// `rank_counts[r]` is the number of samples whose true class
+    /// had the (r + 1)th highest output
+    pub rank_counts: Vec<usize>,
+    pub total: usize,
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        s.push_str(&format!("{:>8} {:>10} {:>10} {:>10} {:>10}\n", "Class", "Precision", "Recall", "F1", "Support"));
+        for c in 0..self.num_classes {
+            s.push_str(&format!(
+                "{:>8} {:>10.4} {:>10.4} {:>10.4} {:>10}\n",
+                c, self.precision(c), self.recall(c), self.f1(c), self.support(c)
+            ));
+        }
+        s.push('\n');
+        s.push_str(&format!(
+            "{:>8} {:>10.4} {:>10.4} {:>10.4} {:>10}\n",
+            "Macro", self.macro_precision(), self.macro_recall(), self.macro_f1(), self.total
+        ));
+        s.push_str(&format!(
+            "{:>8} {:>10.4} {:>10.4} {:>10.4} {:>10}\n",
+            "Weighted", self.weighted_precision(), self.weighted_recall(), self.weighted_f1(), self.total
+        ));
+        s.push('\n');
+        s.push_str(&format!("Accuracy: {:.4}\n", self.accuracy()));
+        s.push_str(&format!("Balanced Accuracy: {:.4}\n", self.balanced_accuracy()));
+        for k in [1, 3, 5] {
+            if k <= self.num_classes {
+                s.push_str(&format!("Top {} Accuracy: {:.4}\n", k, self.top_k_accuracy(k)));
+            }
+        }
+        s.push_str(&format!("\nConfusion Matrix:\n{}\n", self.confusion));
+
+        write!(f, "{}", s)
+    }
+}
+
+impl Metrics {
+    pub fn new(num_classes: usize) -> Metrics {
+        Metrics {
+            num_classes,
+            confusion: Array2::<usize>::zeros((num_classes, num_classes)),
+            rank_counts: vec![0; num_classes],
+            total: 0,
+        }
+    }
+
+    /// Records the network output for a sample with the given class.
+    /// Panics if the label or the output size does not match the number of classes.
+    pub fn record(&mut self, label: usize, output: &Array1<f32>) {
+        assert!(label < self.num_classes, "Label {} is out of range for {} classes", label, self.num_classes);
+        assert_eq!(output.len(), self.num_classes, "Output has {} values but there are {} classes", output.len(), self.num_classes);
+        let mut predicted = 0;
+        let mut rank = 0;
+        // Compared with total_cmp, like Predictor::predict_top_k, so that NaN outputs
+        // rank consistently for the prediction and the rank of the true class
+        for (i, v) in output.iter().enumerate() {
+            if v.total_cmp(&output[predicted]) == Ordering::Greater {
+                predicted = i;
+            }
+            // Ties are resolved in favour of the lower index, as in get_accuracy
+            match v.total_cmp(&output[label]) {
+                Ordering::Greater => rank += 1,
+                Ordering::Equal if i < label => rank += 1,
+                _ => {}
+            }
+        }
+
+        self.confusion[[label, predicted]] += 1;
+        self.rank_counts[rank] += 1;
+        self.total += 1;
+    }
+
+    pub fn accuracy(&self) -> f32 {
+        let correct: usize = (0..self.num_classes).map(|c| self.confusion[[c, c]]).sum();
+        ratio(correct, self.total)
+    }
+
+    /// Mean recall over the classes that have samples, so that
+    /// every class counts the same however rare it is
+    pub fn balanced_accuracy(&self) -> f32 {
+        let present: Vec<usize> = (0..self.num_classes).filter(|&c| self.support(c) > 0).collect();
+        if present.is_empty() {
+            return 0.0;
+        }
+        present.iter().map(|&c| self.recall(c)).sum::<f32>() / present.len() as f32
+    }
+
+    /// Fraction of samples whose true class is among the k highest outputs
+    pub fn top_k_accuracy(&self, k: usize) -> f32 {
+        let correct: usize = self.rank_counts.iter().take(k).sum();
+        ratio(correct, self.total)
+    }
+
+    /// Cumulative top-k correct counts for every k from 1 to n
+    pub fn top_n(&self, n: usize) -> TopNAccuracy {
+        let mut corrects = Vec::with_capacity(n);
+        let mut correct = 0;
+        for k in 0..n {
+            correct += self.rank_counts.get(k).unwrap_or(&0);
+            corrects.push(correct);
+        }
+
+        TopNAccuracy { corrects, total: self.total }
+    }
+
+    /// Number of samples of the given class
+    pub fn support(&self, class: usize) -> usize {
+        self.confusion.row(class).sum()
+    }
+
+    pub fn precision(&self, class: usize) -> f32 {
+        ratio(self.confusion[[class, class]], self.confusion.column(class).sum())
+    }
+
+    /// Fraction of the samples of the given class that were classified correctly
+    #[doc(alias = "class_accuracy")]
+    pub fn recall(&self, class: usize) -> f32 {
+        ratio(self.confusion[[class, class]], self.support(class))
+    }
+
+    pub fn f1(&self, class: usize) -> f32 {
+        let (p, r) = (self.precision(class), self.recall(class));
+        if p + r == 0.0 {
+            0.0
+        } else {
+            2.0 * p * r / (p + r)
+        }
+    }
+
+    pub fn macro_precision(&self) -> f32 {
+        self.macro_average(|c| self.precision(c))
+    }
+
+    pub fn macro_recall(&self) -> f32 {
+        self.macro_average(|c| self.recall(c))
+    }
+
+    pub fn macro_f1(&self) -> f32 {
+        self.macro_average(|c| self.f1(c))
+    }
+
+    pub fn weighted_precision(&self) -> f32 {
+        self.weighted_average(|c| self.precision(c))
+    }
+
+    pub fn weighted_recall(&self) -> f32 {
+        self.weighted_average(|c| self.recall(c))
+    }
+
+    pub fn weighted_f1(&self) -> f32 {
+        self.weighted_average(|c| self.f1(c))
+    }
+
+    fn macro_average<F: Fn(usize) -> f32>(&self, metric: F) -> f32 {
+        if self.num_classes == 0 {
+            return 0.0;
+        }
+        (0..self.num_classes).map(metric).sum::<f32>() / self.num_classes as f32
+    }
+
+    fn weighted_average<F: Fn(usize) -> f32>(&self, metric: F) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (0..self.num_classes).map(|c| metric(c) * self.support(c) as f32).sum::<f32>() / self.total as f32
+    }
+}
+
+/// Top-k accuracy for every k from 1 to n.
+/// `corrects[k - 1]` is the number of samples whose true class was
+/// among the k highest outputs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TopNAccuracy {
+    pub corrects: Vec<usize>,
+    pub total: usize,
+}
+
+impl TopNAccuracy {
+    /// Top-k accuracy, with k clamped to n
+    pub fn accuracy(&self, k: usize) -> f32 {
+        let k = k.min(self.corrects.len());
+        match k {
+            0 => 0.0,
+            k => ratio(self.corrects[k - 1], self.total),
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f32 / denominator as f32
+    }
+}
@@ -1,94 +1,100 @@
 use std::path::Path;
-use rust_mnist::Mnist;
-use crate::util::{TrainingData, TrainImage, load_image};
-use std::collections::HashMap;
+use crate::idx::{load_idx_dataset, IdxFiles};
+use crate::util::TrainingData;
 use ndarray::Array3;
 use rand::seq::IteratorRandom;
 
-pub fn load_mnist<T>(mnist_path: T) -> TrainingData
-where T: AsRef<Path>
-{
-    let (rows, cols) = (28, 28);
-    let mnist_path = mnist_path.as_ref();
-    let mnist = Mnist::new(mnist_path.to_str().unwrap());
+pub const FASHION_MNIST_LABELS: [&str; 10] = [
+    "T-shirt/top", "Trouser", "Pullover", "Dress", "Coat", "Sandal", "Shirt", "Sneaker", "Bag", "Ankle boot",
+];
 
-    let mut trn_img = Vec::<TrainImage>::new();
-    let mut trn_lbl = Vec::<usize>::new();
-    let mut tst_img = Vec::<TrainImage>::new();
-    let mut tst_lbl = Vec::<usize>::new();
+pub const KMNIST_LABELS: [&str; 10] = ["o", "ki", "su", "tsu", "na", "ha", "ma", "ya", "re", "wo"];
 
-    // Make unpacked folder inside mnist_path
-    let mnist_path = mnist_path.join("unpacked");
-    if !mnist_path.exists() {
-        std::fs::create_dir(mnist_path.as_path()).expect("Failed to create unpacked folder.");
-    }
+/// Lowercase letters that EMNIST Balanced and ByMerge keep apart from their
+/// uppercase forms, after the 10 digits and 26 uppercase letters
+const EMNIST_LOWERCASE_MERGED: [char; 11] = ['a', 'b', 'd', 'e', 'f', 'g', 'h', 'n', 'q', 'r', 't'];
 
-    for i in 0..60000 {
-        let mut img: Array3<f32> = Array3::<f32>::zeros((rows, cols, 1));
+/// The EMNIST splits, which share the IDX format but differ in their classes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmnistSplit {
+    /// 26 classes, one per letter with both cases merged
+    Letters,
+    /// 47 classes with the same number of samples each
+    Balanced,
+    /// 47 classes, with the letters whose cases look alike merged
+    ByMerge,
+    /// 62 classes: digits, uppercase and lowercase letters
+    ByClass,
+    /// 10 classes of digits
+    Digits,
+}
 
-        for j in 0..rows {
-            for k in 0..cols {
-                img[[j, k, 0]] = mnist.train_data[i][(j * 28 + k) as usize] as f32 / 255.0;
-            }
+impl EmnistSplit {
+    fn name(&self) -> &'static str {
+        match self {
+            EmnistSplit::Letters => "letters",
+            EmnistSplit::Balanced => "balanced",
+            EmnistSplit::ByMerge => "bymerge",
+            EmnistSplit::ByClass => "byclass",
+            EmnistSplit::Digits => "digits",
         }
-        trn_img.push(TrainImage::Image(img));
-        trn_lbl.push(mnist.train_labels[i] as usize);
     }
 
-    for i in 0..10000 {
-        let mut img: Array3<f32> = Array3::<f32>::zeros((rows, cols, 1));
-
-        for j in 0..rows {
-            for k in 0..cols {
-                img[[j, k, 0]] = mnist.test_data[i][(j * 28 + k) as usize] as f32 / 255.0;
-            }
-        }
-        tst_img.push(TrainImage::Image(img));
-        tst_lbl.push(mnist.test_labels[i] as usize);
+    /// Every label in the files and its name, in class order
+    fn label_names(&self) -> Vec<(usize, String)> {
+        let digits = ('0'..='9').map(String::from);
+        let uppercase = ('A'..='Z').map(String::from);
+        let names: Vec<String> = match self {
+            // Letters are labelled from 1 to 26
+            EmnistSplit::Letters => return ('a'..='z').enumerate()
+                .map(|(i, c)| (i + 1, format!("{}{}", c.to_ascii_uppercase(), c)))
+                .collect(),
+            EmnistSplit::Balanced | EmnistSplit::ByMerge => digits.chain(uppercase)
+                .chain(EMNIST_LOWERCASE_MERGED.iter().map(|c| c.to_string()))
+                .collect(),
+            EmnistSplit::ByClass => digits.chain(uppercase).chain(('a'..='z').map(String::from)).collect(),
+            EmnistSplit::Digits => digits.collect(),
+        };
+        names.into_iter().enumerate().collect()
     }
+}
 
-    // 'classes' allows us to only train on a subset of the data
-    // Here, we use all 10 classes
-
-    let classes: HashMap<usize, usize> = (0..10).enumerate().collect();
+/// Loads MNIST from the folder holding its four IDX files, which may be gzipped
+pub fn load_mnist<T: AsRef<Path>>(mnist_path: T) -> Result<TrainingData, String> {
+    let labels: Vec<(usize, String)> = (0..10).map(|i| (i, i.to_string())).collect();
+    load_idx_dataset(mnist_path, &IdxFiles::mnist(), &labels, false)
+}
 
-    let training_data: TrainingData = TrainingData {
-        trn_img,
-        trn_lbl,
-        tst_img,
-        tst_lbl,
-        rows,
-        cols,
-        trn_size: 60000,
-        tst_size: 10000,
-        classes,
-    };
+/// Loads Fashion-MNIST, which uses the same file names as MNIST
+pub fn load_fashion_mnist<T: AsRef<Path>>(path: T) -> Result<TrainingData, String> {
+    load_idx_dataset(path, &IdxFiles::mnist(), &named_labels(&FASHION_MNIST_LABELS), false)
+}
 
-    training_data
+/// Loads Kuzushiji-MNIST, which uses the same file names as MNIST.
+/// Classes are named by the romanized hiragana they show.
+pub fn load_kmnist<T: AsRef<Path>>(path: T) -> Result<TrainingData, String> {
+    load_idx_dataset(path, &IdxFiles::mnist(), &named_labels(&KMNIST_LABELS), false)
 }
 
+/// Loads one split of EMNIST from the folder holding files such as
+/// `emnist-letters-train-images-idx3-ubyte`. EMNIST stores its images
+/// transposed, so they are flipped back to upright.
+pub fn load_emnist<T: AsRef<Path>>(path: T, split: EmnistSplit) -> Result<TrainingData, String> {
+    load_idx_dataset(path, &IdxFiles::emnist(split.name()), &split.label_names(), true)
+}
 
+fn named_labels(names: &[&str]) -> Vec<(usize, String)> {
+    names.iter().enumerate().map(|(i, name)| (i, String::from(*name))).collect()
+}
 
 pub fn get_random_image(data: &TrainingData) -> (Array3<f32>, usize) {
     let mut rng = rand::thread_rng();
     let (img, label) = data.trn_img.iter().zip(data.trn_lbl.iter()).choose(&mut rng).unwrap();
-    match img {
-        TrainImage::Image(img) => (img.clone(), *label),
-        TrainImage::Path(img_path) => {
-            let img = load_image(img_path).unwrap();
-            (img, *label)
-        }
-    }
+    (img.load_with(&data.image_options).unwrap(), *label)
 }
 
 pub fn get_random_test_image(data: &TrainingData) -> (Array3<f32>, usize) {
     let mut rng = rand::thread_rng();
     let (img, label) = data.tst_img.iter().zip(data.tst_lbl.iter()).choose(&mut rng).unwrap();
-    match img {
-        TrainImage::Image(img) => (img.clone(), *label),
-        TrainImage::Path(img_path) => {
-            let img = load_image(img_path).unwrap();
-            (img, *label)
-        }
-    }
+    (img.load_with(&data.image_options).unwrap(), *label)
 }
\ No newline at end of file
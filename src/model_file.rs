@@ -0,0 +1,460 @@
+use half::f16;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Map, Value};
+use serde_json::ser::{CompactFormatter, Formatter};
+use std::io::Write;
+use std::path::Path;
+
+/// Magic bytes at the start of every binary model file
+const MAGIC: &[u8; 4] = b"OXIN";
+/// Version of the binary container layout
+pub const BINARY_FORMAT_VERSION: u32 = 1;
+/// Version of the serialized model schema, stored in every saved model.
+/// Whenever the serialized fields of the model change, bump this and add
+/// a migration from the previous version to `MIGRATIONS`.
+pub const SCHEMA_VERSION: u64 = 10;
+/// `MIGRATIONS[v]` upgrades a serialized model from schema version v to v + 1.
+/// Files saved before schema versions were introduced are version 0.
+const MIGRATIONS: [fn(&mut Map<String, Value>); SCHEMA_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+    migrate_v8_to_v9,
+    migrate_v9_to_v10,
+];
+
+/// How a full model is written to disk
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFormat {
+    /// Human-readable JSON
+    #[default]
+    Json,
+    /// Binary container with f32 tensors
+    Binary,
+    /// Binary container with f16 tensors, which halves the file size
+    /// at the cost of precision
+    BinaryF16,
+}
+
+impl ModelFormat {
+    /// File extension used for models saved in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ModelFormat::Json => "json",
+            ModelFormat::Binary | ModelFormat::BinaryF16 => "bin",
+        }
+    }
+}
+
+/// Serializes a model to a JSON value tagged with the current schema version,
+/// then writes it in the given format
+pub fn write_model<T: Serialize, P: AsRef<Path>>(model: &T, path: P, format: ModelFormat) -> Result<(), String> {
+    let mut value = serde_json::to_value(model).map_err(|e| e.to_string())?;
+    match value.as_object_mut() {
+        Some(map) => map.insert(String::from("schema_version"), Value::from(SCHEMA_VERSION)),
+        None => return Err(String::from("Model did not serialize to a JSON object")),
+    };
+    write_value(&value, path, format)
+}
+
+/// Writes a model that has already been serialized to a JSON value
+pub fn write_value<P: AsRef<Path>>(value: &Value, path: P, format: ModelFormat) -> Result<(), String> {
+    let bytes = match format {
+        ModelFormat::Json => {
+            let mut bytes = vec![];
+            let mut serializer = serde_json::Serializer::with_formatter(&mut bytes, F32Formatter);
+            value.serialize(&mut serializer).map_err(|e| e.to_string())?;
+            bytes
+        }
+        ModelFormat::Binary => encode_binary(value, false)?,
+        ModelFormat::BinaryF16 => encode_binary(value, true)?,
+    };
+    write_atomic(path, &bytes)
+}
+
+/// Writes to a temporary file next to `path` then renames it into place,
+/// so an interrupted save never leaves a partially written file behind
+pub fn write_atomic<P: AsRef<Path>>(path: P, bytes: &[u8]) -> Result<(), String> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().ok_or_else(|| format!("Invalid file path {}", path.display()))?.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    std::fs::write(&tmp_path, bytes).map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_path);
+        format!("Failed to write {}: {}", path.display(), e)
+    })
+}
+
+/// Reads a model file as a JSON value, detecting whether it is JSON or binary
+pub fn read_value<P: AsRef<Path>>(path: P) -> Result<Value, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if bytes.starts_with(MAGIC) {
+        decode_binary(&bytes)
+    } else {
+        serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// Reads a model file in either format, migrating it to the current schema version
+pub fn read_model<T: for<'de> Deserialize<'de>, P: AsRef<Path>>(path: P) -> Result<T, String> {
+    let value = migrate(read_value(path)?)?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// Upgrades a serialized model of any older schema version to `SCHEMA_VERSION`,
+/// and removes the version tag
+pub fn migrate(value: Value) -> Result<Value, String> {
+    let mut map = match value {
+        Value::Object(map) => map,
+        _ => return Err(String::from("Model file does not contain a JSON object")),
+    };
+    let version = match map.remove("schema_version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| format!("Invalid schema version {}", v))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Model file has schema version {}, but this version of oxi_net only supports versions up to {}. \
+            Upgrade oxi_net to load it.", version, SCHEMA_VERSION
+        ));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut map);
+    }
+
+    Ok(Value::Object(map))
+}
+
+/// Version 1 added the model format, defaulting to JSON
+fn migrate_v0_to_v1(model: &mut Map<String, Value>) {
+    model.entry("model_format").or_insert(json!("Json"));
+}
+
+/// Version 2 made the save location and file names configurable,
+/// defaulting to the previously hard-coded `models/{name}_{time}`
+fn migrate_v1_to_v2(model: &mut Map<String, Value>) {
+    model.entry("save_dir").or_insert(json!("models"));
+    model.entry("file_name").or_insert(json!("{name}_{time}"));
+    model.entry("keep_last").or_insert(Value::Null);
+}
+
+/// Version 3 added the loss and learning rate schedule, defaulting to
+/// cross entropy with a constant learning rate
+fn migrate_v2_to_v3(model: &mut Map<String, Value>) {
+    model.entry("loss").or_insert(json!("CrossEntropy"));
+    model.entry("lr_schedule").or_insert(json!("Constant"));
+}
+
+/// Version 4 added label names to the training data
+fn migrate_v3_to_v4(model: &mut Map<String, Value>) {
+    if let Some(Value::Object(data)) = model.get_mut("data") {
+        data.entry("label_names").or_insert(json!({}));
+    }
+}
+
+/// Version 5 added data augmentation, defaulting to none
+fn migrate_v4_to_v5(model: &mut Map<String, Value>) {
+    model.entry("augmentation").or_insert(json!({"steps": [], "seed": null}));
+}
+
+/// Version 6 added input normalization, defaulting to none
+fn migrate_v5_to_v6(model: &mut Map<String, Value>) {
+    model.entry("normalize").or_insert(json!({"type": "none"}));
+    model.entry("normalization").or_insert(Value::Null);
+}
+
+/// Version 7 added the options for decoding training images stored as paths,
+/// defaulting to RGB at the size of each file
+fn migrate_v6_to_v7(model: &mut Map<String, Value>) {
+    if let Some(Value::Object(data)) = model.get_mut("data") {
+        data.entry("image_options").or_insert(json!({
+            "size": null,
+            "filter": "Bilinear",
+            "mode": "Stretch",
+            "color": "Rgb",
+        }));
+    }
+}
+
+/// Version 8 added prefetching, which older models did not do
+fn migrate_v7_to_v8(model: &mut Map<String, Value>) {
+    model.entry("prefetch").or_insert(json!({"workers": 0, "queue_size": 64, "cache_mb": 0}));
+}
+
+/// Version 9 added weighted sampling and class weights, defaulting to neither
+fn migrate_v8_to_v9(model: &mut Map<String, Value>) {
+    model.entry("sampler").or_insert(json!({"type": "uniform"}));
+    model.entry("class_weights").or_insert(json!({"type": "none"}));
+}
+
+/// Version 10 wrote every enum of the config format in snake_case, tagging enums
+/// that carry settings with a `type` field instead of the variant name
+fn migrate_v9_to_v10(model: &mut Map<String, Value>) {
+    if let Some(optimizer) = model.get_mut("optimizer") {
+        retag_optimizer(optimizer);
+    }
+    if let Some(Value::Array(layers)) = model.get_mut("layers") {
+        // Layers themselves are still tagged by variant name, as in {"Dense": {...}}
+        for layer in layers.iter_mut().filter_map(|l| l.as_object_mut()).flat_map(|l| l.values_mut()) {
+            if let Some(alg) = layer.get_mut("optimizer").and_then(|o| o.get_mut("alg")) {
+                retag_optimizer(alg);
+            }
+            if let Some(activation) = layer.get_mut("activation") {
+                rename_variant(activation);
+            }
+        }
+    }
+    for key in ["loss", "model_format"] {
+        if let Some(value) = model.get_mut(key) {
+            rename_variant(value);
+        }
+    }
+    if let Some(schedule) = model.get_mut("lr_schedule") {
+        retag(schedule, &[]);
+    }
+    if let Some(strategy) = model.get_mut("saving_strategy") {
+        retag(strategy, &[
+            ("EveryEpoch", &["full_save"]),
+            ("EveryNthEpoch", &["full_save", "n"]),
+            ("BestTrainingAccuracy", &["full_save"]),
+            ("BestTestingAccuracy", &["full_save"]),
+        ]);
+    }
+    if let Some(Value::Object(options)) = model.get_mut("data").and_then(|d| d.get_mut("image_options")) {
+        for key in ["filter", "mode", "color"] {
+            if let Some(value) = options.get_mut(key) {
+                rename_variant(value);
+            }
+        }
+    }
+}
+
+fn retag_optimizer(optimizer: &mut Value) {
+    retag(optimizer, &[
+        ("SGD", &["lr"]),
+        ("Momentum", &["lr", "momentum"]),
+        ("RMSProp", &["lr", "rho"]),
+        ("Adam", &["lr", "beta1", "beta2"]),
+    ]);
+    // The acronyms SGD and RMSProp are not split into words
+    if let Some(Value::String(tag)) = optimizer.get_mut("type") {
+        *tag = tag.replace('_', "");
+    }
+}
+
+/// Turns an externally tagged enum, `"Variant"` or `{"Variant": data}`, into one tagged
+/// with `type`. `fields` names the values of tuple and newtype variants by variant name.
+fn retag(value: &mut Value, fields: &[(&str, &[&str])]) {
+    let (variant, data) = match value.take() {
+        Value::String(variant) => (variant, Value::Null),
+        Value::Object(map) if map.len() == 1 => map.into_iter().next().unwrap(),
+        other => {
+            *value = other;
+            return;
+        }
+    };
+    let mut tagged = match data {
+        Value::Object(map) => map,
+        Value::Null => Map::new(),
+        data => {
+            let names = fields.iter().find(|(name, _)| *name == variant).map_or(&[][..], |(_, names)| *names);
+            let values = match data {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+            names.iter().map(|name| name.to_string()).zip(values).collect()
+        }
+    };
+    tagged.insert(String::from("type"), Value::from(snake_case(&variant)));
+    *value = Value::Object(tagged);
+}
+
+/// Renames a unit variant written as `"Variant"` to snake_case
+fn rename_variant(value: &mut Value) {
+    if let Value::String(variant) = value {
+        *variant = snake_case(variant);
+    }
+}
+
+/// Converts a PascalCase variant name to snake_case, as `rename_all = "snake_case"` does
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Converts a model file to the given format without deserializing it.
+/// Converting between JSON and `ModelFormat::Binary` is lossless.
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q, format: ModelFormat) -> Result<(), String> {
+    let value = read_value(src)?;
+    write_value(&value, dst, format)
+}
+
+/// JSON formatter that writes numbers holding an f32 in their shortest f32 form.
+/// `serde_json::Value` stores every float as an f64, so without this a model
+/// written through a `Value` would print each weight with f64 digits.
+struct F32Formatter;
+
+impl Formatter for F32Formatter {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        if value as f32 as f64 == value {
+            CompactFormatter.write_f32(writer, value as f32)
+        } else {
+            CompactFormatter.write_f64(writer, value)
+        }
+    }
+}
+
+/// Encodes a serialized model as:
+/// - the magic bytes `OXIN`
+/// - the container version as a little-endian u32
+/// - the tensor data type as a little-endian u32 (0 for f32, 1 for f16)
+/// - the header length in bytes as a little-endian u64
+/// - the header, a JSON object holding the model's architecture and settings,
+///   with every f32 tensor replaced by a reference into the tensor list
+/// - the raw little-endian data of every tensor, in order
+pub fn encode_binary(value: &Value, half_precision: bool) -> Result<Vec<u8>, String> {
+    let mut tensors: Vec<Vec<f32>> = vec![];
+    let skeleton = extract_tensors(value, &mut tensors);
+    let tensor_lengths: Vec<usize> = tensors.iter().map(|t| t.len()).collect();
+    let header = json!({
+        "tensors": tensor_lengths,
+        "model": skeleton,
+    });
+    let header = serde_json::to_vec(&header).map_err(|e| e.to_string())?;
+
+    let element_size = if half_precision { 2 } else { 4 };
+    let data_len: usize = tensor_lengths.iter().sum::<usize>() * element_size;
+    let mut bytes = Vec::with_capacity(20 + header.len() + data_len);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&BINARY_FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(half_precision as u32).to_le_bytes());
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&header);
+    for tensor in &tensors {
+        for &x in tensor {
+            if half_precision {
+                bytes.extend_from_slice(&f16::from_f32(x).to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Decodes a binary model back into the JSON value it was encoded from
+pub fn decode_binary(bytes: &[u8]) -> Result<Value, String> {
+    if bytes.len() < 20 || !bytes.starts_with(MAGIC) {
+        return Err(String::from("Not an oxi_net binary model file"));
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    if version > BINARY_FORMAT_VERSION {
+        return Err(format!("Binary model format version {} is newer than the supported version {}", version, BINARY_FORMAT_VERSION));
+    }
+    let element_size = match u32::from_le_bytes(bytes[8..12].try_into().unwrap()) {
+        0 => 4,
+        1 => 2,
+        dtype => return Err(format!("Unknown tensor data type {}", dtype)),
+    };
+    let header_len = u64::from_le_bytes(bytes[12..20].try_into().unwrap()) as usize;
+    let header_end = 20usize.checked_add(header_len).filter(|&end| end <= bytes.len())
+        .ok_or_else(|| String::from("Truncated model header"))?;
+    let mut header: Value = serde_json::from_slice(&bytes[20..header_end]).map_err(|e| e.to_string())?;
+
+    let tensor_lengths: Vec<usize> = serde_json::from_value(header["tensors"].take()).map_err(|e| e.to_string())?;
+    let mut tensors: Vec<Vec<f32>> = Vec::with_capacity(tensor_lengths.len());
+    let mut offset = header_end;
+    for len in tensor_lengths {
+        let end = len.checked_mul(element_size).and_then(|n| n.checked_add(offset)).filter(|&end| end <= bytes.len())
+            .ok_or_else(|| String::from("Truncated tensor data"))?;
+        let tensor: Vec<f32> = bytes[offset..end].chunks_exact(element_size).map(|b| {
+            if element_size == 2 {
+                f16::from_le_bytes([b[0], b[1]]).to_f32()
+            } else {
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }
+        }).collect();
+        tensors.push(tensor);
+        offset = end;
+    }
+    if offset != bytes.len() {
+        return Err(String::from("Unexpected data after the last tensor"));
+    }
+
+    insert_tensors(header["model"].take(), &mut tensors)
+}
+
+/// Returns `value` with the data of every serialized ndarray of floats moved
+/// into `tensors` and replaced by the index of the tensor
+fn extract_tensors(value: &Value, tensors: &mut Vec<Vec<f32>>) -> Value {
+    match value {
+        Value::Object(map) => {
+            if let Some(data) = float_array_data(map) {
+                let mut skeleton: Map<String, Value> = map.iter()
+                    .filter(|(k, _)| *k != "data")
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                skeleton.insert(String::from("data"), json!({ "tensor": tensors.len() }));
+                tensors.push(data);
+                return Value::Object(skeleton);
+            }
+            Value::Object(map.iter().map(|(k, v)| (k.clone(), extract_tensors(v, tensors))).collect())
+        }
+        Value::Array(values) => Value::Array(values.iter().map(|v| extract_tensors(v, tensors)).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Returns the elements of an ndarray serialized by serde if every element is a float
+fn float_array_data(map: &Map<String, Value>) -> Option<Vec<f32>> {
+    if map.len() != 3 || !map.contains_key("v") || !map.contains_key("dim") {
+        return None;
+    }
+    let data = map.get("data")?.as_array()?;
+    if data.is_empty() || !data.iter().all(|x| x.is_f64()) {
+        return None;
+    }
+
+    Some(data.iter().map(|x| x.as_f64().unwrap() as f32).collect())
+}
+
+/// Inverse of `extract_tensors`
+fn insert_tensors(value: Value, tensors: &mut [Vec<f32>]) -> Result<Value, String> {
+    match value {
+        Value::Object(mut map) => {
+            let tensor_idx = map.get("data").and_then(|d| d.get("tensor")).and_then(|t| t.as_u64());
+            if let Some(idx) = tensor_idx {
+                let tensor = tensors.get_mut(idx as usize).ok_or_else(|| format!("Missing tensor {}", idx))?;
+                let data = std::mem::take(tensor).into_iter().map(Value::from).collect();
+                map.insert(String::from("data"), Value::Array(data));
+                return Ok(Value::Object(map));
+            }
+            let map = map.into_iter()
+                .map(|(k, v)| Ok((k, insert_tensors(v, tensors)?)))
+                .collect::<Result<Map<String, Value>, String>>()?;
+            Ok(Value::Object(map))
+        }
+        Value::Array(values) => {
+            let values = values.into_iter()
+                .map(|v| insert_tensors(v, tensors))
+                .collect::<Result<Vec<Value>, String>>()?;
+            Ok(Value::Array(values))
+        }
+        value => Ok(value),
+    }
+}
@@ -5,12 +5,12 @@ use std::fmt::{Debug, Formatter};
 /// Defines a `MaxPoolingLayer` structure.
 #[derive(Serialize, Deserialize)]
 pub struct MxplLayer {
-    input_size: (usize, usize, usize),
-    kernel_size: usize,
+    pub input_size: (usize, usize, usize),
+    pub kernel_size: usize,
     pub output_size: (usize, usize, usize),
     #[serde(skip)]
     highest_indices: Array4<usize>,
-    stride: usize,
+    pub stride: usize,
 }
 
 impl Debug for MxplLayer {
@@ -33,14 +33,17 @@ impl MxplLayer {
         kernel_size: usize,
         stride: usize,
     ) -> MxplLayer {
-        let output_width: usize = ((input_size.0 - kernel_size) / stride) + 1;
-        let output_size = (output_width, output_width, input_size.2);
+        let output_size = (
+            (input_size.0 - kernel_size) / stride + 1,
+            (input_size.1 - kernel_size) / stride + 1,
+            input_size.2,
+        );
         let layer: MxplLayer = MxplLayer {
             input_size,
             kernel_size,
             output_size,
             stride,
-            highest_indices: Array4::<usize>::zeros((output_width, output_width, input_size.2, 2)),
+            highest_indices: Array4::<usize>::zeros((output_size.0, output_size.1, input_size.2, 2)),
         };
 
         layer
@@ -50,6 +53,27 @@ impl MxplLayer {
         self.highest_indices = Array4::<usize>::zeros((self.output_size.0, self.output_size.1, self.input_size.2, 2));
     }
     
+    /// Forward pass that does not record the indices needed for back propagation
+    pub fn infer(&self, input: &Array3<f32>) -> Array3<f32> {
+        let mut output: Array3<f32> = Array3::<f32>::from_elem(self.output_size, -1.0);
+
+        for f in 0..self.output_size.2 {
+            for y in 0..self.output_size.1 {
+                for x in 0..self.output_size.0 {
+                    for ky in 0..self.kernel_size {
+                        for kx in 0..self.kernel_size {
+                            let value: f32 = input[[x * self.stride + kx, y * self.stride + ky, f]];
+                            if value > output[[x, y, f]] {
+                                output[[x, y, f]] = value;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        output
+    }
+
     pub fn forward_propagate(&mut self, input: Array3<f32>) -> Array3<f32> {
         let mut output: Array3<f32> = Array3::<f32>::zeros(self.output_size);
         
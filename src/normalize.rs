@@ -0,0 +1,97 @@
+use ndarray::{Array3, Axis};
+use serde::{Serialize, Deserialize};
+use crate::dataset::Dataset;
+
+/// Per-channel statistics subtracted from and divided into every input,
+/// as `(x - mean[c]) / std[c]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Normalization {
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>,
+}
+
+/// Where the normalization of a network comes from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Normalize {
+    /// Inputs are used as they are
+    #[default]
+    None,
+    /// Computed over the training set when training starts, unless already set
+    FromTrainingSet,
+    /// Fixed statistics, such as the published ones of a dataset
+    Fixed { mean: Vec<f32>, std: Vec<f32> },
+}
+
+impl Normalize {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Normalize::Fixed { mean, std } => Normalization::new(mean.clone(), std.clone()).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Normalization {
+    pub fn new(mean: Vec<f32>, std: Vec<f32>) -> Result<Normalization, String> {
+        if mean.is_empty() || mean.len() != std.len() {
+            return Err(format!(
+                "Normalization needs one mean and one std per channel, got {} and {}",
+                mean.len(), std.len()
+            ));
+        }
+        if let Some(std) = std.iter().find(|&&s| s.is_nan() || s <= 0.0) {
+            return Err(format!("Normalization std must be positive, got {}", std));
+        }
+
+        Ok(Normalization { mean, std })
+    }
+
+    /// Mean and standard deviation of every channel over all samples of `data`.
+    /// Channels that never vary get a std of 1, so they are only centred.
+    pub fn from_dataset<D: Dataset + ?Sized>(data: &D) -> Result<Normalization, String> {
+        if data.is_empty() {
+            return Err(String::from("Cannot compute normalization over an empty dataset"));
+        }
+        let channels = data.get(0).0.dim().2;
+        // Sums in f64, as a dataset easily has more values than f32 counts exactly
+        let mut sums = vec![0.0f64; channels];
+        let mut squares = vec![0.0f64; channels];
+        let mut count = 0usize;
+        for i in 0..data.len() {
+            let (image, _) = data.get(i);
+            if image.dim().2 != channels {
+                return Err(format!("Sample {} has {} channels but sample 0 has {}", i, image.dim().2, channels));
+            }
+            for (c, channel) in image.axis_iter(Axis(2)).enumerate() {
+                for &x in channel.iter() {
+                    sums[c] += x as f64;
+                    squares[c] += (x as f64).powi(2);
+                }
+            }
+            count += image.dim().0 * image.dim().1;
+        }
+
+        let mean: Vec<f64> = sums.iter().map(|s| s / count as f64).collect();
+        let std = squares.iter().zip(mean.iter())
+            .map(|(sq, m)| (sq / count as f64 - m * m).max(0.0).sqrt() as f32)
+            .map(|s| if s > f32::EPSILON { s } else { 1.0 })
+            .collect();
+
+        Normalization::new(mean.iter().map(|&m| m as f32).collect(), std)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn apply(&self, mut image: Array3<f32>) -> Array3<f32> {
+        assert_eq!(image.dim().2, self.channels(), "Image channels do not match the normalization");
+        for (c, mut channel) in image.axis_iter_mut(Axis(2)).enumerate() {
+            let (mean, std) = (self.mean[c], self.std[c]);
+            channel.mapv_inplace(|x| (x - mean) / std);
+        }
+        image
+    }
+}
@@ -0,0 +1,350 @@
+use ndarray::{Array1, Array2, Array4, ArrayD, Ix1, Ix2, Ix4, IxDyn};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+use crate::layer::Layer;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// Parameter tensor of a layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    /// Kernels of a `ConvLayer` or weights of a `DenseLayer`
+    Weights,
+    /// Biases of a `DenseLayer`
+    Biases,
+}
+
+/// Memory layout of imported parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightLayout {
+    /// oxi_net's own layout: kernels are [filter, ky, kx, channel], dense weights
+    /// are [output, input] and inputs are flattened from [rows, cols, channels]
+    OxiNet,
+    /// PyTorch layout: kernels are [filter, channel, ky, kx], dense weights are
+    /// [output, input] and inputs are flattened from [channels, rows, cols]
+    PyTorch,
+    /// Keras channels-last layout: kernels are [ky, kx, channel, filter], dense
+    /// weights are [input, output] and inputs are flattened from [rows, cols, channels]
+    Keras,
+}
+
+/// Default array names, `{layer}.weight` and `{layer}.bias`, for every parameter of the network
+pub fn default_param_names(layers: &[Layer]) -> Vec<(String, usize, Param)> {
+    let mut names = vec![];
+    for (i, layer) in layers.iter().enumerate() {
+        match layer {
+            Layer::Conv(_) => names.push((format!("{}.weight", i), i, Param::Weights)),
+            Layer::Mxpl(_) => {}
+            Layer::Dense(_) => {
+                names.push((format!("{}.weight", i), i, Param::Weights));
+                names.push((format!("{}.bias", i), i, Param::Biases));
+            }
+        }
+    }
+
+    names
+}
+
+/// Fills layer parameters from named arrays. `mapping` lists the array name,
+/// layer index and parameter of every array to import. Every array in
+/// `arrays` must be used, so parameters the network cannot represent, such
+/// as convolution biases, are not silently dropped. Nothing is changed
+/// unless every array is valid.
+pub fn import_weights(
+    layers: &mut [Layer],
+    arrays: &HashMap<String, ArrayD<f32>>,
+    mapping: &[(String, usize, Param)],
+    layout: WeightLayout,
+) -> Result<(), String> {
+    let mut errors = vec![];
+    let mut converted = vec![];
+    let mut used = HashSet::new();
+    for (name, layer, param) in mapping {
+        match arrays.get(name) {
+            Some(array) => {
+                used.insert(name);
+                match convert_param(layers, *layer, *param, array.clone(), layout) {
+                    Ok(value) => converted.push((*layer, value)),
+                    Err(e) => errors.push(format!("{}: {}", name, e)),
+                }
+            }
+            None => errors.push(format!("Missing array {} for layer {}", name, layer)),
+        }
+    }
+    let mut unused: Vec<&String> = arrays.keys().filter(|name| !used.contains(name)).collect();
+    unused.sort();
+    for name in unused {
+        errors.push(format!("Array {} does not match any layer parameter", name));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    for (layer, value) in converted {
+        apply_param(&mut layers[layer], value);
+    }
+
+    Ok(())
+}
+
+/// Replaces a single layer parameter, converting it from `layout` and
+/// checking its shape against the layer
+pub fn set_param(layers: &mut [Layer], layer: usize, param: Param, array: ArrayD<f32>, layout: WeightLayout) -> Result<(), String> {
+    let value = convert_param(layers, layer, param, array, layout)?;
+    apply_param(&mut layers[layer], value);
+
+    Ok(())
+}
+
+/// A parameter converted to oxi_net's layout and checked against its layer
+enum ParamValue {
+    Kernels(Array4<f32>),
+    Weights(Array2<f32>),
+    Biases(Array1<f32>),
+}
+
+fn convert_param(layers: &[Layer], layer: usize, param: Param, array: ArrayD<f32>, layout: WeightLayout) -> Result<ParamValue, String> {
+    let target = layers.get(layer).ok_or_else(|| format!("Layer {} does not exist, the network has {} layers", layer, layers.len()))?;
+    match (target, param) {
+        (Layer::Conv(conv_layer), Param::Weights) => {
+            let kernels = array.into_dimensionality::<Ix4>().map_err(|_| String::from("Kernels must have 4 dimensions"))?;
+            let kernels = match layout {
+                WeightLayout::OxiNet => kernels,
+                WeightLayout::PyTorch => kernels.permuted_axes([0, 2, 3, 1]),
+                WeightLayout::Keras => kernels.permuted_axes([3, 0, 1, 2]),
+            };
+            check_shape(layer, "kernels", kernels.shape(), conv_layer.kernels.shape())?;
+            Ok(ParamValue::Kernels(kernels.as_standard_layout().to_owned()))
+        }
+        (Layer::Dense(dense_layer), Param::Weights) => {
+            let weights = array.into_dimensionality::<Ix2>().map_err(|_| String::from("Dense weights must have 2 dimensions"))?;
+            let weights = match layout {
+                WeightLayout::Keras => weights.reversed_axes(),
+                _ => weights,
+            };
+            check_shape(layer, "weights", weights.shape(), dense_layer.weights.shape())?;
+            Ok(ParamValue::Weights(match layout {
+                WeightLayout::PyTorch => permute_columns(&weights, dense_layer.transition_shape, false),
+                _ => weights.as_standard_layout().to_owned(),
+            }))
+        }
+        (Layer::Dense(dense_layer), Param::Biases) => {
+            let biases = array.into_dimensionality::<Ix1>().map_err(|_| String::from("Biases must have 1 dimension"))?;
+            check_shape(layer, "biases", biases.shape(), dense_layer.biases.shape())?;
+            Ok(ParamValue::Biases(biases))
+        }
+        (Layer::Conv(_), Param::Biases) => Err(format!("Layer {} is a convolutional layer, which has no biases", layer)),
+        (Layer::Mxpl(_), _) => Err(format!("Layer {} is a max pooling layer, which has no parameters", layer)),
+    }
+}
+
+fn apply_param(layer: &mut Layer, value: ParamValue) {
+    match (layer, value) {
+        (Layer::Conv(conv_layer), ParamValue::Kernels(kernels)) => conv_layer.kernels = kernels,
+        (Layer::Dense(dense_layer), ParamValue::Weights(weights)) => dense_layer.weights = weights,
+        (Layer::Dense(dense_layer), ParamValue::Biases(biases)) => dense_layer.biases = biases,
+        _ => unreachable!(),
+    }
+}
+
+/// Every layer parameter converted to `layout`, named as in `default_param_names`
+pub fn export_weights(layers: &[Layer], layout: WeightLayout) -> Vec<(String, ArrayD<f32>)> {
+    default_param_names(layers).into_iter().map(|(name, layer, param)| {
+        let array = match (&layers[layer], param) {
+            (Layer::Conv(conv_layer), _) => match layout {
+                WeightLayout::OxiNet => conv_layer.kernels.clone().into_dyn(),
+                WeightLayout::PyTorch => conv_layer.kernels.view().permuted_axes([0, 3, 1, 2]).as_standard_layout().into_owned().into_dyn(),
+                WeightLayout::Keras => conv_layer.kernels.view().permuted_axes([1, 2, 3, 0]).as_standard_layout().into_owned().into_dyn(),
+            },
+            (Layer::Dense(dense_layer), Param::Weights) => match layout {
+                WeightLayout::OxiNet => dense_layer.weights.clone().into_dyn(),
+                WeightLayout::PyTorch => permute_columns(&dense_layer.weights, dense_layer.transition_shape, true).into_dyn(),
+                WeightLayout::Keras => dense_layer.weights.t().as_standard_layout().into_owned().into_dyn(),
+            },
+            (Layer::Dense(dense_layer), Param::Biases) => dense_layer.biases.clone().into_dyn(),
+            (Layer::Mxpl(_), _) => unreachable!(),
+        };
+        (name, array)
+    }).collect()
+}
+
+fn check_shape(layer: usize, name: &str, actual: &[usize], expected: &[usize]) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("Layer {} {} should have shape {:?} but the array has shape {:?}", layer, name, expected, actual))
+    }
+}
+
+/// Reorders dense weight columns between inputs flattened from [rows, cols, channels]
+/// (oxi_net) and from [channels, rows, cols] (PyTorch). With `to_channels_first`
+/// set, oxi_net columns are converted to PyTorch columns, otherwise the reverse.
+pub fn permute_columns(weights: &Array2<f32>, transition_shape: (usize, usize, usize), to_channels_first: bool) -> Array2<f32> {
+    let (rows, cols, channels) = transition_shape;
+    let mut permuted = Array2::<f32>::zeros(weights.dim());
+    for y in 0..rows {
+        for x in 0..cols {
+            for c in 0..channels {
+                let channels_last = (y * cols + x) * channels + c;
+                let channels_first = (c * rows + y) * cols + x;
+                if to_channels_first {
+                    permuted.column_mut(channels_first).assign(&weights.column(channels_last));
+                } else {
+                    permuted.column_mut(channels_last).assign(&weights.column(channels_first));
+                }
+            }
+        }
+    }
+
+    permuted
+}
+
+/// Reads a `.npy` file of floats as an f32 array
+pub fn read_npy<P: AsRef<Path>>(path: P) -> Result<ArrayD<f32>, String> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_npy(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads every array in a `.npz` archive, keyed by name without the `.npy` extension
+pub fn read_npz<P: AsRef<Path>>(path: P) -> Result<HashMap<String, ArrayD<f32>>, String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut arrays = HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = entry.name().trim_end_matches(".npy").to_string();
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        let array = parse_npy(&bytes).map_err(|e| format!("{}: {}: {}", path.display(), name, e))?;
+        arrays.insert(name, array);
+    }
+
+    Ok(arrays)
+}
+
+/// Writes an f32 array as a `.npy` file
+pub fn write_npy<P: AsRef<Path>>(path: P, array: &ArrayD<f32>) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, encode_npy(array)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Writes f32 arrays as an uncompressed `.npz` archive, as `numpy.savez` does
+pub fn write_npz<P: AsRef<Path>>(path: P, arrays: &[(String, ArrayD<f32>)]) -> Result<(), String> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut archive = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, array) in arrays {
+        archive.start_file(format!("{}.npy", name), options).map_err(|e| e.to_string())?;
+        archive.write_all(&encode_npy(array)).map_err(|e| e.to_string())?;
+    }
+    archive.finish().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Parses the contents of a `.npy` file holding f4 or f8 values
+pub fn parse_npy(bytes: &[u8]) -> Result<ArrayD<f32>, String> {
+    if bytes.len() < 10 || !bytes.starts_with(MAGIC) {
+        return Err(String::from("Not a .npy file"));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12),
+        version => return Err(format!("Unsupported .npy version {}", version)),
+    };
+    let data_start = header_start + header_len;
+    if bytes.len() < data_start {
+        return Err(String::from("Truncated .npy header"));
+    }
+    let header = std::str::from_utf8(&bytes[header_start..data_start]).map_err(|e| e.to_string())?;
+
+    let descr = header_value(header, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+    let fortran_order = match header_value(header, "fortran_order")? {
+        "True" => true,
+        "False" => false,
+        v => return Err(format!("Invalid fortran_order {}", v)),
+    };
+    let shape: Vec<usize> = header_value(header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse::<usize>().map_err(|e| format!("Invalid shape: {}", e)))
+        .collect::<Result<_, _>>()?;
+
+    let (little_endian, size) = match descr {
+        "<f4" | "=f4" => (true, 4),
+        ">f4" => (false, 4),
+        "<f8" | "=f8" => (true, 8),
+        ">f8" => (false, 8),
+        _ => return Err(format!("Unsupported dtype {}, only float32 and float64 arrays can be loaded", descr)),
+    };
+    let expected = shape.iter()
+        .try_fold(size, |len: usize, &d| len.checked_mul(d))
+        .ok_or_else(|| format!("Array of shape {:?} is too large", shape))?;
+    let data = &bytes[data_start..];
+    if data.len() != expected {
+        return Err(format!("Expected {} bytes of data for shape {:?}, found {}", expected, shape, data.len()));
+    }
+    let values: Vec<f32> = data.chunks_exact(size).map(|b| match (size, little_endian) {
+        (4, true) => f32::from_le_bytes(b.try_into().unwrap()),
+        (4, false) => f32::from_be_bytes(b.try_into().unwrap()),
+        (_, true) => f64::from_le_bytes(b.try_into().unwrap()) as f32,
+        (_, false) => f64::from_be_bytes(b.try_into().unwrap()) as f32,
+    }).collect();
+
+    if fortran_order {
+        // Column-major data is the row-major data of the reversed shape, transposed
+        let reversed: Vec<usize> = shape.iter().rev().cloned().collect();
+        let array = ArrayD::from_shape_vec(IxDyn(&reversed), values).map_err(|e| e.to_string())?;
+        Ok(array.reversed_axes().as_standard_layout().to_owned())
+    } else {
+        ArrayD::from_shape_vec(IxDyn(&shape), values).map_err(|e| e.to_string())
+    }
+}
+
+/// Encodes an array as a version 1.0 `.npy` file of little-endian f4 values
+pub fn encode_npy(array: &ArrayD<f32>) -> Vec<u8> {
+    let shape = match array.shape() {
+        [d] => format!("({},)", d),
+        dims => format!("({})", dims.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+    // The header is padded with spaces and ends in a newline so the data is 64-byte aligned
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 4 + header.len() + array.len() * 4);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for x in array.iter() {
+        bytes.extend_from_slice(&x.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Returns the raw text of a value in the Python dict literal of a `.npy` header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let key_start = header.find(&format!("'{}'", key))
+        .ok_or_else(|| format!("Missing {} in .npy header", key))?;
+    let rest = header[key_start + key.len() + 2..].trim_start().trim_start_matches(':').trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    }.ok_or_else(|| format!("Invalid {} in .npy header", key))?;
+
+    Ok(rest[..end].trim())
+}
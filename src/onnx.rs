@@ -0,0 +1,271 @@
+use std::path::Path;
+use crate::activation::Activation;
+use crate::layer::Layer;
+use crate::normalize::Normalization;
+use crate::model_file::write_atomic;
+use crate::numpy::permute_columns;
+
+/// ONNX IR version of exported models
+const IR_VERSION: u64 = 7;
+/// Version of the default ONNX operator set used by exported models
+const OPSET_VERSION: u64 = 13;
+/// `TensorProto.DataType.FLOAT`
+const FLOAT: u64 = 1;
+
+// `AttributeProto.AttributeType` values
+const ATTRIBUTE_INT: u64 = 2;
+const ATTRIBUTE_INTS: u64 = 7;
+
+/// Writes the network as an ONNX model.
+/// The graph input `input` takes a [1, channels, rows, cols] tensor and the
+/// graph output `output` is the [1, classes] output of the final layer.
+/// - `ConvLayer` becomes Conv followed by Relu
+/// - `MxplLayer` becomes MaxPool, followed by a Max with -1 unless its input
+///   comes from a Relu, as oxi_net max pooling never outputs less than -1
+/// - `DenseLayer` becomes Gemm followed by its activation, preceded by a
+///   Flatten when it follows a convolutional or max pooling layer
+/// - Dropout becomes Identity, as it is only applied during training
+/// - A normalization becomes Sub and Div by [1, channels, 1, 1] constants
+pub fn export_onnx<P: AsRef<Path>>(layers: &[Layer], input_shape: (usize, usize, usize), normalization: Option<&Normalization>, path: P) -> Result<(), String> {
+    write_atomic(path, &onnx_model(layers, input_shape, normalization)?)
+}
+
+/// Encodes the network as a serialized ONNX `ModelProto`
+pub fn onnx_model(layers: &[Layer], input_shape: (usize, usize, usize), normalization: Option<&Normalization>) -> Result<Vec<u8>, String> {
+    let output_size = match layers.last() {
+        Some(Layer::Dense(dense_layer)) => dense_layer.output_size,
+        _ => return Err(String::from("Last layer is not a DenseLayer")),
+    };
+
+    let mut graph = GraphBuilder::default();
+    let mut current = String::from("input");
+    // Whether `current` is still a [1, channels, rows, cols] tensor
+    let mut spatial = true;
+    // Whether `current` has passed through a Relu, so that it cannot be negative
+    let mut non_negative = false;
+
+    if let Some(normalization) = normalization {
+        let shape = [1, normalization.channels(), 1, 1];
+        graph.initializer("input_mean", &shape, &normalization.mean);
+        graph.initializer("input_std", &shape, &normalization.std);
+        let centred = graph.node("Sub", "input_centre", &[&current, "input_mean"], vec![]);
+        current = graph.node("Div", "input_scale", &[&centred, "input_std"], vec![]);
+    }
+
+    for (i, layer) in layers.iter().enumerate() {
+        match layer {
+            Layer::Conv(conv_layer) => {
+                let (_, _, channels) = conv_layer.input_size;
+                let k = conv_layer.kernel_size;
+                // oxi_net kernels are [filter, ky, kx, channel], ONNX expects [filter, channel, ky, kx]
+                let kernels = &conv_layer.kernels;
+                let mut weights = Vec::with_capacity(kernels.len());
+                for f in 0..conv_layer.num_filters {
+                    for c in 0..channels {
+                        for ky in 0..k {
+                            for kx in 0..k {
+                                weights.push(kernels[[f, ky, kx, c]]);
+                            }
+                        }
+                    }
+                }
+                let weight_name = format!("conv{}_kernels", i);
+                graph.initializer(&weight_name, &[conv_layer.num_filters, channels, k, k], &weights);
+
+                let conv = format!("conv{}", i);
+                let stride = conv_layer.stride as i64;
+                graph.node("Conv", &conv, &[&current, &weight_name], vec![
+                    ints_attribute("kernel_shape", &[k as i64, k as i64]),
+                    ints_attribute("strides", &[stride, stride]),
+                ]);
+                current = graph.node("Relu", &format!("conv{}_relu", i), &[&conv], vec![]);
+                non_negative = true;
+            }
+            Layer::Mxpl(mxpl_layer) => {
+                let k = mxpl_layer.kernel_size as i64;
+                let stride = mxpl_layer.stride as i64;
+                current = graph.node("MaxPool", &format!("mxpl{}", i), &[&current], vec![
+                    ints_attribute("kernel_shape", &[k, k]),
+                    ints_attribute("strides", &[stride, stride]),
+                ]);
+                if !non_negative {
+                    // Clamping the pooled maximum is the same as clamping every input first
+                    let floor = format!("mxpl{}_floor", i);
+                    graph.initializer(&floor, &[1], &[-1.0]);
+                    current = graph.node("Max", &format!("mxpl{}_clamp", i), &[&current, &floor], vec![]);
+                }
+            }
+            Layer::Dense(dense_layer) => {
+                if spatial {
+                    current = graph.node("Flatten", &format!("dense{}_flatten", i), &[&current], vec![
+                        int_attribute("axis", 1),
+                    ]);
+                    spatial = false;
+                }
+
+                // oxi_net flattens [rows, cols, channels] while Flatten produces
+                // [channels, rows, cols], so the weight columns are permuted to match
+                let gemm_weights = permute_columns(&dense_layer.weights, dense_layer.transition_shape, true);
+                let weight_name = format!("dense{}_weights", i);
+                let bias_name = format!("dense{}_biases", i);
+                graph.initializer(&weight_name, &[dense_layer.output_size, dense_layer.input_size], gemm_weights.as_slice().unwrap());
+                graph.initializer(&bias_name, &[dense_layer.output_size], &dense_layer.biases.to_vec());
+
+                let gemm = format!("dense{}", i);
+                graph.node("Gemm", &gemm, &[&current, &weight_name, &bias_name], vec![
+                    int_attribute("transB", 1),
+                ]);
+                current = match dense_layer.activation {
+                    Activation::Relu => graph.node("Relu", &format!("dense{}_relu", i), &[&gemm], vec![]),
+                    Activation::Sigmoid => graph.node("Sigmoid", &format!("dense{}_sigmoid", i), &[&gemm], vec![]),
+                    Activation::Softmax => graph.node("Softmax", &format!("dense{}_softmax", i), &[&gemm], vec![
+                        int_attribute("axis", 1),
+                    ]),
+                };
+                if dense_layer.dropout.is_some() {
+                    current = graph.node("Identity", &format!("dense{}_dropout", i), &[&current], vec![]);
+                }
+            }
+        }
+    }
+    graph.node("Identity", "output", &[&current], vec![]);
+
+    let (rows, cols, channels) = input_shape;
+    let mut graph_proto = Message::default();
+    for node in &graph.nodes {
+        graph_proto.message(1, node);
+    }
+    graph_proto.string(2, "oxi_net");
+    for initializer in &graph.initializers {
+        graph_proto.message(5, initializer);
+    }
+    graph_proto.message(11, &value_info("input", &[1, channels, rows, cols]));
+    graph_proto.message(12, &value_info("output", &[1, output_size]));
+
+    let mut opset = Message::default();
+    opset.string(1, "");
+    opset.varint(2, OPSET_VERSION);
+
+    let mut model = Message::default();
+    model.varint(1, IR_VERSION);
+    model.string(2, "oxi_net");
+    model.string(3, env!("CARGO_PKG_VERSION"));
+    model.message(7, &graph_proto);
+    model.message(8, &opset);
+
+    Ok(model.bytes)
+}
+
+#[derive(Default)]
+struct GraphBuilder {
+    nodes: Vec<Message>,
+    initializers: Vec<Message>,
+}
+
+impl GraphBuilder {
+    /// Adds a node with a single output named `name`, and returns the name
+    fn node(&mut self, op_type: &str, name: &str, inputs: &[&str], attributes: Vec<Message>) -> String {
+        let mut node = Message::default();
+        for input in inputs {
+            node.string(1, input);
+        }
+        node.string(2, name);
+        node.string(3, name);
+        node.string(4, op_type);
+        for attribute in &attributes {
+            node.message(5, attribute);
+        }
+        self.nodes.push(node);
+
+        String::from(name)
+    }
+
+    fn initializer(&mut self, name: &str, dims: &[usize], data: &[f32]) {
+        let mut tensor = Message::default();
+        for &dim in dims {
+            tensor.varint(1, dim as u64);
+        }
+        tensor.varint(2, FLOAT);
+        tensor.string(8, name);
+        let raw_data: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+        tensor.bytes(9, &raw_data);
+        self.initializers.push(tensor);
+    }
+}
+
+fn int_attribute(name: &str, value: i64) -> Message {
+    let mut attribute = Message::default();
+    attribute.string(1, name);
+    attribute.varint(3, value as u64);
+    attribute.varint(20, ATTRIBUTE_INT);
+    attribute
+}
+
+fn ints_attribute(name: &str, values: &[i64]) -> Message {
+    let mut attribute = Message::default();
+    attribute.string(1, name);
+    for &value in values {
+        attribute.varint(8, value as u64);
+    }
+    attribute.varint(20, ATTRIBUTE_INTS);
+    attribute
+}
+
+/// `ValueInfoProto` for a float tensor with a fixed shape
+fn value_info(name: &str, dims: &[usize]) -> Message {
+    let mut shape = Message::default();
+    for &dim in dims {
+        let mut dimension = Message::default();
+        dimension.varint(1, dim as u64);
+        shape.message(1, &dimension);
+    }
+    let mut tensor_type = Message::default();
+    tensor_type.varint(1, FLOAT);
+    tensor_type.message(2, &shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+
+    let mut info = Message::default();
+    info.string(1, name);
+    info.message(2, &type_proto);
+    info
+}
+
+/// Minimal protobuf encoder, supporting only the wire types ONNX export needs
+#[derive(Default)]
+struct Message {
+    bytes: Vec<u8>,
+}
+
+impl Message {
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.raw_varint(field << 3 | wire_type);
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn varint(&mut self, field: u64, value: u64) {
+        self.key(field, 0);
+        self.raw_varint(value);
+    }
+
+    fn bytes(&mut self, field: u64, value: &[u8]) {
+        self.key(field, 2);
+        self.raw_varint(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u64, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u64, value: &Message) {
+        self.bytes(field, &value.bytes);
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Serialize, Deserialize};
 use std::fmt::{Debug, Formatter};
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(from = "OptimizerSpec", into = "OptimizerSpec")]
 pub enum OptimizerAlg {
     SGD(f32),
     Momentum(f32, f32),
@@ -10,26 +11,141 @@ pub enum OptimizerAlg {
     Adam(f32, f32, f32),
 }
 
+/// How an `OptimizerAlg` is written in config and model files, with named parameters
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum OptimizerSpec {
+    Sgd { lr: f32 },
+    Momentum { lr: f32, momentum: f32 },
+    #[serde(rename = "rmsprop")]
+    RmsProp { lr: f32, rho: f32 },
+    Adam { lr: f32, beta1: f32, beta2: f32 },
+}
+
+impl From<OptimizerSpec> for OptimizerAlg {
+    fn from(spec: OptimizerSpec) -> OptimizerAlg {
+        match spec {
+            OptimizerSpec::Sgd { lr } => OptimizerAlg::SGD(lr),
+            OptimizerSpec::Momentum { lr, momentum } => OptimizerAlg::Momentum(lr, momentum),
+            OptimizerSpec::RmsProp { lr, rho } => OptimizerAlg::RMSProp(lr, rho),
+            OptimizerSpec::Adam { lr, beta1, beta2 } => OptimizerAlg::Adam(lr, beta1, beta2),
+        }
+    }
+}
+
+impl From<OptimizerAlg> for OptimizerSpec {
+    fn from(alg: OptimizerAlg) -> OptimizerSpec {
+        match alg {
+            OptimizerAlg::SGD(lr) => OptimizerSpec::Sgd { lr },
+            OptimizerAlg::Momentum(lr, momentum) => OptimizerSpec::Momentum { lr, momentum },
+            OptimizerAlg::RMSProp(lr, rho) => OptimizerSpec::RmsProp { lr, rho },
+            OptimizerAlg::Adam(lr, beta1, beta2) => OptimizerSpec::Adam { lr, beta1, beta2 },
+        }
+    }
+}
+
+impl OptimizerAlg {
+    pub fn learning_rate(&self) -> f32 {
+        match *self {
+            OptimizerAlg::SGD(lr) => lr,
+            OptimizerAlg::Momentum(lr, _) => lr,
+            OptimizerAlg::RMSProp(lr, _) => lr,
+            OptimizerAlg::Adam(lr, _, _) => lr,
+        }
+    }
+
+    /// The same optimizer with a different learning rate
+    pub fn with_learning_rate(&self, lr: f32) -> OptimizerAlg {
+        match *self {
+            OptimizerAlg::SGD(_) => OptimizerAlg::SGD(lr),
+            OptimizerAlg::Momentum(_, mu) => OptimizerAlg::Momentum(lr, mu),
+            OptimizerAlg::RMSProp(_, rho) => OptimizerAlg::RMSProp(lr, rho),
+            OptimizerAlg::Adam(_, beta1, beta2) => OptimizerAlg::Adam(lr, beta1, beta2),
+        }
+    }
+}
+
+/// How the learning rate changes from epoch to epoch during training
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LrSchedule {
+    /// The optimizer's learning rate is used for every epoch
+    #[default]
+    Constant,
+    /// The learning rate is multiplied by `gamma` every `step_size` epochs
+    Step { step_size: usize, gamma: f32 },
+    /// The learning rate is multiplied by `gamma` every epoch
+    Exponential { gamma: f32 },
+    /// The learning rate follows a cosine curve from the optimizer's learning rate
+    /// down to `min_lr` over the training epochs
+    Cosine { min_lr: f32 },
+}
+
+impl LrSchedule {
+    /// Learning rate for the given (zero-based) epoch out of `epochs`
+    pub fn learning_rate(&self, base_lr: f32, epoch: usize, epochs: usize) -> f32 {
+        match *self {
+            LrSchedule::Constant => base_lr,
+            LrSchedule::Step { step_size, gamma } => base_lr * gamma.powi((epoch / step_size.max(1)) as i32),
+            LrSchedule::Exponential { gamma } => base_lr * gamma.powi(epoch as i32),
+            LrSchedule::Cosine { min_lr } => {
+                let progress = epoch as f32 / epochs.saturating_sub(1).max(1) as f32;
+                min_lr + (base_lr - min_lr) * (1.0 + (std::f32::consts::PI * progress).cos()) / 2.0
+            }
+        }
+    }
+
+    /// Checks that the schedule parameters are usable
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            LrSchedule::Constant => Ok(()),
+            LrSchedule::Step { step_size, gamma } => {
+                if step_size == 0 {
+                    Err(String::from("Step schedule step_size must be at least 1"))
+                } else if gamma <= 0.0 {
+                    Err(format!("Step schedule gamma must be positive, got {}", gamma))
+                } else {
+                    Ok(())
+                }
+            }
+            LrSchedule::Exponential { gamma } => {
+                if gamma <= 0.0 {
+                    Err(format!("Exponential schedule gamma must be positive, got {}", gamma))
+                } else {
+                    Ok(())
+                }
+            }
+            LrSchedule::Cosine { min_lr } => {
+                if min_lr < 0.0 {
+                    Err(format!("Cosine schedule min_lr cannot be negative, got {}", min_lr))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
 impl Debug for OptimizerAlg {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
         match self {
             OptimizerAlg::SGD(lr) => {
-                s.push_str(&format!("SGD\n"));
+                s.push_str("SGD\n");
                 s.push_str(&format!(" - Learning Rate: {}\n", lr));
             },
             OptimizerAlg::Momentum(lr, mu) => {
-                s.push_str(&format!("Momentum\n"));
+                s.push_str("Momentum\n");
                 s.push_str(&format!(" - Learning Rate: {}\n", lr));
                 s.push_str(&format!(" - Momentum: {}\n", mu));
             },
             OptimizerAlg::RMSProp(lr, rho) => {
-                s.push_str(&format!("RMSProp\n"));
+                s.push_str("RMSProp\n");
                 s.push_str(&format!(" - Learning Rate: {}\n", lr));
                 s.push_str(&format!(" - Rho: {}\n", rho));
             },
             OptimizerAlg::Adam(lr, beta1, beta2) => {
-                s.push_str(&format!("Adam\n"));
+                s.push_str("Adam\n");
                 s.push_str(&format!(" - Learning Rate: {}\n", lr));
                 s.push_str(&format!(" - Beta1: {}\n", beta1));
                 s.push_str(&format!(" - Beta2: {}\n", beta2));
@@ -80,7 +196,7 @@ impl Optimizer2D {
             },
             OptimizerAlg::RMSProp(lr, rho) => {
                 self.momentum1 = &self.momentum1 * rho;
-                self.momentum1 += &(gradients.mapv(|x| x.powi(2) as f32) * (1.0 - rho));
+                self.momentum1 += &(gradients.mapv(|x| x.powi(2)) * (1.0 - rho));
                 gradients * lr / (self.momentum1.mapv(|x| x.sqrt()) + 1e-8)
             },
             OptimizerAlg::Adam(lr, beta1, beta2) => {
@@ -88,7 +204,7 @@ impl Optimizer2D {
                 self.momentum1 = &self.momentum1 * beta1;
                 self.momentum1 += &(gradients.mapv(|x| x * (1.0 - beta1)));
                 self.momentum2 = &self.momentum2 * beta2;
-                self.momentum2 += &(gradients.mapv(|x| x.powi(2) * (1.0 - beta2) as f32));
+                self.momentum2 += &(gradients.mapv(|x| x.powi(2) * (1.0 - beta2)));
                 let biased_beta1 = if self.beta1_done {
                     0.0
                 } else {
@@ -172,7 +288,7 @@ impl Optimizer4D {
             },
             OptimizerAlg::RMSProp(lr, rho) => {
                 self.momentum1 = &self.momentum1 * rho;
-                self.momentum1 += &(gradients.mapv(|x| x.powi(2) as f32) * (1.0 - rho));
+                self.momentum1 += &(gradients.mapv(|x| x.powi(2)) * (1.0 - rho));
                 gradients * lr / (self.momentum1.mapv(|x| x.sqrt()) + 1e-8)
             },
             OptimizerAlg::Adam(lr, beta1, beta2) => {
@@ -180,7 +296,7 @@ impl Optimizer4D {
                 self.momentum1 = &self.momentum1 * beta1;
                 self.momentum1 += &(gradients.mapv(|x| x * (1.0 - beta1)));
                 self.momentum2 = &self.momentum2 * beta2;
-                self.momentum2 += &(gradients.mapv(|x| x.powi(2) * (1.0 - beta2) as f32));
+                self.momentum2 += &(gradients.mapv(|x| x.powi(2) * (1.0 - beta2)));
                 let biased_beta1 = if self.beta1_done {
                     0.0
                 } else {
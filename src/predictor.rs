@@ -0,0 +1,102 @@
+use ndarray::{Array1, Array3};
+use crate::layer::Layer;
+use crate::normalize::Normalization;
+
+/// Inference-only view of a trained network.
+/// Unlike `CNN::forward_propagate`, prediction takes `&self`, so a
+/// single `Predictor` can be shared between threads. Inputs are normalized
+/// the same way as during training.
+pub struct Predictor {
+    layers: Vec<Layer>,
+    input_shape: (usize, usize, usize),
+    normalization: Option<Normalization>,
+}
+
+impl Predictor {
+    pub(crate) fn new(layers: Vec<Layer>, input_shape: (usize, usize, usize), normalization: Option<Normalization>) -> Predictor {
+        match layers.last() {
+            Some(Layer::Dense(_)) => {}
+            _ => panic!("Last layer is not a DenseLayer"),
+        }
+
+        Predictor { layers, input_shape, normalization }
+    }
+
+    pub fn input_shape(&self) -> (usize, usize, usize) {
+        self.input_shape
+    }
+
+    pub fn num_classes(&self) -> usize {
+        match self.layers.last() {
+            Some(Layer::Dense(dense_layer)) => dense_layer.output_size,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Output of the final layer for a single image
+    pub fn predict_proba(&self, image: &Array3<f32>) -> Array1<f32> {
+        assert_eq!(image.dim(), self.input_shape, "Image shape does not match the network's input shape");
+        let mut output: Array3<f32> = match &self.normalization {
+            Some(normalization) => normalization.apply(image.clone()),
+            None => image.clone(),
+        };
+        let mut flat_output: Array1<f32> = output.iter().cloned().collect();
+        for layer in &self.layers {
+            match layer {
+                Layer::Conv(conv_layer) => {
+                    output = conv_layer.infer(&output);
+                    flat_output = output.iter().cloned().collect();
+                }
+                Layer::Mxpl(mxpl_layer) => {
+                    output = mxpl_layer.infer(&output);
+                    flat_output = output.iter().cloned().collect();
+                }
+                Layer::Dense(dense_layer) => {
+                    flat_output = dense_layer.infer(&flat_output);
+                }
+            }
+        }
+
+        flat_output
+    }
+
+    /// Index of the highest output for a single image
+    pub fn predict(&self, image: &Array3<f32>) -> usize {
+        let output = self.predict_proba(image);
+        let mut max_idx = 0;
+        for (i, &v) in output.iter().enumerate() {
+            if v > output[max_idx] {
+                max_idx = i;
+            }
+        }
+
+        max_idx
+    }
+
+    /// The k highest outputs for a single image as (index, output) pairs,
+    /// sorted from highest to lowest with ties in index order
+    pub fn predict_top_k(&self, image: &Array3<f32>, k: usize) -> Vec<(usize, f32)> {
+        let output = self.predict_proba(image);
+        let mut ranked: Vec<(usize, f32)> = output.iter().cloned().enumerate().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(k);
+
+        ranked
+    }
+
+    pub fn predict_proba_batch(&self, images: &[Array3<f32>]) -> Vec<Array1<f32>> {
+        images.iter().map(|image| self.predict_proba(image)).collect()
+    }
+
+    pub fn predict_batch(&self, images: &[Array3<f32>]) -> Vec<usize> {
+        images.iter().map(|image| self.predict(image)).collect()
+    }
+
+    pub fn predict_top_k_batch(&self, images: &[Array3<f32>], k: usize) -> Vec<Vec<(usize, f32)>> {
+        images.iter().map(|image| self.predict_top_k(image, k)).collect()
+    }
+}
+
+// Fail to compile if Predictor ever stops being shareable between threads
+fn assert_send_sync<T: Send + Sync>() {}
+const _: fn() = assert_send_sync::<Predictor>;
@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use ndarray::Array3;
+use serde::{Serialize, Deserialize};
+use crate::dataset::{Dataset, Label};
+
+/// How samples are read during training and testing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Prefetch {
+    /// Threads decoding upcoming samples, or 0 to decode them on the training thread
+    pub workers: usize,
+    /// Most decoded samples waiting to be used
+    pub queue_size: usize,
+    /// Memory for keeping decoded samples between epochs, in MiB, shared by the
+    /// training and testing sets in proportion to their sizes. 0 disables the cache.
+    pub cache_mb: usize,
+}
+
+impl Default for Prefetch {
+    fn default() -> Self {
+        Prefetch {
+            workers: 0,
+            queue_size: 64,
+            cache_mb: 0,
+        }
+    }
+}
+
+impl Prefetch {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.queue_size == 0 {
+            return Err(String::from("Prefetch queue size must be at least 1"));
+        }
+        Ok(())
+    }
+
+    pub fn cache_bytes(&self) -> usize {
+        self.cache_mb * 1024 * 1024
+    }
+
+    /// Splits `cache_bytes` between a training and testing set of the given
+    /// lengths, in proportion to their lengths, so that together they stay in budget
+    pub fn cache_budgets(&self, train_len: usize, test_len: usize) -> (usize, usize) {
+        let total = self.cache_bytes();
+        if train_len + test_len == 0 {
+            return (total, 0);
+        }
+        let train = (total as u128 * train_len as u128 / (train_len + test_len) as u128) as usize;
+        (train, total - train)
+    }
+}
+
+/// Reads the samples at `indices` of `data` in that order and passes them to `consume`.
+/// With workers, up to `queue_size` samples are decoded ahead on background threads,
+/// which all stop once `consume` returns. A panic while decoding is raised again
+/// when the sample is reached.
+pub fn prefetch<D, F, R>(data: &D, indices: &[usize], options: &Prefetch, consume: F) -> R
+where
+    D: Dataset + Sync + ?Sized,
+    F: FnOnce(&mut dyn Iterator<Item = (Array3<f32>, Label)>) -> R,
+{
+    if options.workers == 0 {
+        return consume(&mut indices.iter().map(|&i| data.get(i)));
+    }
+
+    let queue = Queue {
+        state: Mutex::new(QueueState { next: 0, consumed: 0, ready: HashMap::new(), stopped: false }),
+        changed: Condvar::new(),
+        capacity: options.queue_size.max(1),
+        len: indices.len(),
+    };
+    thread::scope(|scope| {
+        for _ in 0..options.workers {
+            scope.spawn(|| queue.work(data, indices));
+        }
+        // Stops the workers even if `consume` panics, so that the scope can join them
+        let _stop = StopOnDrop(&queue);
+        consume(&mut Samples { queue: &queue })
+    })
+}
+
+type Sample = thread::Result<(Array3<f32>, Label)>;
+
+struct Queue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    capacity: usize,
+    len: usize,
+}
+
+struct QueueState {
+    /// Position in `indices` of the next sample to decode
+    next: usize,
+    /// Number of samples handed out
+    consumed: usize,
+    /// Decoded samples by position, waiting to be handed out
+    ready: HashMap<usize, Sample>,
+    stopped: bool,
+}
+
+impl Queue {
+    fn work<D: Dataset + ?Sized>(&self, data: &D, indices: &[usize]) {
+        loop {
+            let position = {
+                let mut state = self.state.lock().unwrap();
+                while !state.stopped && state.next < self.len && state.next >= state.consumed + self.capacity {
+                    state = self.changed.wait(state).unwrap();
+                }
+                if state.stopped || state.next >= self.len {
+                    return;
+                }
+                state.next += 1;
+                state.next - 1
+            };
+
+            let sample = panic::catch_unwind(AssertUnwindSafe(|| data.get(indices[position])));
+            self.state.lock().unwrap().ready.insert(position, sample);
+            self.changed.notify_all();
+        }
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+}
+
+struct StopOnDrop<'a>(&'a Queue);
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+/// The samples of a `Queue` in order
+struct Samples<'a> {
+    queue: &'a Queue,
+}
+
+impl Iterator for Samples<'_> {
+    type Item = (Array3<f32>, Label);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut state = self.queue.state.lock().unwrap();
+        if state.consumed >= self.queue.len {
+            return None;
+        }
+        loop {
+            let position = state.consumed;
+            if let Some(sample) = state.ready.remove(&position) {
+                state.consumed += 1;
+                drop(state);
+                self.queue.changed.notify_all();
+                return Some(sample.unwrap_or_else(|e| panic::resume_unwind(e)));
+            }
+            state = self.queue.changed.wait(state).unwrap();
+        }
+    }
+}
+
+/// Wraps a dataset, keeping the most recently read samples in memory
+/// as long as they fit in a budget of bytes
+pub struct CachedDataset<D> {
+    data: D,
+    budget: usize,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    samples: HashMap<usize, (Array3<f32>, Label, u64)>,
+    /// Indices of the cached samples by the time they were last read
+    recency: BTreeMap<u64, usize>,
+    time: u64,
+    bytes: usize,
+    hits: usize,
+    misses: usize,
+}
+
+impl<D: Dataset> CachedDataset<D> {
+    pub fn new(data: D, budget: usize) -> CachedDataset<D> {
+        CachedDataset { data, budget, cache: Mutex::new(Cache::default()) }
+    }
+
+    /// Number of reads answered from the cache
+    pub fn hits(&self) -> usize {
+        self.cache.lock().unwrap().hits
+    }
+
+    /// Number of reads passed on to the wrapped dataset
+    pub fn misses(&self) -> usize {
+        self.cache.lock().unwrap().misses
+    }
+
+    /// Memory taken by the cached tensors
+    pub fn cached_bytes(&self) -> usize {
+        self.cache.lock().unwrap().bytes
+    }
+
+    pub fn into_inner(self) -> D {
+        self.data
+    }
+}
+
+impl<D: Dataset> Dataset for CachedDataset<D> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.time += 1;
+            let time = cache.time;
+            if let Some((image, label, last_read)) = cache.samples.get_mut(&i) {
+                let (image, label, previous) = (image.clone(), *label, *last_read);
+                *last_read = time;
+                cache.recency.remove(&previous);
+                cache.recency.insert(time, i);
+                cache.hits += 1;
+                return (image, label);
+            }
+            cache.misses += 1;
+        }
+
+        // Decoded without holding the lock, so that workers can read in parallel
+        let (image, label) = self.data.get(i);
+        let bytes = image.len() * std::mem::size_of::<f32>();
+        if bytes <= self.budget {
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.samples.contains_key(&i) {
+                while cache.bytes + bytes > self.budget {
+                    let (_, oldest) = cache.recency.pop_first().unwrap();
+                    let (evicted, _, _) = cache.samples.remove(&oldest).unwrap();
+                    cache.bytes -= evicted.len() * std::mem::size_of::<f32>();
+                }
+                cache.time += 1;
+                let time = cache.time;
+                cache.samples.insert(i, (image.clone(), label, time));
+                cache.recency.insert(time, i);
+                cache.bytes += bytes;
+            }
+        }
+
+        (image, label)
+    }
+
+    fn label(&self, i: usize) -> Label {
+        self.data.label(i)
+    }
+}
@@ -0,0 +1,100 @@
+use rand::Rng;
+use rand::distributions::{Distribution, WeightedIndex};
+use serde::{Serialize, Deserialize};
+use crate::dataset::{class_counts, Dataset};
+
+/// How training samples are drawn, with replacement
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Sampler {
+    /// Every sample is equally likely
+    #[default]
+    Uniform,
+    /// Every class is equally likely, however many samples it has
+    ClassBalanced,
+    /// Classes are drawn in proportion to their weight, and samples
+    /// uniformly within their class
+    Weighted { class_weights: Vec<f32> },
+}
+
+impl Sampler {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Sampler::Weighted { class_weights } => check_weights("Sampler class weights", class_weights),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the sampler has one weight per class
+    pub fn class_errors(&self, num_classes: usize) -> Vec<String> {
+        match self {
+            Sampler::Weighted { class_weights } if class_weights.len() != num_classes => vec![format!(
+                "Sampler has {} class weights but the network has {} classes", class_weights.len(), num_classes
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// Checks that a class present in the data, with the given number of samples
+    /// per class, has a positive weight, so that some sample can be drawn
+    pub fn data_errors(&self, counts: &[usize]) -> Vec<String> {
+        match self {
+            Sampler::Weighted { class_weights } if counts.iter().any(|&c| c > 0)
+                && !class_weights.iter().zip(counts).any(|(&w, &c)| w > 0.0 && c > 0) => vec![String::from(
+                "Sampler class weights are zero for every class in the training data"
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// Probability weight of every sample of `data`, or `None` for uniform sampling
+    pub fn sample_weights<D: Dataset + ?Sized>(&self, data: &D, num_classes: usize) -> Result<Option<Vec<f64>>, String> {
+        let class_weights = match self {
+            Sampler::Uniform => return Ok(None),
+            Sampler::ClassBalanced => vec![1.0; num_classes],
+            Sampler::Weighted { class_weights } => {
+                if let Some(e) = self.class_errors(num_classes).pop() {
+                    return Err(e);
+                }
+                class_weights.clone()
+            }
+        };
+        let counts = class_counts(data, num_classes)?;
+        if let Some(e) = self.data_errors(&counts).pop() {
+            return Err(e);
+        }
+
+        Ok(Some((0..data.len()).map(|i| {
+            let label = data.label(i);
+            class_weights[label] as f64 / counts[label] as f64
+        }).collect()))
+    }
+}
+
+/// Draws indices in proportion to a weight per sample, for custom sampling
+pub struct WeightedSampler {
+    distribution: WeightedIndex<f64>,
+}
+
+impl WeightedSampler {
+    pub fn new(weights: &[f64]) -> Result<WeightedSampler, String> {
+        let distribution = WeightedIndex::new(weights)
+            .map_err(|e| format!("Invalid sample weights: {}", e))?;
+        Ok(WeightedSampler { distribution })
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.distribution.sample(rng)
+    }
+}
+
+/// Checks that weights are finite and non-negative, with at least one positive
+pub(crate) fn check_weights(name: &str, weights: &[f32]) -> Result<(), String> {
+    if let Some(weight) = weights.iter().find(|w| !w.is_finite() || **w < 0.0) {
+        return Err(format!("{} must be finite and non-negative, got {}", name, weight));
+    }
+    if !weights.iter().any(|&w| w > 0.0) {
+        return Err(format!("{} need at least one positive weight", name));
+    }
+    Ok(())
+}
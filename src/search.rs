@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::builder::CnnBuilder;
+use crate::cnn::CNN;
+use crate::config::NetworkConfig;
+use crate::cross_validation::stratified_folds;
+use crate::dataset::{Dataset, Subset};
+use crate::metrics::Metrics;
+use crate::util::TrainingData;
+
+/// Values a parameter is searched over
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ParamRange {
+    /// One of the listed values, such as layer widths or whole optimizers
+    Choice { values: Vec<Value> },
+    /// Integers from `min` to `max` inclusive, every `step` in grid search
+    IntRange {
+        min: i64,
+        max: i64,
+        #[serde(default = "default_step")]
+        step: i64,
+    },
+    /// Floats from `min` to `max`, at `steps` evenly spaced values in grid search
+    Uniform { min: f64, max: f64, steps: usize },
+    /// Floats from `min` to `max` on a log scale, such as learning rates,
+    /// at `steps` evenly spaced exponents in grid search
+    LogUniform { min: f64, max: f64, steps: usize },
+}
+
+fn default_step() -> i64 {
+    1
+}
+
+impl ParamRange {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ParamRange::Choice { ref values } if values.is_empty() => Err(String::from("Choice needs at least one value")),
+            ParamRange::IntRange { min, max, step } if min > max || step < 1 => Err(format!(
+                "Integer range needs min <= max and a positive step, got {} to {} every {}", min, max, step
+            )),
+            ParamRange::Uniform { min, max, steps } if !(min.is_finite() && max.is_finite()) || min > max || steps == 0 => Err(format!(
+                "Uniform range needs finite min <= max and at least one step, got {} to {} in {} steps", min, max, steps
+            )),
+            ParamRange::LogUniform { min, max, steps } if !(min > 0.0 && max.is_finite()) || min > max || steps == 0 => Err(format!(
+                "Log-uniform range needs 0 < min <= max and at least one step, got {} to {} in {} steps", min, max, steps
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The values visited by grid search
+    pub fn grid(&self) -> Vec<Value> {
+        match *self {
+            ParamRange::Choice { ref values } => values.clone(),
+            ParamRange::IntRange { min, max, step } => (min..=max).step_by(step as usize).map(Value::from).collect(),
+            ParamRange::Uniform { min, max, steps } => spaced(min, max, steps).into_iter().map(Value::from).collect(),
+            ParamRange::LogUniform { min, max, steps } => spaced(min.ln(), max.ln(), steps)
+                .into_iter()
+                .map(|x| Value::from(x.exp()))
+                .collect(),
+        }
+    }
+
+    /// A random value, as drawn by random search
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Value {
+        match *self {
+            ParamRange::Choice { ref values } => values[rng.gen_range(0..values.len())].clone(),
+            ParamRange::IntRange { min, max, step } => Value::from(min + rng.gen_range(0..=(max - min) / step) * step),
+            ParamRange::Uniform { min, max, .. } => Value::from(min + rng.gen::<f64>() * (max - min)),
+            ParamRange::LogUniform { min, max, .. } => Value::from((min.ln() + rng.gen::<f64>() * (max.ln() - min.ln())).exp()),
+        }
+    }
+}
+
+/// `steps` evenly spaced values from `min` to `max`, or just `min` for one step
+fn spaced(min: f64, max: f64, steps: usize) -> Vec<f64> {
+    match steps {
+        1 => vec![min],
+        _ => (0..steps).map(|i| min + (max - min) * i as f64 / (steps - 1) as f64).collect(),
+    }
+}
+
+/// A setting of the network config to search over. The path addresses the setting
+/// in the config as it is written in JSON, with dots between keys and array indices,
+/// such as `hyperparameters.batch_size`, `hyperparameters.optimizer.lr`,
+/// `layers.2.output_size` or `layers.2.dropout`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Parameter {
+    pub path: String,
+    pub range: ParamRange,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Every combination of the grid values, in order
+    #[default]
+    Grid,
+    /// Independent random draws from every range
+    Random,
+}
+
+/// Metric on the validation fold that trials are ranked by, higher being better
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    #[default]
+    Accuracy,
+    BalancedAccuracy,
+    MacroF1,
+}
+
+impl Objective {
+    pub fn score(&self, metrics: &Metrics) -> f32 {
+        match self {
+            Objective::Accuracy => metrics.accuracy(),
+            Objective::BalancedAccuracy => metrics.balanced_accuracy(),
+            Objective::MacroF1 => metrics.macro_f1(),
+        }
+    }
+}
+
+/// A hyperparameter search over a base network config, read from a JSON or TOML file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Search {
+    pub parameters: Vec<Parameter>,
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Most trials to run. Required for random search, and a cap on grid search.
+    #[serde(default)]
+    pub trials: Option<usize>,
+    /// Seed of random search and of the validation fold
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub objective: Objective,
+    /// The training split is divided into this many stratified folds, and trials
+    /// are trained on all but the first and scored on the first
+    #[serde(default = "default_validation_folds")]
+    pub validation_folds: usize,
+}
+
+fn default_validation_folds() -> usize {
+    5
+}
+
+impl Search {
+    /// Reads a search file, using TOML for `.toml` files and JSON otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Search, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let search = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+            _ => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+        };
+
+        search.map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Checks the search against the base config, returning every problem found, one per line
+    pub fn validate(&self, base: &NetworkConfig) -> Result<(), String> {
+        let mut errors = vec![];
+        if self.parameters.is_empty() {
+            errors.push(String::from("The search has no parameters"));
+        }
+        if self.strategy == Strategy::Random && self.trials.is_none() {
+            errors.push(String::from("Random search needs a number of trials"));
+        }
+        if self.trials == Some(0) {
+            errors.push(String::from("The search needs at least 1 trial"));
+        }
+        if self.validation_folds < 2 {
+            errors.push(format!("validation_folds must be at least 2, got {}", self.validation_folds));
+        }
+        let mut config = serde_json::to_value(base).unwrap();
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if let Err(e) = parameter.range.validate() {
+                errors.push(format!("Parameter {} ({}): {}", i, parameter.path, e));
+            }
+            if lookup(&mut config, &parameter.path).is_none() {
+                errors.push(format!("Parameter {}: {} is not a setting of the config", i, parameter.path));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// The parameter values of every trial to run, in order
+    pub fn trial_values(&self) -> Vec<Vec<Value>> {
+        match self.strategy {
+            Strategy::Grid => {
+                let grids: Vec<Vec<Value>> = self.parameters.iter().map(|p| p.range.grid()).collect();
+                let mut combinations: Vec<Vec<Value>> = vec![vec![]];
+                for grid in &grids {
+                    combinations = combinations.into_iter()
+                        .flat_map(|combination| grid.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push(value.clone());
+                            combination
+                        }))
+                        .collect();
+                }
+                combinations.truncate(self.trials.unwrap_or(usize::MAX));
+                combinations
+            }
+            Strategy::Random => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                (0..self.trials.unwrap_or(0))
+                    .map(|_| self.parameters.iter().map(|p| p.range.sample(&mut rng)).collect())
+                    .collect()
+            }
+        }
+    }
+
+    /// Runs every trial: builds a network from `base` with the trial's values, trains it on
+    /// the training split of `data` less a stratified validation fold, and scores it on that
+    /// fold. Trials whose values make an invalid network, or one that does not fit `data`,
+    /// are recorded with their error.
+    /// Keeps the best trained network, and only that network is evaluated on the test split,
+    /// so its test metrics are not biased by the selection.
+    pub fn run(&self, base: &NetworkConfig, data: &TrainingData) -> Result<SearchResults, String> {
+        self.validate(base)?;
+        let (samples, test) = (data.train_set(), data.test_set());
+        if samples.is_empty() || test.is_empty() {
+            return Err(String::from("Hyperparameter search needs training and testing samples"));
+        }
+        let folds = stratified_folds(&samples, self.validation_folds, self.seed)?;
+        let validation = Subset::new(&samples, folds[0].clone())?;
+        let train = Subset::new(&samples, folds[1..].concat())?;
+        // Trials train on subsets of `data`, so their networks only keep what describes it
+        let metadata = || TrainingData {
+            rows: data.rows,
+            cols: data.cols,
+            classes: data.classes.clone(),
+            label_names: data.label_names.clone(),
+            image_options: data.image_options.clone(),
+            ..TrainingData::default()
+        };
+
+        let mut trials = vec![];
+        let mut best: Option<(f32, CNN)> = None;
+        for (number, values) in self.trial_values().into_iter().enumerate() {
+            let start = Instant::now();
+            let named: BTreeMap<String, Value> = self.parameters.iter()
+                .map(|p| p.path.clone())
+                .zip(values.iter().cloned())
+                .collect();
+            let mut trial = Trial { number, values: named, score: None, metrics: None, error: None, seconds: 0.0 };
+
+            let trained = self.trial_builder(base, &values)
+                .and_then(|builder| match builder.data_errors(data) {
+                    errors if errors.is_empty() => builder.build(metadata()),
+                    errors => Err(errors.join("\n")),
+                })
+                .and_then(|mut cnn| cnn.train_on(&train, Some(&validation)).map(|_| cnn));
+            match trained {
+                Ok(mut cnn) => {
+                    let metrics = cnn.evaluate(&validation);
+                    let score = self.objective.score(&metrics);
+                    trial.score = Some(score);
+                    trial.metrics = Some(metrics);
+                    if best.as_ref().map(|(best, _)| score > *best).unwrap_or(true) {
+                        best = Some((score, cnn));
+                    }
+                }
+                Err(e) => trial.error = Some(e),
+            }
+            trial.seconds = start.elapsed().as_secs_f32();
+            trials.push(trial);
+        }
+
+        let mut best_model = best.map(|(_, cnn)| cnn);
+        let test_metrics = best_model.as_mut().map(|cnn| cnn.evaluate(&test));
+
+        Ok(SearchResults {
+            parameters: self.parameters.iter().map(|p| p.path.clone()).collect(),
+            trials,
+            best_model,
+            test_metrics,
+        })
+    }
+
+    /// The base config with one value set per parameter
+    fn trial_builder(&self, base: &NetworkConfig, values: &[Value]) -> Result<CnnBuilder, String> {
+        let mut config = serde_json::to_value(base).unwrap();
+        for (parameter, value) in self.parameters.iter().zip(values) {
+            *lookup(&mut config, &parameter.path).unwrap() = value.clone();
+        }
+        let config: NetworkConfig = serde_json::from_value(config).map_err(|e| e.to_string())?;
+
+        Ok(CnnBuilder::from_config(&config))
+    }
+}
+
+/// The setting at a dotted path of a config, if it exists
+fn lookup<'a>(config: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(config, |value, key| match value {
+        Value::Object(map) => map.get_mut(key),
+        Value::Array(array) => key.parse::<usize>().ok().and_then(|i| array.get_mut(i)),
+        _ => None,
+    })
+}
+
+/// One network trained during a search
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Trial {
+    pub number: usize,
+    /// Value of every parameter, by path
+    pub values: BTreeMap<String, Value>,
+    /// Objective on the validation fold, if the trial ran
+    pub score: Option<f32>,
+    /// Metrics on the validation fold, if the trial ran
+    pub metrics: Option<Metrics>,
+    /// Why the trial could not run, such as an invalid layer width
+    pub error: Option<String>,
+    pub seconds: f32,
+}
+
+/// Trials of a `Search`, in the order they ran, and the best network
+pub struct SearchResults {
+    /// Paths of the parameters, in the order of the search
+    pub parameters: Vec<String>,
+    pub trials: Vec<Trial>,
+    pub best_model: Option<CNN>,
+    /// Metrics of the best network on the test split, which no trial was scored on
+    pub test_metrics: Option<Metrics>,
+}
+
+impl SearchResults {
+    /// Trials from best to worst score, followed by those that failed
+    pub fn leaderboard(&self) -> Vec<&Trial> {
+        let mut trials: Vec<&Trial> = self.trials.iter().collect();
+        // Stable, so ties keep the order the trials ran in
+        trials.sort_by(|a, b| match (a.score, b.score) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        trials
+    }
+
+    pub fn best_trial(&self) -> Option<&Trial> {
+        self.leaderboard().into_iter().next().filter(|trial| trial.score.is_some())
+    }
+
+    /// The leaderboard as CSV, with one column per parameter holding its JSON value
+    pub fn to_csv(&self) -> String {
+        let mut header = vec![String::from("rank"), String::from("trial"), String::from("score")];
+        header.extend(["accuracy", "balanced_accuracy", "macro_f1", "seconds"].iter().map(|s| s.to_string()));
+        header.extend(self.parameters.iter().cloned());
+        header.push(String::from("error"));
+        let mut csv = header.iter().map(|h| csv_field(h)).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+
+        for (rank, trial) in self.leaderboard().into_iter().enumerate() {
+            let metric = |f: fn(&Metrics) -> f32| trial.metrics.as_ref().map(|m| f(m).to_string()).unwrap_or_default();
+            let mut row = vec![
+                (rank + 1).to_string(),
+                trial.number.to_string(),
+                trial.score.map(|s| s.to_string()).unwrap_or_default(),
+                metric(Metrics::accuracy),
+                metric(Metrics::balanced_accuracy),
+                metric(Metrics::macro_f1),
+                format!("{:.3}", trial.seconds),
+            ];
+            row.extend(self.parameters.iter().map(|p| trial.values[p].to_string()));
+            row.push(trial.error.clone().unwrap_or_default());
+            csv.push_str(&row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// The leaderboard as a JSON array of trials
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.leaderboard()).unwrap()
+    }
+
+    /// Writes `leaderboard.csv`, `leaderboard.json`, the best network, as
+    /// `best_model` with the extension of its format, and its `test_metrics.json`
+    /// to a directory. Returns the path of the best network, if any trial ran.
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<Option<PathBuf>, String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        for (name, contents) in [("leaderboard.csv", self.to_csv()), ("leaderboard.json", self.to_json())] {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+
+        match &self.best_model {
+            Some(cnn) => {
+                if let Some(metrics) = &self.test_metrics {
+                    let path = dir.join("test_metrics.json");
+                    std::fs::write(&path, serde_json::to_string_pretty(metrics).unwrap())
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                }
+                let path = dir.join(format!("best_model.{}", cnn.model_format().extension()));
+                cnn.save_to(&path)?;
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
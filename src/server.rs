@@ -0,0 +1,174 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use ndarray::Array3;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+use crate::cnn::CNN;
+use crate::model_file::SCHEMA_VERSION;
+use crate::predictor::Predictor;
+use crate::util::{decode_image_with, ColorMode, ImageOptions};
+
+/// Largest request body accepted, in bytes
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+/// Number of classes returned in `top_k` when the request does not set `k`
+const DEFAULT_TOP_K: usize = 5;
+
+/// Raw tensor accepted by `POST /predict` as `application/json`,
+/// with `data` holding the values in row-major [rows, cols, channels] order
+#[derive(Deserialize)]
+struct TensorRequest {
+    shape: Vec<usize>,
+    data: Vec<f32>,
+}
+
+/// HTTP server answering prediction requests with a loaded model.
+///
+/// - `GET /health` returns the model metadata
+/// - `POST /predict` takes a PNG or JPEG image, or a JSON tensor
+///   `{"shape": [rows, cols, channels], "data": [...]}`, and returns the class
+///   probabilities and the `k` most likely classes, where `k` is set with `?k=`
+pub struct InferenceServer {
+    server: tiny_http::Server,
+    predictor: Predictor,
+    metadata: Value,
+    labels: Option<Vec<String>>,
+}
+
+impl InferenceServer {
+    /// Binds to `addr`, such as `127.0.0.1:8080`. Port 0 picks a free port.
+    pub fn bind(cnn: CNN, addr: &str) -> Result<InferenceServer, String> {
+        let summary = cnn.summary();
+        let metadata = json!({
+            "name": cnn.name(),
+            "input_shape": cnn.input_shape(),
+            "num_classes": summary.layers.last().map(|l| l.output_shape.0),
+            "layers": summary.layers.iter().map(|l| &l.layer_type).collect::<Vec<_>>(),
+            "params": summary.total_params,
+            "schema_version": SCHEMA_VERSION,
+            "oxi_net_version": env!("CARGO_PKG_VERSION"),
+        });
+        let labels = cnn.class_names();
+        let server = tiny_http::Server::http(addr).map_err(|e| format!("Failed to bind to {}: {}", addr, e))?;
+
+        Ok(InferenceServer {
+            server,
+            predictor: cnn.into_predictor(),
+            metadata,
+            labels,
+        })
+    }
+
+    /// Names the classes in responses, in class index order, replacing any
+    /// label names saved with the model
+    pub fn with_labels(mut self, labels: Vec<String>) -> Result<InferenceServer, String> {
+        if labels.len() != self.predictor.num_classes() {
+            return Err(format!(
+                "Got {} labels but the model has {} classes",
+                labels.len(), self.predictor.num_classes()
+            ));
+        }
+        self.labels = Some(labels);
+        Ok(self)
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Answers requests until `stop` is called
+    pub fn run(&self) {
+        for request in self.server.incoming_requests() {
+            self.respond(request);
+        }
+    }
+
+    /// Makes `run` return, from another thread
+    pub fn stop(&self) {
+        self.server.unblock();
+    }
+
+    fn respond(&self, mut request: Request) {
+        let content_type = request.headers().iter()
+            .find(|h| h.field.equiv("Content-Type"))
+            .map(|h| h.value.as_str().to_lowercase())
+            .unwrap_or_default();
+        let url = String::from(request.url());
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        let mut body = vec![];
+        let read = request.as_reader().take(MAX_BODY_SIZE as u64 + 1).read_to_end(&mut body);
+        let (status, value) = match read {
+            Err(e) => (400, error(&format!("Failed to read the request body: {}", e))),
+            Ok(_) if body.len() > MAX_BODY_SIZE => (413, error("Request body is too large")),
+            Ok(_) => self.handle(request.method(), path, query, &content_type, &body),
+        };
+
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_string(value.to_string())
+            .with_status_code(status)
+            .with_header(header);
+        // The client may have gone away, which only affects that client
+        let _ = request.respond(response);
+    }
+
+    fn handle(&self, method: &Method, path: &str, query: &str, content_type: &str, body: &[u8]) -> (u16, Value) {
+        match (method, path) {
+            (Method::Get, "/health") => (200, json!({"status": "ok", "model": self.metadata})),
+            (Method::Post, "/predict") => match self.predict(query, content_type, body) {
+                Ok(value) => (200, value),
+                Err(e) => (400, error(&e)),
+            },
+            (_, "/health") | (_, "/predict") => (405, error(&format!("{} is not allowed on {}", method, path))),
+            _ => (404, error(&format!("No endpoint at {}", path))),
+        }
+    }
+
+    fn predict(&self, query: &str, content_type: &str, body: &[u8]) -> Result<Value, String> {
+        let k = match query.split('&').find_map(|pair| pair.strip_prefix("k=")) {
+            Some(k) => k.parse::<usize>().map_err(|_| format!("Invalid k {:?}", k))?,
+            None => DEFAULT_TOP_K,
+        };
+        let image = if content_type.starts_with("application/json") {
+            let tensor: TensorRequest = serde_json::from_slice(body).map_err(|e| format!("Invalid tensor: {}", e))?;
+            let shape = match tensor.shape[..] {
+                [rows, cols, channels] => (rows, cols, channels),
+                _ => return Err(format!("Tensor shape must have 3 dimensions, got {:?}", tensor.shape)),
+            };
+            Array3::from_shape_vec(shape, tensor.data)
+                .map_err(|_| format!("Tensor data does not match its shape {:?}", tensor.shape))?
+        } else {
+            // Convert to the model's channels, so that grayscale models accept color images
+            let (_, _, channels) = self.predictor.input_shape();
+            let options = ImageOptions { color: ColorMode::from_channels(channels)?, ..ImageOptions::default() };
+            decode_image_with(body, &options).map_err(|e| format!("Invalid image: {}", e))?
+        };
+        if image.dim() != self.predictor.input_shape() {
+            return Err(format!(
+                "Input has shape {:?} but the model expects {:?}",
+                image.dim(), self.predictor.input_shape()
+            ));
+        }
+
+        let probabilities = self.predictor.predict_proba(&image);
+        let mut ranked: Vec<(usize, f32)> = probabilities.iter().cloned().enumerate().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(k);
+        let top_k: Vec<Value> = ranked.into_iter()
+            .map(|(class, probability)| {
+                let mut value = json!({"class": class, "probability": probability});
+                // Outputs past the classes of the training data have no name
+                if let Some(label) = self.labels.as_ref().and_then(|labels| labels.get(class)) {
+                    value["label"] = json!(label);
+                }
+                value
+            })
+            .collect();
+
+        Ok(json!({"probabilities": probabilities.to_vec(), "top_k": top_k}))
+    }
+}
+
+fn error(message: &str) -> Value {
+    json!({"error": message})
+}
@@ -0,0 +1,115 @@
+use std::fmt::{Debug, Formatter};
+use serde::{Serialize, Deserialize};
+use crate::layer::Layer;
+
+/// Bytes per stored value, as every tensor holds f32s
+const BYTES_PER_VALUE: usize = 4;
+
+/// One row of a `ModelSummary`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LayerSummary {
+    pub index: usize,
+    /// "Conv", "Mxpl" or "Dense"
+    pub layer_type: String,
+    pub output_shape: (usize, usize, usize),
+    /// Trainable parameters
+    pub params: usize,
+    /// Approximate floating point operations of a forward pass,
+    /// counting a multiply-add as two
+    pub flops: usize,
+    /// Bytes taken by the parameters and the output of the layer
+    pub memory: usize,
+}
+
+/// Per-layer and total sizes of a network, see `CNN::summary`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ModelSummary {
+    pub input_shape: (usize, usize, usize),
+    pub layers: Vec<LayerSummary>,
+    pub total_params: usize,
+    pub total_flops: usize,
+    /// Bytes taken by all parameters and layer outputs, including the input
+    pub total_memory: usize,
+}
+
+impl ModelSummary {
+    pub fn new(layers: &[Layer], input_shape: (usize, usize, usize)) -> ModelSummary {
+        let layers: Vec<LayerSummary> = layers.iter().enumerate().map(|(index, layer)| summarize(index, layer)).collect();
+        let input_memory = input_shape.0 * input_shape.1 * input_shape.2 * BYTES_PER_VALUE;
+
+        ModelSummary {
+            input_shape,
+            total_params: layers.iter().map(|l| l.params).sum(),
+            total_flops: layers.iter().map(|l| l.flops).sum(),
+            total_memory: input_memory + layers.iter().map(|l| l.memory).sum::<usize>(),
+            layers,
+        }
+    }
+}
+
+fn summarize(index: usize, layer: &Layer) -> LayerSummary {
+    let (layer_type, output_shape, params, flops) = match layer {
+        Layer::Conv(conv_layer) => {
+            let (rows, cols, filters) = conv_layer.output_size;
+            let window = conv_layer.kernel_size.pow(2) * conv_layer.input_size.2;
+            // Multiply-adds over every window, then the ReLU
+            let flops = rows * cols * filters * (2 * window + 1);
+            ("Conv", conv_layer.output_size, conv_layer.kernels.len(), flops)
+        }
+        Layer::Mxpl(mxpl_layer) => {
+            let (rows, cols, channels) = mxpl_layer.output_size;
+            // One comparison per value in every window
+            let flops = rows * cols * channels * mxpl_layer.kernel_size.pow(2);
+            ("Mxpl", mxpl_layer.output_size, 0, flops)
+        }
+        Layer::Dense(dense_layer) => {
+            // Multiply-adds, the bias and the activation
+            let flops = dense_layer.output_size * (2 * dense_layer.input_size + 2);
+            let params = dense_layer.weights.len() + dense_layer.biases.len();
+            ("Dense", (dense_layer.output_size, 1, 1), params, flops)
+        }
+    };
+    let outputs = output_shape.0 * output_shape.1 * output_shape.2;
+
+    LayerSummary {
+        index,
+        layer_type: String::from(layer_type),
+        output_shape,
+        params,
+        flops,
+        memory: (params + outputs) * BYTES_PER_VALUE,
+    }
+}
+
+impl Debug for ModelSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        let (rows, cols, channels) = self.input_shape;
+        s.push_str(&format!("Input: {}x{}x{}\n", rows, cols, channels));
+        s.push_str(&format!("{:>5} {:<6} {:>14} {:>12} {:>14} {:>10}\n", "Layer", "Type", "Output Shape", "Params", "FLOPs", "Memory"));
+        for layer in &self.layers {
+            let (rows, cols, channels) = layer.output_shape;
+            s.push_str(&format!(
+                "{:>5} {:<6} {:>14} {:>12} {:>14} {:>10}\n",
+                layer.index, layer.layer_type, format!("{}x{}x{}", rows, cols, channels),
+                layer.params, layer.flops, format_bytes(layer.memory)
+            ));
+        }
+        s.push('\n');
+        s.push_str(&format!("Total params: {}\n", self.total_params));
+        s.push_str(&format!("Total FLOPs: {}\n", self.total_flops));
+        s.push_str(&format!("Total memory: {}\n", format_bytes(self.total_memory)));
+
+        write!(f, "{}", s)
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
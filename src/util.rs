@@ -2,6 +2,9 @@ use ndarray::{Array1, Array2};
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::io::Cursor;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgba, RgbaImage};
+use image::imageops::FilterType;
 use image::io::Reader as ImageReader;
 use ndarray::Array3;
 
@@ -12,6 +15,21 @@ pub enum TrainImage {
     Path(PathBuf),
 }
 
+impl TrainImage {
+    /// Returns the image, loading it from disk if necessary
+    pub fn load(&self) -> Result<Array3<f32>, String> {
+        self.load_with(&ImageOptions::default())
+    }
+
+    /// Returns the image, loading it from disk with `options` if necessary
+    pub fn load_with(&self, options: &ImageOptions) -> Result<Array3<f32>, String> {
+        match self {
+            TrainImage::Image(img) => Ok(img.clone()),
+            TrainImage::Path(img_path) => load_image_with(img_path, options),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct TrainingData {
     pub trn_img: Vec<TrainImage>,
@@ -23,6 +41,39 @@ pub struct TrainingData {
     pub trn_size: usize,
     pub tst_size: usize,
     pub classes: HashMap<usize, usize>,
+    /// Human-readable name of each label, where the loader knows them
+    pub label_names: HashMap<usize, String>,
+    /// How images stored as paths are decoded
+    pub image_options: ImageOptions,
+}
+
+impl TrainingData {
+    /// Name of the class at the given index of the network output,
+    /// falling back to the label number when the loader gave no names
+    pub fn class_name(&self, class: usize) -> Option<String> {
+        let label = self.classes.iter().find(|(_, &c)| c == class).map(|(&label, _)| label)?;
+        Some(self.label_names.get(&label).cloned().unwrap_or_else(|| label.to_string()))
+    }
+
+    /// Checks that the images match a network input shape of (rows, cols, channels),
+    /// decoding the first image to find its channels
+    pub fn check_input_shape(&self, input_shape: (usize, usize, usize)) -> Result<(), String> {
+        let first = match self.trn_img.first().or(self.tst_img.first()) {
+            Some(first) => first.load_with(&self.image_options)?,
+            None => return Ok(()),
+        };
+        check_shape(&first, input_shape)
+            .map_err(|_| format!("Images have shape {:?} but the network input shape is {:?}", first.dim(), input_shape))
+    }
+}
+
+/// 64-bit FNV-1a hash, used for train/test splits because the algorithm is
+/// fixed, unlike `DefaultHasher`, so splits are the same on every platform and
+/// Rust release
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 /// Computes the outer product of two vectors
@@ -38,47 +89,8 @@ pub fn outer(x: Array1<f32>, y: Array1<f32>) -> Array2<f32> {
     result
 }
 
-/// Converts a state name to an index, based on
-/// alphabetical order of the states
-pub fn state_to_idx<T>(state: T) -> usize
-where T: Into<&'static str> {
-    let state_map: HashMap<&'static str, usize> = [
-        ("Alabama", 0), ("Alaska", 1), ("Arizona", 2), ("Arkansas", 3), ("California", 4),
-        ("Colorado", 5), ("Connecticut", 6), ("Delaware", 7), ("Florida", 8), ("Georgia", 9),
-        ("Hawaii", 10), ("Idaho", 11), ("Illinois", 12), ("Indiana", 13), ("Iowa", 14),
-        ("Kansas", 15), ("Kentucky", 16), ("Louisiana", 17), ("Maine", 18), ("Maryland", 19),
-        ("Massachusetts", 20), ("Michigan", 21), ("Minnesota", 22), ("Mississippi", 23), ("Missouri", 24),
-        ("Montana", 25), ("Nebraska", 26), ("Nevada", 27), ("New Hampshire", 28), ("New Jersey", 29),
-        ("New Mexico", 30), ("New York", 31), ("North Carolina", 32), ("North Dakota", 33), ("Ohio", 34),
-        ("Oklahoma", 35), ("Oregon", 36), ("Pennsylvania", 37), ("Rhode Island", 38), ("South Carolina", 39),
-        ("South Dakota", 40), ("Tennessee", 41), ("Texas", 42), ("Utah", 43), ("Vermont", 44),
-        ("Virginia", 45), ("Washington", 46), ("West Virginia", 47), ("Wisconsin", 48), ("Wyoming", 49),
-    ].iter().cloned().collect();
-
-    *state_map.get(state.into()).unwrap()
-}
-
-
-/// Converts a state index to a name, based on
-/// alphabetical order of the states
-pub fn idx_to_state(idx: usize) -> &'static str {
-    let cluster_map: HashMap<usize, &'static str> = [
-        (0, "Alabama"), (1, "Alaska"), (2, "Arizona"), (3, "Arkansas"), (4, "California"),
-        (5, "Colorado"), (6, "Connecticut"), (7, "Delaware"), (8, "Florida"), (9, "Georgia"),
-        (10, "Hawaii"), (11, "Idaho"), (12, "Illinois"), (13, "Indiana"), (14, "Iowa"),
-        (15, "Kansas"), (16, "Kentucky"), (17, "Louisiana"), (18, "Maine"), (19, "Maryland"),
-        (20, "Massachusetts"), (21, "Michigan"), (22, "Minnesota"), (23, "Mississippi"), (24, "Missouri"),
-        (25, "Montana"), (26, "Nebraska"), (27, "Nevada"), (28, "New Hampshire"), (29, "New Jersey"),
-        (30, "New Mexico"), (31, "New York"), (32, "North Carolina"), (33, "North Dakota"), (34, "Ohio"),
-        (35, "Oklahoma"), (36, "Oregon"), (37, "Pennsylvania"), (38, "Rhode Island"), (39, "South Carolina"),
-        (40, "South Dakota"), (41, "Tennessee"), (42, "Texas"), (43, "Utah"), (44, "Vermont"),
-        (45, "Virginia"), (46, "Washington"), (47, "West Virginia"), (48, "Wisconsin"), (49, "Wyoming"),
-    ].iter().cloned().collect();
-
-    cluster_map.get(&idx).unwrap()
-}
-
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(from = "SavingStrategySpec", into = "SavingStrategySpec")]
 /// Defines when the model should be saved.
 /// The bool is whether to save the full model (true), or just metadata (false)
 pub enum SavingStrategy {
@@ -89,23 +101,275 @@ pub enum SavingStrategy {
     Never,
 }
 
+/// How a `SavingStrategy` is written in config and model files, with named parameters
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SavingStrategySpec {
+    EveryEpoch { full_save: bool },
+    /// `n` is the fraction of an epoch between saves
+    EveryNthEpoch { full_save: bool, n: f32 },
+    BestTrainingAccuracy { full_save: bool },
+    BestTestingAccuracy { full_save: bool },
+    Never,
+}
+
+impl From<SavingStrategySpec> for SavingStrategy {
+    fn from(spec: SavingStrategySpec) -> SavingStrategy {
+        match spec {
+            SavingStrategySpec::EveryEpoch { full_save } => SavingStrategy::EveryEpoch(full_save),
+            SavingStrategySpec::EveryNthEpoch { full_save, n } => SavingStrategy::EveryNthEpoch(full_save, n),
+            SavingStrategySpec::BestTrainingAccuracy { full_save } => SavingStrategy::BestTrainingAccuracy(full_save),
+            SavingStrategySpec::BestTestingAccuracy { full_save } => SavingStrategy::BestTestingAccuracy(full_save),
+            SavingStrategySpec::Never => SavingStrategy::Never,
+        }
+    }
+}
+
+impl From<SavingStrategy> for SavingStrategySpec {
+    fn from(strategy: SavingStrategy) -> SavingStrategySpec {
+        match strategy {
+            SavingStrategy::EveryEpoch(full_save) => SavingStrategySpec::EveryEpoch { full_save },
+            SavingStrategy::EveryNthEpoch(full_save, n) => SavingStrategySpec::EveryNthEpoch { full_save, n },
+            SavingStrategy::BestTrainingAccuracy(full_save) => SavingStrategySpec::BestTrainingAccuracy { full_save },
+            SavingStrategy::BestTestingAccuracy(full_save) => SavingStrategySpec::BestTestingAccuracy { full_save },
+            SavingStrategy::Never => SavingStrategySpec::Never,
+        }
+    }
+}
+
+/// Interpolation used when resizing images
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Averages every source pixel that an output pixel covers, best for shrinking
+    Area,
+}
+
+/// How an image is fitted to a size with a different aspect ratio
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    /// Scales each axis independently, distorting the image
+    #[default]
+    Stretch,
+    /// Scales to cover the size, then cuts off the overhang equally on both sides
+    CenterCrop,
+    /// Scales to fit inside the size, then pads equally on both sides with zeros
+    Letterbox,
+}
+
+/// Channels an image is converted to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    Grayscale,
+    #[default]
+    Rgb,
+    Rgba,
+}
+
+impl ColorMode {
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorMode::Grayscale => 1,
+            ColorMode::Rgb => 3,
+            ColorMode::Rgba => 4,
+        }
+    }
+
+    pub fn from_channels(channels: usize) -> Result<ColorMode, String> {
+        match channels {
+            1 => Ok(ColorMode::Grayscale),
+            3 => Ok(ColorMode::Rgb),
+            4 => Ok(ColorMode::Rgba),
+            _ => Err(format!("Images can have 1, 3 or 4 channels, not {}", channels)),
+        }
+    }
+}
+
+/// How `load_image_with` and `decode_image_with` turn an image file into a tensor
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageOptions {
+    /// (rows, cols) to resize to, or `None` to keep the size of the file
+    pub size: Option<(usize, usize)>,
+    pub filter: ResizeFilter,
+    pub mode: ResizeMode,
+    pub color: ColorMode,
+}
+
+impl ImageOptions {
+    /// Options producing tensors of a network input shape of (rows, cols, channels)
+    pub fn for_input_shape(input_shape: (usize, usize, usize)) -> Result<ImageOptions, String> {
+        let (rows, cols, channels) = input_shape;
+        Ok(ImageOptions {
+            size: Some((rows, cols)),
+            color: ColorMode::from_channels(channels)?,
+            ..ImageOptions::default()
+        })
+    }
+}
+
+/// Loads an image file as RGB values in [0, 1], keeping its size
 pub fn load_image(path: &Path) -> Result<Array3<f32>, String> {
+    load_image_with(path, &ImageOptions::default())
+}
+
+/// Loads an image file as values in [0, 1], resized and converted as set by `options`
+pub fn load_image_with(path: &Path, options: &ImageOptions) -> Result<Array3<f32>, String> {
     let img = ImageReader::open(path)
         .map_err(|e| e.to_string())?
         .decode()
         .map_err(|e| e.to_string())?;
-    let img = img.to_rgb8();
 
+    image_to_array(img, options)
+}
+
+/// Decodes an encoded image, such as the contents of a PNG or JPEG file,
+/// in the same way as `load_image`
+pub fn decode_image(bytes: &[u8]) -> Result<Array3<f32>, String> {
+    decode_image_with(bytes, &ImageOptions::default())
+}
+
+/// Decodes an encoded image in the same way as `load_image_with`
+pub fn decode_image_with(bytes: &[u8], options: &ImageOptions) -> Result<Array3<f32>, String> {
+    let img = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())?;
+
+    image_to_array(img, options)
+}
+
+/// Checks that an image has the (rows, cols, channels) shape a network expects
+pub fn check_shape(image: &Array3<f32>, expected: (usize, usize, usize)) -> Result<(), String> {
+    if image.dim() == expected {
+        Ok(())
+    } else {
+        Err(format!("Image has shape {:?} but the model expects {:?}", image.dim(), expected))
+    }
+}
+
+/// Saves a (rows, cols, channels) tensor with values in [0, 1] as an image file,
+/// in the format given by the extension of `path`. Values outside [0, 1] are clamped.
+pub fn save_image(image: &Array3<f32>, path: &Path) -> Result<(), String> {
+    let (rows, cols, channels) = image.dim();
+    let color = match ColorMode::from_channels(channels)? {
+        ColorMode::Grayscale => image::ColorType::L8,
+        ColorMode::Rgb => image::ColorType::Rgb8,
+        ColorMode::Rgba => image::ColorType::Rgba8,
+    };
+    // Row-major (H, W, C) is the interleaved layout image files use
+    let bytes: Vec<u8> = image.iter()
+        .map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+
+    image::save_buffer(path, &bytes, cols as u32, rows as u32, color).map_err(|e| e.to_string())
+}
+
+fn image_to_array(img: DynamicImage, options: &ImageOptions) -> Result<Array3<f32>, String> {
+    let img = match options.size {
+        Some((rows, cols)) => fit(img, rows, cols, options)?,
+        None => img,
+    };
+
+    Ok(match options.color {
+        ColorMode::Grayscale => buffer_to_array(&img.to_luma8()),
+        ColorMode::Rgb => buffer_to_array(&img.to_rgb8()),
+        ColorMode::Rgba => buffer_to_array(&img.to_rgba8()),
+    })
+}
+
+fn buffer_to_array<P: Pixel<Subpixel = u8> + 'static>(img: &ImageBuffer<P, Vec<u8>>) -> Array3<f32> {
     let rows = img.height() as usize;
     let cols = img.width() as usize;
-    let mut array = Array3::zeros((rows, cols, 3));
+    let mut array = Array3::zeros((rows, cols, P::CHANNEL_COUNT as usize));
 
+    // Pixels are addressed (x, y) = (column, row), tensors [row, column, channel]
     for (x, y, pixel) in img.enumerate_pixels() {
-        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
-        array[[x as usize, y as usize, 0]] = r / 255.0;
-        array[[x as usize, y as usize, 1]] = g / 255.0;
-        array[[x as usize, y as usize, 2]] = b / 255.0;
+        for (c, &value) in pixel.channels().iter().enumerate() {
+            array[[y as usize, x as usize, c]] = value as f32 / 255.0;
+        }
     }
 
-    Ok(array)
-}
\ No newline at end of file
+    array
+}
+
+/// Resizes an image to `rows` x `cols` following `options.mode`
+fn fit(img: DynamicImage, rows: usize, cols: usize, options: &ImageOptions) -> Result<DynamicImage, String> {
+    if rows == 0 || cols == 0 {
+        return Err(format!("Cannot resize an image to {}x{}", rows, cols));
+    }
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    let (target_width, target_height) = (cols as u32, rows as u32);
+    if (img.width(), img.height()) == (target_width, target_height) {
+        return Ok(img);
+    }
+
+    Ok(match options.mode {
+        ResizeMode::Stretch => resize(&img, target_width, target_height, options.filter),
+        ResizeMode::CenterCrop => {
+            let scale = (cols as f64 / width).max(rows as f64 / height);
+            let scaled_width = ((width * scale).round() as u32).max(target_width);
+            let scaled_height = ((height * scale).round() as u32).max(target_height);
+            let scaled = resize(&img, scaled_width, scaled_height, options.filter);
+            scaled.crop_imm((scaled_width - target_width) / 2, (scaled_height - target_height) / 2, target_width, target_height)
+        }
+        ResizeMode::Letterbox => {
+            let scale = (cols as f64 / width).min(rows as f64 / height);
+            let scaled_width = ((width * scale).round() as u32).clamp(1, target_width);
+            let scaled_height = ((height * scale).round() as u32).clamp(1, target_height);
+            let scaled = resize(&img, scaled_width, scaled_height, options.filter).to_rgba8();
+            let mut canvas = RgbaImage::from_pixel(target_width, target_height, Rgba([0, 0, 0, 0]));
+            image::imageops::overlay(&mut canvas, &scaled, (target_width - scaled_width) / 2, (target_height - scaled_height) / 2);
+            DynamicImage::ImageRgba8(canvas)
+        }
+    })
+}
+
+fn resize(img: &DynamicImage, width: u32, height: u32, filter: ResizeFilter) -> DynamicImage {
+    if (img.width(), img.height()) == (width, height) {
+        return img.clone();
+    }
+    match filter {
+        ResizeFilter::Nearest => img.resize_exact(width, height, FilterType::Nearest),
+        ResizeFilter::Bilinear => img.resize_exact(width, height, FilterType::Triangle),
+        ResizeFilter::Area => DynamicImage::ImageRgba8(resize_area(&img.to_rgba8(), width, height)),
+    }
+}
+
+/// Resizes by averaging the source pixels under each output pixel,
+/// weighted by how much of each one it covers
+fn resize_area(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let scale_x = img.width() as f64 / width as f64;
+    let scale_y = img.height() as f64 / height as f64;
+    // Source pixels overlapping [start, end) along one axis, with their coverage
+    let spans = |start: f64, end: f64, len: u32| -> Vec<(u32, f64)> {
+        let first = start.floor() as u32;
+        let last = (end.ceil() as u32).min(len);
+        (first..last)
+            .map(|i| (i, (end.min(i as f64 + 1.0) - start.max(i as f64)).max(0.0)))
+            .collect()
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let columns = spans(x as f64 * scale_x, (x + 1) as f64 * scale_x, img.width());
+        let rows = spans(y as f64 * scale_y, (y + 1) as f64 * scale_y, img.height());
+        let mut sums = [0.0f64; 4];
+        let mut total = 0.0;
+        for &(src_y, weight_y) in &rows {
+            for &(src_x, weight_x) in &columns {
+                let weight = weight_x * weight_y;
+                for (sum, &value) in sums.iter_mut().zip(img.get_pixel(src_x, src_y).0.iter()) {
+                    *sum += value as f64 * weight;
+                }
+                total += weight;
+            }
+        }
+        Rgba(sums.map(|sum| (sum / total).round() as u8))
+    })
+}
@@ -0,0 +1,151 @@
+use ndarray::Array3;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use oxi_net::augment::{Augmentation, AugmentationPipeline};
+use oxi_net::cnn::Hyperparameters;
+use oxi_net::config::{hyperparameter_errors, NetworkConfig};
+use oxi_net::dataset::InMemoryDataset;
+
+/// A 4x5x2 image whose value at (row, col, channel) is row * 10 + col + channel * 100
+fn ramp() -> Array3<f32> {
+    Array3::from_shape_fn((4, 5, 2), |(row, col, channel)| (row * 10 + col + channel * 100) as f32)
+}
+
+fn apply(step: Augmentation, image: Array3<f32>, seed: u64) -> Array3<f32> {
+    AugmentationPipeline::new().step(step).transform(image, &mut StdRng::seed_from_u64(seed))
+}
+
+#[test]
+fn flips_along_the_right_axis() {
+    let image = ramp();
+    let flipped = apply(Augmentation::HorizontalFlip { probability: 1.0 }, image.clone(), 0);
+    assert_eq!(flipped[[1, 0, 1]], image[[1, 4, 1]]);
+    let flipped = apply(Augmentation::VerticalFlip { probability: 1.0 }, image.clone(), 0);
+    assert_eq!(flipped[[0, 2, 0]], image[[3, 2, 0]]);
+    assert_eq!(apply(Augmentation::HorizontalFlip { probability: 0.0 }, image.clone(), 0), image);
+}
+
+#[test]
+fn shifts_keep_the_shape_and_fill_with_zeros() {
+    let image = ramp();
+    assert_eq!(apply(Augmentation::RandomCrop { padding: 0 }, image.clone(), 0), image);
+    assert_eq!(apply(Augmentation::Rotation { max_degrees: 0.0 }, image.clone(), 0), image);
+    for seed in 0..20 {
+        let cropped = apply(Augmentation::RandomCrop { padding: 2 }, image.clone(), seed);
+        assert_eq!(cropped.dim(), image.dim());
+        // Every value is either padding or an original value
+        assert!(cropped.iter().all(|&x| x == 0.0 || image.iter().any(|&y| y == x)));
+        let translated = apply(Augmentation::Translation { max_fraction: 0.5 }, image.clone(), seed);
+        assert_eq!(translated.dim(), image.dim());
+    }
+}
+
+#[test]
+fn small_rotations_barely_move_pixels() {
+    let mut image = Array3::zeros((3, 3, 1));
+    image[[1, 2, 0]] = 1.0;
+    let rotated = apply(Augmentation::Rotation { max_degrees: 90.0 }, image.clone(), 0);
+    assert_eq!(rotated.dim(), (3, 3, 1));
+    let almost = apply(Augmentation::Rotation { max_degrees: 1e-3 }, image, 0);
+    assert!((almost[[1, 2, 0]] - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn cutout_zeroes_at_most_a_square() {
+    let image = Array3::from_elem((8, 8, 3), 1.0);
+    for seed in 0..20 {
+        let cut = apply(Augmentation::Cutout { size: 3 }, image.clone(), seed);
+        let zeros = cut.iter().filter(|&&x| x == 0.0).count();
+        assert!(zeros > 0 && zeros <= 3 * 3 * 3, "{} zeros", zeros);
+        assert_eq!(zeros % 3, 0);
+    }
+}
+
+#[test]
+fn jitter_and_noise_change_values() {
+    let image = ramp();
+    let jittered = apply(Augmentation::ColorJitter { brightness: 0.5, contrast: 0.5 }, image.clone(), 1);
+    assert_ne!(jittered, image);
+    let noisy = apply(Augmentation::GaussianNoise { std_dev: 0.1 }, image.clone(), 1);
+    let max_change = (&noisy - &image).iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    assert!(max_change > 0.0 && max_change < 1.0);
+}
+
+#[test]
+fn seeded_pipelines_are_reproducible() {
+    let pipeline = AugmentationPipeline::new()
+        .step(Augmentation::RandomCrop { padding: 1 })
+        .step(Augmentation::GaussianNoise { std_dev: 0.5 })
+        .seed(7);
+    let first = pipeline.transform(ramp(), &mut pipeline.rng());
+    assert_eq!(pipeline.transform(ramp(), &mut pipeline.rng()), first);
+    assert_ne!(pipeline.clone().seed(8).transform(ramp(), &mut pipeline.clone().seed(8).rng()), first);
+}
+
+#[test]
+fn mixing_blends_images_and_labels_alike() {
+    // Sample i is filled with i, and has label i
+    let images = (0..3).map(|i| Array3::from_elem((4, 4, 1), i as f32)).collect();
+    let data = InMemoryDataset::new(images, vec![0, 1, 2]).unwrap();
+    for step in [Augmentation::Mixup { alpha: 0.4 }, Augmentation::Cutmix { alpha: 1.0 }] {
+        let pipeline = AugmentationPipeline::new().step(step).seed(3);
+        let mut rng = pipeline.rng();
+        for _ in 0..20 {
+            let (image, desired) = pipeline.augment(&data, 2, 4, &mut rng);
+            assert_eq!(desired.len(), 4);
+            assert!((desired.sum() - 1.0).abs() < 1e-5);
+            // The mean value is the label-weighted mean of the two samples
+            let expected: f32 = desired.iter().enumerate().map(|(i, d)| i as f32 * d).sum();
+            assert!((image.mean().unwrap() - expected).abs() < 1e-4, "{} vs {}", image.mean().unwrap(), expected);
+        }
+    }
+
+    let plain = AugmentationPipeline::new();
+    let (image, desired) = plain.augment(&data, 1, 3, &mut plain.rng());
+    assert_eq!(image, data.images[1]);
+    assert_eq!(desired.to_vec(), vec![0.0, 1.0, 0.0]);
+}
+
+#[test]
+fn reports_invalid_steps() {
+    let params = Hyperparameters {
+        augmentation: AugmentationPipeline::new()
+            .step(Augmentation::HorizontalFlip { probability: 0.5 })
+            .step(Augmentation::VerticalFlip { probability: 1.5 })
+            .step(Augmentation::Mixup { alpha: 0.0 })
+            .step(Augmentation::Rotation { max_degrees: f32::NAN })
+            .step(Augmentation::Rotation { max_degrees: f32::INFINITY })
+            .step(Augmentation::Rotation { max_degrees: -15.0 }),
+        ..Hyperparameters::default()
+    };
+    assert_eq!(hyperparameter_errors(&params), vec![
+        "Augmentation 1: probability must be in [0, 1], got 1.5",
+        "Augmentation 2: alpha must be positive, got 0",
+        "Augmentation 3: max_degrees must be finite, got NaN",
+        "Augmentation 4: max_degrees must be finite, got inf",
+    ]);
+}
+
+#[test]
+fn reads_augmentation_from_toml() {
+    let config = NetworkConfig::from_toml(r#"
+        input_shape = [4, 4, 1]
+        [[layers]]
+        type = "dense"
+        output_size = 2
+        activation = "softmax"
+
+        [hyperparameters.augmentation]
+        seed = 42
+        [[hyperparameters.augmentation.steps]]
+        type = "random_crop"
+        padding = 2
+        [[hyperparameters.augmentation.steps]]
+        type = "cutmix"
+        alpha = 1.0
+    "#).unwrap();
+    assert_eq!(config.hyperparameters.augmentation, AugmentationPipeline::new()
+        .step(Augmentation::RandomCrop { padding: 2 })
+        .step(Augmentation::Cutmix { alpha: 1.0 })
+        .seed(42));
+}
@@ -0,0 +1,115 @@
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::layer::Layer;
+use oxi_net::loss::Loss;
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::util::TrainingData;
+
+#[test]
+fn builds_valid_network() {
+    let builder = CnnBuilder::new()
+        .input_shape(vec![28, 28, 1])
+        .conv(8, 3)
+        .mxpl(2)
+        .dense(64, Activation::Relu, Some(0.25))
+        .dense(10, Activation::Softmax, None)
+        .optimizer(OptimizerAlg::SGD(0.1));
+    assert_eq!(builder.output_shapes().unwrap(), vec![(26, 26, 8), (13, 13, 8), (64, 1, 1), (10, 1, 1)]);
+
+    let cnn = builder.build(TrainingData::default()).unwrap();
+    assert_eq!(cnn.layers().len(), 4);
+    match &cnn.layers()[2] {
+        Layer::Dense(dense_layer) => assert_eq!(dense_layer.weights.dim(), (64, 13 * 13 * 8)),
+        _ => panic!("Layer 2 is not a DenseLayer"),
+    }
+}
+
+#[test]
+fn reports_every_error_with_layer_indices() {
+    let err = CnnBuilder::new()
+        .conv(0, 3)
+        .dense(16, Activation::Relu, Some(1.5))
+        .mxpl(2)
+        .dense(10, Activation::Softmax, None)
+        .batch_size(0)
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    let expected = [
+        "Input shape not set",
+        "Layer 0: num_filters must be at least 1",
+        "Layer 1: dropout must be in [0, 1), got 1.5",
+        "Layer 2: Max Pooling Layer cannot follow a Dense Layer",
+        "batch_size must be at least 1",
+    ];
+    for message in expected {
+        assert!(err.contains(message), "missing {:?} in:\n{}", message, err);
+    }
+    assert_eq!(err.lines().count(), expected.len(), "{}", err);
+}
+
+#[test]
+fn rejects_kernel_larger_than_input() {
+    let err = CnnBuilder::new()
+        .input_shape(vec![8, 8, 1])
+        .conv(4, 3)
+        .conv(4, 7)
+        .dense(10, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    assert_eq!(err, "Layer 1: kernel_size 7 is larger than the 6x6 input");
+}
+
+#[test]
+fn rejects_mean_squared_error_with_softmax_and_non_finite_learning_rates() {
+    let network = || CnnBuilder::new().input_shape(vec![4, 4, 1]);
+    let err = network()
+        .dense(2, Activation::Softmax, None)
+        .loss(Loss::MeanSquaredError)
+        .optimizer(OptimizerAlg::SGD(f32::NAN))
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    assert_eq!(err, [
+        "Learning rate must be positive and finite, got NaN",
+        "Mean squared error loss cannot be used with a Softmax output layer, use cross entropy or a Sigmoid output layer",
+    ].join("\n"));
+
+    let err = network().dense(2, Activation::Softmax, None).optimizer(OptimizerAlg::Adam(f32::INFINITY, 0.9, 0.999)).output_shapes().unwrap_err();
+    assert_eq!(err, "Learning rate must be positive and finite, got inf");
+    assert!(network().dense(2, Activation::Sigmoid, None).loss(Loss::MeanSquaredError).build(TrainingData::default()).is_ok());
+}
+
+#[test]
+fn rejects_too_few_outputs_for_the_data() {
+    let data = TrainingData {
+        classes: (0..5).map(|i| (i, i)).collect(),
+        ..TrainingData::default()
+    };
+    let err = CnnBuilder::new()
+        .input_shape(vec![4])
+        .dense(3, Activation::Softmax, None)
+        .build(data)
+        .err()
+        .unwrap();
+    assert!(err.contains("Layer 0: has 3 outputs but the training data has 5 classes"), "{}", err);
+}
+
+#[test]
+fn supports_non_square_inputs() {
+    let cnn = CnnBuilder::new()
+        .input_shape(vec![12, 8, 2])
+        .conv(3, 3)
+        .mxpl(2)
+        .dense(4, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap();
+    match &cnn.layers()[1] {
+        Layer::Mxpl(mxpl_layer) => assert_eq!(mxpl_layer.output_size, (5, 3, 3)),
+        _ => panic!("Layer 1 is not a MxplLayer"),
+    }
+    let output = cnn.into_predictor().predict_proba(&Array3::from_elem((12, 8, 2), 0.5));
+    assert!((output.sum() - 1.0).abs() < 1e-5);
+}
@@ -0,0 +1,84 @@
+use oxi_net::cifar::{load_cifar10, load_cifar100, parse_batch, Cifar100Labels};
+use oxi_net::util::TrainImage;
+
+mod common;
+use common::temp_dir;
+
+const IMAGE_BYTES: usize = 32 * 32 * 3;
+
+/// A record whose pixel at (row, col) in channel c is (c * 100 + row + col) % 256
+fn record(labels: &[u8]) -> Vec<u8> {
+    let mut bytes = labels.to_vec();
+    for c in 0..3 {
+        for row in 0..32 {
+            for col in 0..32 {
+                bytes.push(((c * 100 + row + col) % 256) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn parses_planar_pixels() {
+    let mut bytes = record(&[3]);
+    bytes.extend(record(&[7]));
+    let images = parse_batch(&bytes, 1, 0).unwrap();
+    assert_eq!(images.len(), 2);
+    let (image, label) = &images[0];
+    assert_eq!(*label, 3);
+    assert_eq!(images[1].1, 7);
+    assert_eq!(image.dim(), (32, 32, 3));
+    assert_eq!(image[[0, 5, 0]], 5.0 / 255.0);
+    assert_eq!(image[[2, 1, 1]], 103.0 / 255.0);
+    assert_eq!(image[[31, 30, 2]], 261.0 % 256.0 / 255.0);
+}
+
+#[test]
+fn rejects_truncated_batches() {
+    let bytes = record(&[1]);
+    let err = parse_batch(&bytes[..IMAGE_BYTES], 1, 0).err().unwrap();
+    assert!(err.contains("not a multiple of the 3073 byte record size"), "{}", err);
+}
+
+#[test]
+fn loads_cifar10_folders() {
+    let dir = temp_dir("10");
+    for i in 1..=5 {
+        std::fs::write(dir.join(format!("data_batch_{}.bin", i)), [record(&[i]), record(&[0])].concat()).unwrap();
+    }
+    std::fs::write(dir.join("test_batch.bin"), record(&[9])).unwrap();
+
+    let data = load_cifar10(&dir).unwrap();
+    assert_eq!((data.trn_size, data.tst_size), (10, 1));
+    assert_eq!(data.trn_lbl[..4], [1, 0, 2, 0]);
+    assert_eq!(data.classes.len(), 10);
+    assert_eq!(data.class_name(data.tst_lbl[0]).unwrap(), "truck");
+    assert!(matches!(&data.tst_img[0], TrainImage::Image(image) if image.dim() == (32, 32, 3)));
+
+    std::fs::remove_file(dir.join("data_batch_3.bin")).unwrap();
+    let err = load_cifar10(&dir).err().unwrap();
+    assert!(err.contains("data_batch_3.bin"), "{}", err);
+}
+
+#[test]
+fn loads_cifar100_coarse_or_fine_labels() {
+    let dir = temp_dir("100");
+    std::fs::write(dir.join("train.bin"), [record(&[4, 30]), record(&[19, 99])].concat()).unwrap();
+    std::fs::write(dir.join("test.bin"), record(&[0, 0])).unwrap();
+
+    let fine = load_cifar100(&dir, Cifar100Labels::Fine).unwrap();
+    assert_eq!(fine.trn_lbl, vec![30, 99]);
+    assert_eq!(fine.classes.len(), 100);
+    assert_eq!(fine.class_name(30).unwrap(), "dolphin");
+    assert_eq!(fine.class_name(99).unwrap(), "worm");
+
+    let coarse = load_cifar100(&dir, Cifar100Labels::Coarse).unwrap();
+    assert_eq!(coarse.trn_lbl, vec![4, 19]);
+    assert_eq!(coarse.classes.len(), 20);
+    assert_eq!(coarse.class_name(19).unwrap(), "vehicles_2");
+
+    std::fs::write(dir.join("test.bin"), record(&[20, 0])).unwrap();
+    let err = load_cifar100(&dir, Cifar100Labels::Coarse).err().unwrap();
+    assert_eq!(err, "Label 20 is out of range for 20 classes");
+}
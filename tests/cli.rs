@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::process::{Command, Output};
+use image::{Rgb, RgbImage};
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::util::TrainingData;
+
+mod common;
+use common::temp_dir;
+
+fn oxi_net(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_oxi_net")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn summarizes_configs_and_models() {
+    let output = oxi_net(&["summary", "configs/mnist.toml"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Total params: 182162"), "{}", stdout(&output));
+
+    let output = oxi_net(&["summary", "tests/fixtures/model_v3.json", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let summary: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(summary["input_shape"], serde_json::json!([6, 6, 1]));
+}
+
+#[test]
+fn converts_between_formats() {
+    let dir = temp_dir("convert");
+    let binary = dir.join("model.bin");
+    let output = oxi_net(&["convert", "tests/fixtures/model_v3.json", binary.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let json = oxi_net(&["summary", "tests/fixtures/model_v3.json"]);
+    let converted = oxi_net(&["summary", binary.to_str().unwrap()]);
+    assert_eq!(stdout(&converted), stdout(&json));
+
+    let output = oxi_net(&["convert", "tests/fixtures/model_v3.json", dir.join("model.onnx").to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--format"), "{}", stderr(&output));
+}
+
+#[test]
+fn predicts_images_in_a_directory() {
+    let dir = temp_dir("predict");
+    let model = dir.join("model.json");
+    CnnBuilder::new()
+        .input_shape(vec![6, 6, 3])
+        .conv(2, 3)
+        .dense(4, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap()
+        .save_to(&model)
+        .unwrap();
+    let images = dir.join("images");
+    std::fs::create_dir_all(&images).unwrap();
+    RgbImage::from_fn(6, 6, |x, y| Rgb([(x * 40) as u8, (y * 40) as u8, 0])).save(images.join("a.png")).unwrap();
+    RgbImage::from_pixel(6, 6, Rgb([255, 255, 255])).save(images.join("b.png")).unwrap();
+    std::fs::write(images.join("notes.txt"), "not an image").unwrap();
+
+    let output = oxi_net(&["predict", "-m", model.to_str().unwrap(), images.to_str().unwrap(), "-k", "2", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let predictions: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(predictions.as_array().unwrap().len(), 2);
+    assert_eq!(predictions[0]["top_k"].as_array().unwrap().len(), 2);
+
+    // Images of the wrong size are reported, and the command fails
+    RgbImage::new(8, 8).save(images.join("c.png")).unwrap();
+    let output = oxi_net(&["predict", "-m", model.to_str().unwrap(), images.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("c.png: image has shape (8, 8, 3) but the model expects (6, 6, 3)"), "{}", stderr(&output));
+    assert_eq!(stdout(&output).lines().count(), 2);
+}
+
+#[test]
+fn predicts_with_more_outputs_than_named_classes() {
+    let dir = temp_dir("predict_labels");
+    let model = dir.join("model.json");
+    let data = TrainingData {
+        classes: HashMap::from([(0, 0), (1, 1)]),
+        label_names: HashMap::from([(0, String::from("cat")), (1, String::from("dog"))]),
+        ..TrainingData::default()
+    };
+    CnnBuilder::new()
+        .input_shape(vec![6, 6, 3])
+        .dense(4, Activation::Softmax, None)
+        .build(data)
+        .unwrap()
+        .save_to(&model)
+        .unwrap();
+    let image = dir.join("a.png");
+    RgbImage::from_fn(6, 6, |x, y| Rgb([(x * 40) as u8, (y * 40) as u8, 0])).save(&image).unwrap();
+
+    let output = oxi_net(&["predict", "-m", model.to_str().unwrap(), image.to_str().unwrap(), "-k", "4", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let predictions: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let mut labels: Vec<(u64, Option<&str>)> = predictions[0]["top_k"].as_array().unwrap().iter()
+        .map(|p| (p["class"].as_u64().unwrap(), p["label"].as_str()))
+        .collect();
+    labels.sort();
+    assert_eq!(labels, vec![(0, Some("cat")), (1, Some("dog")), (2, None), (3, None)]);
+
+    let output = oxi_net(&["predict", "-m", model.to_str().unwrap(), image.to_str().unwrap(), "-k", "4"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let line = stdout(&output);
+    assert!(line.contains("cat (") && line.contains("dog (") && line.contains("2 (") && line.contains("3 ("), "{}", line);
+}
+
+#[test]
+fn cross_validates_a_config() {
+    let dir = temp_dir("cross_validate");
+    for (class, shade) in [("dark", 0), ("light", 255)] {
+        std::fs::create_dir_all(dir.join("images").join(class)).unwrap();
+        for i in 0..6 {
+            RgbImage::from_pixel(4, 4, Rgb([shade, shade, i * 10])).save(dir.join("images").join(class).join(format!("{}.png", i))).unwrap();
+        }
+    }
+    let config = dir.join("config.json");
+    std::fs::write(&config, r#"{
+        "input_shape": [4, 4, 3],
+        "layers": [{"type": "dense", "output_size": 2, "activation": "softmax"}],
+        "hyperparameters": {"epochs": 1, "verbose": false}
+    }"#).unwrap();
+
+    let output = oxi_net(&[
+        "cross-validate", "-c", config.to_str().unwrap(), "-d", dir.join("images").to_str().unwrap(),
+        "--dataset", "image-folder", "-k", "2", "--json",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let results: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(results["folds"].as_array().unwrap().len(), 2);
+    assert!(results["accuracy"]["std"].is_number());
+}
+
+#[test]
+fn searches_over_a_config() {
+    let dir = temp_dir("search");
+    for (class, shade) in [("dark", 0), ("light", 255)] {
+        std::fs::create_dir_all(dir.join("images").join(class)).unwrap();
+        for i in 0..10 {
+            RgbImage::from_pixel(4, 4, Rgb([shade, shade, i * 10])).save(dir.join("images").join(class).join(format!("{}.png", i))).unwrap();
+        }
+    }
+    let config = dir.join("config.json");
+    std::fs::write(&config, r#"{
+        "input_shape": [4, 4, 3],
+        "layers": [{"type": "dense", "output_size": 2, "activation": "softmax"}],
+        "hyperparameters": {"epochs": 1, "verbose": false}
+    }"#).unwrap();
+    let search = dir.join("search.toml");
+    std::fs::write(&search, "[[parameters]]\npath = \"hyperparameters.batch_size\"\nrange = { type = \"int_range\", min = 1, max = 2 }\n").unwrap();
+
+    let output = oxi_net(&[
+        "search", "-c", config.to_str().unwrap(), "-s", search.to_str().unwrap(),
+        "-d", dir.join("images").to_str().unwrap(), "--dataset", "image-folder", "-o", dir.join("out").to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Trial 1: "), "{}", stdout(&output));
+    assert_eq!(std::fs::read_to_string(dir.join("out").join("leaderboard.csv")).unwrap().lines().count(), 3);
+    assert!(dir.join("out").join("best_model.json").exists());
+}
+
+#[test]
+fn reports_errors_with_exit_codes() {
+    let output = oxi_net(&["summary", "does/not/exist.json"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("error: "), "{}", stderr(&output));
+
+    let output = oxi_net(&["eval", "-m", "tests/fixtures/model_v3.json", "-d", "does/not/exist"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("does not exist"), "{}", stderr(&output));
+
+    // Invalid arguments
+    let output = oxi_net(&["train"]);
+    assert_eq!(output.status.code(), Some(2));
+}
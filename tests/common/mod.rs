@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+/// An empty directory for one test, named after the test file and `name`,
+/// replacing any left over from an earlier run
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oxi_net_{}_{}_{}", env!("CARGO_CRATE_NAME"), name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,124 @@
+use oxi_net::cnn::CNN;
+use oxi_net::config::NetworkConfig;
+use oxi_net::layer::Layer;
+use oxi_net::loss::Loss;
+use oxi_net::optimizer::LrSchedule;
+use oxi_net::util::TrainingData;
+
+fn output_sizes(cnn: &CNN) -> Vec<(usize, usize, usize)> {
+    cnn.layers().iter().map(|layer| match layer {
+        Layer::Conv(conv_layer) => conv_layer.output_size,
+        Layer::Mxpl(mxpl_layer) => mxpl_layer.output_size,
+        Layer::Dense(dense_layer) => (dense_layer.output_size, 1, 1),
+    }).collect()
+}
+
+#[test]
+fn builds_example_configs() {
+    let expected = vec![(26, 26, 8), (13, 13, 8), (128, 1, 1), (64, 1, 1), (10, 1, 1)];
+    for path in ["configs/mnist.toml", "configs/mnist.json"] {
+        let cnn = CNN::from_config(path, TrainingData::default()).unwrap();
+        assert_eq!(cnn.input_shape(), (28, 28, 1), "{}", path);
+        assert_eq!(output_sizes(&cnn), expected, "{}", path);
+    }
+
+    let toml = NetworkConfig::load("configs/mnist.toml").unwrap();
+    let json = NetworkConfig::load("configs/mnist.json").unwrap();
+    assert_eq!(toml.layers, json.layers);
+    assert_eq!(toml.hyperparameters.loss, Loss::CrossEntropy);
+    assert_eq!(toml.hyperparameters.lr_schedule, LrSchedule::Step { step_size: 5, gamma: 0.5 });
+    assert_eq!(toml.hyperparameters.optimizer.learning_rate(), 0.1);
+}
+
+#[test]
+fn round_trips_through_toml() {
+    let config = NetworkConfig::load("configs/mnist.json").unwrap();
+    let toml = NetworkConfig::from_toml(&config.to_toml().unwrap()).unwrap();
+    assert_eq!(toml.to_json(), config.to_json());
+}
+
+#[test]
+fn reports_every_problem() {
+    let config = NetworkConfig::from_json(r#"{
+        "input_shape": [8, 8, 1],
+        "layers": [
+            {"type": "conv", "num_filters": 4, "kernel_size": 3},
+            {"type": "mxpl", "kernel_size": 9},
+            {"type": "dense", "output_size": 10, "activation": "softmax"}
+        ],
+        "hyperparameters": {"batch_size": 0, "optimizer": {"type": "sgd", "lr": -0.1}}
+    }"#).unwrap();
+    let err = CNN::from_network_config(&config, TrainingData::default()).err().unwrap();
+    assert!(err.contains("Layer 1: kernel_size 9 is larger than the 6x6 input"), "{}", err);
+    assert!(err.contains("batch_size must be at least 1"), "{}", err);
+    assert!(err.contains("Learning rate must be positive"), "{}", err);
+}
+
+#[test]
+fn rejects_invalid_layer_order() {
+    let config = NetworkConfig::from_toml(r#"
+        input_shape = [8, 8]
+        layers = [
+            { type = "dense", output_size = 16, activation = "relu" },
+            { type = "conv", num_filters = 4, kernel_size = 3 },
+        ]
+    "#).unwrap();
+    let err = config.validate().unwrap_err();
+    assert!(err.contains("Layer 1: Convolutional Layer cannot follow a Dense Layer"), "{}", err);
+    assert!(err.contains("The last layer must be a dense layer"), "{}", err);
+}
+
+#[test]
+fn writes_every_enum_in_snake_case() {
+    let config = NetworkConfig::load("configs/mnist.json").unwrap();
+    let value: serde_json::Value = serde_json::from_str(&config.to_json()).unwrap();
+    let params = &value["hyperparameters"];
+    assert_eq!(value["layers"][2]["activation"], "relu");
+    assert_eq!(params["optimizer"], serde_json::json!({"type": "sgd", "lr": 0.1}));
+    assert_eq!(params["loss"], "cross_entropy");
+    assert_eq!(params["lr_schedule"], serde_json::json!({"type": "step", "step_size": 5, "gamma": 0.5}));
+    assert_eq!(params["saving_strategy"], serde_json::json!({"type": "never"}));
+    assert_eq!(params["model_format"], "json");
+
+    let err = NetworkConfig::from_json(r#"{
+        "input_shape": [4],
+        "layers": [{"type": "dense", "output_size": 2, "activation": "softmax"}],
+        "hyperparameters": {"optimizer": {"SGD": 0.1}}
+    }"#).err().unwrap();
+    assert!(err.contains("missing field `type`"), "{}", err);
+}
+
+#[test]
+fn rejects_nan_learning_rates_and_mean_squared_error_with_softmax() {
+    let config = NetworkConfig::from_toml(r#"
+        input_shape = [4]
+        layers = [{ type = "dense", output_size = 2, activation = "softmax" }]
+        [hyperparameters]
+        optimizer = { type = "momentum", lr = nan, momentum = 0.9 }
+        loss = "mean_squared_error"
+    "#).unwrap();
+    assert_eq!(config.validate().unwrap_err(), [
+        "Learning rate must be positive and finite, got NaN",
+        "Mean squared error loss cannot be used with a Softmax output layer, use cross entropy or a Sigmoid output layer",
+    ].join("\n"));
+}
+
+#[test]
+fn rejects_unknown_fields() {
+    let err = NetworkConfig::from_toml(r#"
+        input_shape = [8, 8]
+        layers = [{ type = "dense", output_size = 2, activation = "softmax", units = 3 }]
+    "#).err().unwrap();
+    assert!(err.contains("units"), "{}", err);
+}
+
+#[test]
+fn schedules_learning_rate() {
+    let step = LrSchedule::Step { step_size: 2, gamma: 0.5 };
+    let rates: Vec<f32> = (0..5).map(|epoch| step.learning_rate(0.1, epoch, 5)).collect();
+    assert_eq!(rates, vec![0.1, 0.1, 0.05, 0.05, 0.025]);
+
+    let cosine = LrSchedule::Cosine { min_lr: 0.0 };
+    assert_eq!(cosine.learning_rate(0.1, 0, 5), 0.1);
+    assert!(cosine.learning_rate(0.1, 4, 5).abs() < 1e-6);
+}
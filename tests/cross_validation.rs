@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::Hyperparameters;
+use oxi_net::cross_validation::{cross_validate, stratified_folds, Summary};
+use oxi_net::dataset::{InMemoryDataset, Label};
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::sampler::Sampler;
+use oxi_net::util::{ImageOptions, TrainImage, TrainingData};
+
+/// Class 0 lights the top half of a 4x4 image, class 1 the bottom half
+fn image(label: Label) -> Array3<f32> {
+    Array3::from_shape_fn((4, 4, 1), |(row, _, _)| ((row < 2) == (label == 0)) as usize as f32)
+}
+
+/// 45 samples of class 0 and 15 of class 1 for training, and a few held out
+fn data() -> TrainingData {
+    let trn_lbl: Vec<usize> = (0..60).map(|i| (i % 4 == 0) as usize).collect();
+    let tst_lbl = vec![0, 1];
+    TrainingData {
+        trn_img: trn_lbl.iter().map(|&l| TrainImage::Image(image(l))).collect(),
+        tst_img: tst_lbl.iter().map(|&l| TrainImage::Image(image(l))).collect(),
+        trn_size: trn_lbl.len(),
+        tst_size: tst_lbl.len(),
+        trn_lbl,
+        tst_lbl,
+        rows: 4,
+        cols: 4,
+        classes: HashMap::from([(0, 0), (1, 1)]),
+        label_names: HashMap::new(),
+        image_options: ImageOptions::default(),
+    }
+}
+
+#[test]
+fn folds_are_stratified_and_cover_every_sample_once() {
+    let labels: Vec<Label> = (0..40).map(|i| (i >= 30) as usize).collect();
+    let dataset = InMemoryDataset::new(vec![Array3::zeros((1, 1, 1)); 40], labels.clone()).unwrap();
+    let folds = stratified_folds(&dataset, 5, 3).unwrap();
+
+    assert_eq!(folds.len(), 5);
+    for fold in &folds {
+        assert_eq!(fold.iter().filter(|&&i| labels[i] == 0).count(), 6);
+        assert_eq!(fold.iter().filter(|&&i| labels[i] == 1).count(), 2);
+        assert!(fold.windows(2).all(|w| w[0] < w[1]));
+    }
+    let mut all: Vec<usize> = folds.concat();
+    all.sort_unstable();
+    assert_eq!(all, (0..40).collect::<Vec<_>>());
+
+    assert_eq!(stratified_folds(&dataset, 5, 3).unwrap(), folds);
+    assert_ne!(stratified_folds(&dataset, 5, 4).unwrap(), folds);
+    assert_eq!(stratified_folds(&dataset, 1, 0).err().unwrap(), "Cross-validation needs at least 2 folds, got 1");
+    assert_eq!(stratified_folds(&dataset, 41, 0).err().unwrap(), "Cannot split 40 samples into 41 folds");
+}
+
+#[test]
+fn summarizes_with_sample_standard_deviation() {
+    let summary = Summary::of(&[0.5, 1.0, 0.75]);
+    assert!((summary.mean - 0.75).abs() < 1e-6);
+    assert!((summary.std - 0.25).abs() < 1e-6);
+    assert_eq!(Summary::of(&[0.5]), Summary { mean: 0.5, std: 0.0 });
+}
+
+fn builder() -> CnnBuilder {
+    CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(8, Activation::Relu, None)
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            batch_size: 4,
+            epochs: 5,
+            optimizer: OptimizerAlg::SGD(0.1),
+            verbose: false,
+            ..Hyperparameters::default()
+        })
+}
+
+#[test]
+fn trains_a_fresh_network_per_fold() {
+    let results = cross_validate(&builder(), &data(), 3).unwrap();
+    assert_eq!(results.folds.len(), 3);
+    for (i, fold) in results.folds.iter().enumerate() {
+        assert_eq!((fold.fold, fold.train_size, fold.test_size), (i, 40, 20));
+        assert_eq!(fold.metrics.total, 20);
+        // Stratified: a quarter of every fold is class 1
+        assert_eq!(fold.metrics.support(1), 5);
+    }
+    assert_eq!(results.accuracy, Summary { mean: 1.0, std: 0.0 });
+    assert_eq!(results.balanced_accuracy.mean, 1.0);
+    let report = format!("{:?}", results);
+    assert!(report.contains("Accuracy: 1.0000 ± 0.0000"), "{}", report);
+}
+
+#[test]
+fn reports_invalid_setups() {
+    let errors = cross_validate(&builder().input_shape(vec![5, 5, 1]), &data(), 3).err().unwrap();
+    assert_eq!(errors, "Images have shape (4, 4, 1) but the network input shape is (5, 5, 1)");
+    let errors = cross_validate(&builder().batch_size(0), &data(), 3).err().unwrap();
+    assert!(errors.contains("batch_size"), "{}", errors);
+    assert!(cross_validate(&builder(), &data(), 61).is_err());
+}
+
+#[test]
+fn checks_the_data_before_any_fold() {
+    let one_output = CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(1, Activation::Sigmoid, None)
+        .hyperparameters(Hyperparameters { verbose: false, ..Hyperparameters::default() });
+    let errors = cross_validate(&one_output, &data(), 3).err().unwrap();
+    assert_eq!(errors, "Layer 0: has 1 outputs but the training data has 2 classes");
+
+    let errors = cross_validate(&builder().sampler(Sampler::Weighted { class_weights: vec![0.0, 0.0] }), &data(), 3).err().unwrap();
+    assert_eq!(errors, "Sampler class weights are zero for every class in the training data");
+}
+
+#[test]
+fn reports_a_sampler_that_cannot_draw_from_a_fold() {
+    // The only sample of class 1 is left out of the training folds once
+    let mut data = data();
+    data.trn_lbl = (0..60).map(|i| (i == 0) as usize).collect();
+    data.trn_img = data.trn_lbl.iter().map(|&l| TrainImage::Image(image(l))).collect();
+    let builder = builder().sampler(Sampler::Weighted { class_weights: vec![0.0, 1.0] });
+
+    let errors = cross_validate(&builder, &data, 2).err().unwrap();
+    assert!(errors.starts_with("Fold "), "{}", errors);
+    assert!(errors.ends_with(": Failed to set up the sampler: Sampler class weights are zero for every class in the training data"), "{}", errors);
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::Hyperparameters;
+use oxi_net::dataset::{Dataset, InMemoryDataset, Label};
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::util::{ImageOptions, TrainImage, TrainingData};
+
+/// Generates 4x4 images on the fly: class 0 lights the top half, class 1 the bottom half
+struct Halves {
+    len: usize,
+}
+
+impl Dataset for Halves {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        let label = i % 2;
+        let image = Array3::from_shape_fn((4, 4, 1), |(row, _, _)| {
+            if (row < 2) == (label == 0) { 1.0 } else { 0.0 }
+        });
+        (image, label)
+    }
+}
+
+fn builder() -> CnnBuilder {
+    CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(8, Activation::Relu, None)
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            batch_size: 4,
+            epochs: 5,
+            optimizer: OptimizerAlg::SGD(0.1),
+            verbose: false,
+            ..Hyperparameters::default()
+        })
+}
+
+#[test]
+fn trains_and_evaluates_on_a_generated_dataset() {
+    let mut cnn = builder().build(TrainingData::default()).unwrap();
+    let train = Halves { len: 200 };
+    let test = Halves { len: 20 };
+    cnn.train_on(&train, Some(&test)).unwrap();
+
+    let metrics = cnn.evaluate(&test);
+    assert_eq!(metrics.total, 20);
+    assert_eq!(metrics.accuracy(), 1.0);
+}
+
+#[test]
+fn in_memory_dataset_checks_lengths() {
+    let image = Array3::zeros((4, 4, 1));
+    assert!(InMemoryDataset::new(vec![image.clone()], vec![0, 1]).is_err());
+    let dataset = InMemoryDataset::new(vec![image.clone(), image], vec![0, 1]).unwrap();
+    assert_eq!(dataset.len(), 2);
+    assert_eq!(dataset.get(1).1, 1);
+}
+
+#[test]
+fn training_data_splits_map_labels_to_classes() {
+    let image = |value: f32| TrainImage::Image(Array3::from_elem((2, 2, 1), value));
+    // Only labels 3 and 7 are trained on, as classes 0 and 1
+    let data = TrainingData {
+        trn_img: vec![image(0.0), image(1.0), image(2.0)],
+        trn_lbl: vec![7, 5, 3],
+        tst_img: vec![image(3.0)],
+        tst_lbl: vec![3],
+        rows: 2,
+        cols: 2,
+        trn_size: 3,
+        tst_size: 1,
+        classes: HashMap::from([(3, 0), (7, 1)]),
+        label_names: HashMap::new(),
+        image_options: ImageOptions::default(),
+    };
+
+    let train = data.train_set();
+    assert_eq!(train.len(), 2);
+    let (first, label) = train.get(0);
+    assert_eq!((first[[0, 0, 0]], label), (0.0, 1));
+    let (second, label) = train.get(1);
+    assert_eq!((second[[0, 0, 0]], label), (2.0, 0));
+    assert_eq!(data.test_set().get(0).1, 0);
+}
@@ -0,0 +1,39 @@
+use std::collections::BTreeSet;
+use oxi_net::fiftystates::load_50states10k;
+use oxi_net::util::TrainImage;
+
+mod common;
+use common::temp_dir;
+
+/// Locations whose rotations are written as `{location}_{rotation}.jpg`
+fn locations(images: &[TrainImage]) -> Vec<String> {
+    images
+        .iter()
+        .map(|image| match image {
+            TrainImage::Path(path) => path.file_name().unwrap().to_str().unwrap().split('_').next().unwrap().to_string(),
+            _ => panic!("Expected a path"),
+        })
+        .collect()
+}
+
+#[test]
+fn splits_every_rotation_of_a_location_the_same_way_on_every_platform() {
+    let root = temp_dir("rotations");
+    for state in ["Alabama", "Alaska"] {
+        std::fs::create_dir_all(root.join(state)).unwrap();
+        for location in ["harbor", "canyon", "prairie", "glacier", "bayou", "mesa", "forest", "dunes"] {
+            for rotation in [0, 90, 180, 270] {
+                std::fs::write(root.join(state).join(format!("{}_{}.jpg", location, rotation)), []).unwrap();
+            }
+        }
+    }
+
+    let data = load_50states10k(&root, 0.5, Some(vec!["Alabama", "Alaska"])).unwrap();
+    let train: BTreeSet<String> = locations(&data.trn_img).into_iter().collect();
+    let test: BTreeSet<String> = locations(&data.tst_img).into_iter().collect();
+
+    assert!(train.is_disjoint(&test));
+    assert_eq!(train, ["canyon", "forest", "mesa"].iter().map(|s| s.to_string()).collect());
+    assert_eq!(data.trn_size, 2 * 3 * 4);
+    assert_eq!(data.tst_size, 2 * 5 * 4);
+}
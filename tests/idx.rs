@@ -0,0 +1,164 @@
+use std::io::Write;
+use std::path::Path;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use oxi_net::idx::{parse_idx, read_idx, IdxType};
+use oxi_net::mnist::{load_emnist, load_fashion_mnist, load_mnist, EmnistSplit};
+use oxi_net::util::TrainImage;
+
+mod common;
+use common::temp_dir;
+
+/// An unsigned byte IDX file with the given dimensions and values
+fn idx_bytes(dims: &[u32], values: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0, 0, 0x08, dims.len() as u8];
+    for dim in dims {
+        bytes.extend(dim.to_be_bytes());
+    }
+    bytes.extend(values);
+    bytes
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes `count` images of `rows` x `cols` whose pixel i is `i + image index`,
+/// and their labels, under the given file names
+fn write_split(dir: &Path, images: &str, labels: &str, rows: u32, cols: u32, label_values: &[u8], gzipped: bool) {
+    let count = label_values.len() as u32;
+    let pixels: Vec<u8> = (0..count).flat_map(|n| (0..rows * cols).map(move |i| (i + n) as u8)).collect();
+    let files = [
+        (images, idx_bytes(&[count, rows, cols], &pixels)),
+        (labels, idx_bytes(&[count], label_values)),
+    ];
+    for (name, bytes) in files {
+        if gzipped {
+            std::fs::write(dir.join(format!("{}.gz", name)), gzip(&bytes)).unwrap();
+        } else {
+            std::fs::write(dir.join(name), bytes).unwrap();
+        }
+    }
+}
+
+fn write_mnist(dir: &Path, rows: u32, cols: u32, gzipped: bool) {
+    write_split(dir, "train-images-idx3-ubyte", "train-labels-idx1-ubyte", rows, cols, &[0, 1, 9], gzipped);
+    write_split(dir, "t10k-images-idx3-ubyte", "t10k-labels-idx1-ubyte", rows, cols, &[5, 3], gzipped);
+}
+
+fn image(image: &TrainImage) -> &ndarray::Array3<f32> {
+    match image {
+        TrainImage::Image(image) => image,
+        TrainImage::Path(_) => panic!("Expected a decoded image"),
+    }
+}
+
+#[test]
+fn parses_header_and_typed_values() {
+    let array = parse_idx(&idx_bytes(&[2, 3], &[1, 2, 3, 4, 5, 6])).unwrap();
+    assert_eq!(array.data_type, IdxType::UnsignedByte);
+    assert_eq!(array.dims, vec![2, 3]);
+    assert_eq!(array.data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+    let mut floats = vec![0, 0, 0x0D, 1, 0, 0, 0, 2];
+    floats.extend(1.5f32.to_be_bytes());
+    floats.extend((-2.0f32).to_be_bytes());
+    let array = parse_idx(&floats).unwrap();
+    assert_eq!(array.data_type, IdxType::Float);
+    assert_eq!(array.data, vec![1.5, -2.0]);
+
+    let shorts = [0, 0, 0x0B, 1, 0, 0, 0, 1, 0xff, 0xfe];
+    assert_eq!(parse_idx(&shorts).unwrap().data, vec![-2.0]);
+}
+
+#[test]
+fn rejects_malformed_files() {
+    assert!(parse_idx(&[1, 0, 8, 1]).is_err());
+    assert!(parse_idx(&[0, 0, 0x42, 1, 0, 0, 0, 0]).err().unwrap().contains("data type"));
+    assert!(parse_idx(&[0, 0, 8, 2, 0, 0]).err().unwrap().contains("Truncated"));
+    let short = idx_bytes(&[2, 2], &[1, 2, 3]);
+    assert!(parse_idx(&short).err().unwrap().contains("should be 16 bytes"));
+
+    // A zero dimension would leave no pixels per image
+    assert_eq!(parse_idx(&idx_bytes(&[2, 0, 3], &[])).err().unwrap(), "IDX file has dimensions [2, 0, 3], which cannot contain 0");
+    let huge = idx_bytes(&[u32::MAX, u32::MAX, u32::MAX], &[]);
+    assert_eq!(parse_idx(&huge).err().unwrap(), format!("IDX file with dimensions {:?} is too large", [u32::MAX as usize; 3]));
+}
+
+#[test]
+fn reads_gzipped_files() {
+    let dir = temp_dir("gzip");
+    let bytes = idx_bytes(&[4], &[7, 8, 9, 10]);
+    std::fs::write(dir.join("plain"), &bytes).unwrap();
+    std::fs::write(dir.join("packed.gz"), gzip(&bytes)).unwrap();
+    assert_eq!(read_idx(dir.join("plain")).unwrap().data, read_idx(dir.join("packed.gz")).unwrap().data);
+}
+
+#[test]
+fn loads_mnist_with_inferred_counts_and_dimensions() {
+    for gzipped in [false, true] {
+        let dir = temp_dir(&format!("mnist_{}", gzipped));
+        write_mnist(&dir, 4, 3, gzipped);
+        let data = load_mnist(&dir).unwrap();
+        assert_eq!((data.trn_size, data.tst_size), (3, 2));
+        assert_eq!((data.rows, data.cols), (4, 3));
+        assert_eq!(data.trn_lbl, vec![0, 1, 9]);
+        assert_eq!(data.tst_lbl, vec![5, 3]);
+        assert_eq!(data.classes.len(), 10);
+        let first = image(&data.trn_img[1]);
+        assert_eq!(first.dim(), (4, 3, 1));
+        // Pixel (row, col) of image n is row * cols + col + n
+        assert_eq!(first[[2, 1, 0]], 8.0 / 255.0);
+    }
+}
+
+#[test]
+fn names_fashion_mnist_classes() {
+    let dir = temp_dir("fashion");
+    write_mnist(&dir, 2, 2, true);
+    let data = load_fashion_mnist(&dir).unwrap();
+    assert_eq!(data.class_name(0).unwrap(), "T-shirt/top");
+    assert_eq!(data.class_name(9).unwrap(), "Ankle boot");
+}
+
+#[test]
+fn reports_missing_files_and_unknown_labels() {
+    let dir = temp_dir("missing");
+    assert!(load_mnist(&dir).err().unwrap().contains("train-images-idx3-ubyte"));
+
+    write_split(&dir, "train-images-idx3-ubyte", "train-labels-idx1-ubyte", 2, 2, &[0, 10], false);
+    write_split(&dir, "t10k-images-idx3-ubyte", "t10k-labels-idx1-ubyte", 2, 2, &[1], false);
+    assert!(load_mnist(&dir).err().unwrap().contains("Label 10"));
+}
+
+#[test]
+fn loads_emnist_letters_transposed() {
+    let dir = temp_dir("emnist");
+    write_split(&dir, "emnist-letters-train-images-idx3-ubyte", "emnist-letters-train-labels-idx1-ubyte", 2, 3, &[1, 26], true);
+    write_split(&dir, "emnist-letters-test-images-idx3-ubyte", "emnist-letters-test-labels-idx1-ubyte", 2, 3, &[13], true);
+    let data = load_emnist(&dir, EmnistSplit::Letters).unwrap();
+    assert_eq!(data.classes.len(), 26);
+    assert_eq!(data.classes[&1], 0);
+    assert_eq!(data.classes[&26], 25);
+    assert_eq!(data.class_name(0).unwrap(), "Aa");
+    assert_eq!(data.class_name(12).unwrap(), "Mm");
+    // Stored as 2x3, so upright images are 3x2
+    assert_eq!((data.rows, data.cols), (3, 2));
+    let first = image(&data.trn_img[0]);
+    // Stored pixel (row, col) = row * 3 + col lands at (col, row)
+    assert_eq!(first[[2, 1, 0]], 5.0 / 255.0);
+}
+
+#[test]
+fn emnist_balanced_has_47_classes() {
+    let dir = temp_dir("balanced");
+    write_split(&dir, "emnist-balanced-train-images-idx3-ubyte", "emnist-balanced-train-labels-idx1-ubyte", 2, 2, &[0, 46], false);
+    write_split(&dir, "emnist-balanced-test-images-idx3-ubyte", "emnist-balanced-test-labels-idx1-ubyte", 2, 2, &[10], false);
+    let data = load_emnist(&dir, EmnistSplit::Balanced).unwrap();
+    assert_eq!(data.classes.len(), 47);
+    assert_eq!(data.class_name(10).unwrap(), "A");
+    assert_eq!(data.class_name(36).unwrap(), "a");
+    assert_eq!(data.class_name(46).unwrap(), "t");
+}
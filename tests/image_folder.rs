@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use image::{Rgb, RgbImage};
+use oxi_net::image_folder::{load_image_folder, ImageFolderOptions, Split};
+use oxi_net::util::TrainImage;
+
+mod common;
+use common::temp_dir;
+
+fn write_image(path: &Path, shade: u8) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    RgbImage::from_pixel(4, 3, Rgb([shade, shade, shade])).save(path).unwrap();
+}
+
+/// cat has 10 images, dog has 6 in mixed formats, bird is filtered out in some tests
+fn dataset(name: &str) -> PathBuf {
+    let root = temp_dir(name);
+    for i in 0..10 {
+        write_image(&root.join("cat").join(format!("{}.png", i)), i * 10);
+    }
+    for (i, extension) in ["jpg", "JPG", "bmp", "png", "jpeg", "bmp"].iter().enumerate() {
+        write_image(&root.join("dog").join("nested").join(format!("{}.{}", i, extension)), 200);
+    }
+    write_image(&root.join("bird").join("0.png"), 100);
+    write_image(&root.join(".hidden").join("0.png"), 100);
+    std::fs::write(root.join("cat").join("notes.txt"), "not an image").unwrap();
+    root
+}
+
+#[test]
+fn names_classes_after_folders() {
+    let root = dataset("names");
+    let data = load_image_folder(&root, &ImageFolderOptions::default()).unwrap();
+    assert_eq!(data.trn_size + data.tst_size, 17);
+    assert_eq!((data.rows, data.cols), (3, 4));
+    assert_eq!(data.classes.len(), 3);
+    let names: Vec<String> = (0..3).map(|c| data.class_name(c).unwrap()).collect();
+    assert_eq!(names, vec!["bird", "cat", "dog"]);
+    let dogs = data.trn_lbl.iter().chain(data.tst_lbl.iter()).filter(|&&l| l == 2).count();
+    assert_eq!(dogs, 6);
+}
+
+#[test]
+fn splits_by_ratio_per_class() {
+    let root = dataset("ratio");
+    let options = ImageFolderOptions {
+        split: Split::Ratio { train_fraction: 0.7, seed: 42 },
+        classes: Some(vec![String::from("cat"), String::from("dog")]),
+        ..ImageFolderOptions::default()
+    };
+    let data = load_image_folder(&root, &options).unwrap();
+    let count = |labels: &[usize], label| labels.iter().filter(|&&l| l == label).count();
+    assert_eq!(count(&data.trn_lbl, 0), 7);
+    assert_eq!(count(&data.tst_lbl, 0), 3);
+    assert_eq!(count(&data.trn_lbl, 1), 4);
+    assert_eq!(data.class_name(1).unwrap(), "dog");
+
+    let paths = |images: &[TrainImage]| -> Vec<PathBuf> {
+        images.iter().map(|image| match image {
+            TrainImage::Path(path) => path.clone(),
+            TrainImage::Image(_) => panic!("Images should not be preloaded"),
+        }).collect()
+    };
+    let again = load_image_folder(&root, &options).unwrap();
+    assert_eq!(paths(&again.trn_img), paths(&data.trn_img));
+}
+
+#[test]
+fn hash_split_is_stable_as_files_are_added() {
+    let root = dataset("hash");
+    let options = ImageFolderOptions { split: Split::Hash(0.5), ..ImageFolderOptions::default() };
+    let before = load_image_folder(&root, &options).unwrap();
+    write_image(&root.join("cat").join("new.png"), 0);
+    let after = load_image_folder(&root, &options).unwrap();
+
+    for image in &before.trn_img {
+        if let TrainImage::Path(path) = image {
+            assert!(after.trn_img.iter().any(|i| matches!(i, TrainImage::Path(p) if p == path)));
+        }
+    }
+    assert_eq!(after.trn_size + after.tst_size, before.trn_size + before.tst_size + 1);
+}
+
+#[test]
+fn hash_split_is_pinned_to_fnv_1a() {
+    let root = dataset("hash_pinned");
+    let options = ImageFolderOptions {
+        split: Split::Hash(0.5),
+        classes: Some(vec![String::from("cat"), String::from("dog")]),
+        ..ImageFolderOptions::default()
+    };
+    let data = load_image_folder(&root, &options).unwrap();
+    let mut trn: Vec<String> = data.trn_img.iter()
+        .map(|image| match image {
+            TrainImage::Path(path) => path.strip_prefix(&root).unwrap().components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/"),
+            _ => unreachable!(),
+        })
+        .collect();
+    trn.sort();
+    // Nested paths hash with `/` between components, whatever the platform
+    assert_eq!(trn, [
+        "cat/1.png", "cat/2.png", "cat/3.png", "cat/8.png",
+        "dog/nested/1.JPG", "dog/nested/3.png", "dog/nested/5.bmp",
+    ]);
+    assert_eq!(data.tst_size, 9);
+}
+
+#[test]
+fn uses_train_and_test_subfolders() {
+    let root = temp_dir("subfolders");
+    for path in ["train/a/0.png", "train/a/1.png", "train/b/0.png", "test/b/0.png"] {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::new(5, 5).save(path).unwrap();
+    }
+    let options = ImageFolderOptions { split: Split::Subfolders, preload: true, ..ImageFolderOptions::default() };
+    let data = load_image_folder(&root, &options).unwrap();
+    assert_eq!(data.trn_lbl, vec![0, 0, 1]);
+    assert_eq!(data.tst_lbl, vec![1]);
+    assert!(matches!(&data.trn_img[0], TrainImage::Image(image) if image.dim() == (5, 5, 3)));
+}
+
+#[test]
+fn reports_problems() {
+    let root = dataset("problems");
+    write_image(&root.join("cat").join("big.png"), 0);
+    RgbImage::new(8, 8).save(root.join("cat").join("big.png")).unwrap();
+    let err = load_image_folder(&root, &ImageFolderOptions::default()).err().unwrap();
+    assert!(err.contains("every image must have the same dimensions"), "{}", err);
+
+    let options = ImageFolderOptions { classes: Some(vec![String::from("fish")]), ..ImageFolderOptions::default() };
+    let err = load_image_folder(&root, &options).err().unwrap();
+    assert!(err.contains("no class folders named [\"fish\"]"), "{}", err);
+
+    let err = load_image_folder(root.join("missing"), &ImageFolderOptions::default()).err().unwrap();
+    assert!(err.contains("does not exist"), "{}", err);
+}
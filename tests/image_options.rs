@@ -0,0 +1,122 @@
+use std::path::Path;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::dataset::Dataset;
+use oxi_net::image_folder::{load_image_folder, ImageFolderOptions};
+use oxi_net::util::{check_shape, decode_image, decode_image_with, ColorMode, ImageOptions, ResizeFilter, ResizeMode, TrainImage, TrainingData};
+
+mod common;
+use common::temp_dir;
+
+fn png(image: RgbImage) -> Vec<u8> {
+    let mut bytes = vec![];
+    DynamicImage::ImageRgb8(image).write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+    bytes
+}
+
+fn options(size: (usize, usize), filter: ResizeFilter, mode: ResizeMode) -> ImageOptions {
+    ImageOptions { size: Some(size), filter, mode, color: ColorMode::Rgb }
+}
+
+#[test]
+fn resizes_with_each_filter() {
+    // Left half black, right half white
+    let bytes = png(RgbImage::from_fn(8, 8, |x, _| if x < 4 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) }));
+    for filter in [ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Area] {
+        let image = decode_image_with(&bytes, &options((4, 4), filter, ResizeMode::Stretch)).unwrap();
+        assert_eq!(image.dim(), (4, 4, 3));
+        let mean: f32 = image.iter().sum::<f32>() / image.len() as f32;
+        assert!((mean - 0.5).abs() < 0.05, "{:?}: {}", filter, mean);
+    }
+
+    // Area averages the 2x2 blocks exactly
+    let checkers = png(RgbImage::from_fn(4, 4, |x, y| if (x + y) % 2 == 0 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) }));
+    let image = decode_image_with(&checkers, &options((2, 2), ResizeFilter::Area, ResizeMode::Stretch)).unwrap();
+    assert!(image.iter().all(|&v| (v - 128.0 / 255.0).abs() < 1e-6), "{:?}", image);
+}
+
+#[test]
+fn crops_and_letterboxes_to_the_requested_size() {
+    // A wide image: a white square in the middle of black sides
+    let bytes = png(RgbImage::from_fn(12, 4, |x, _| if (4..8).contains(&x) { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) }));
+
+    let cropped = decode_image_with(&bytes, &options((4, 4), ResizeFilter::Nearest, ResizeMode::CenterCrop)).unwrap();
+    assert_eq!(cropped.dim(), (4, 4, 3));
+    assert!(cropped.iter().all(|&v| v == 1.0));
+
+    let stretched = decode_image_with(&bytes, &options((4, 4), ResizeFilter::Nearest, ResizeMode::Stretch)).unwrap();
+    assert!(stretched.iter().any(|&v| v == 0.0));
+
+    // Scaled to 6x2, then padded with a row of zeros above and below
+    let boxed = decode_image_with(&bytes, &ImageOptions { color: ColorMode::Rgba, ..options((6, 6), ResizeFilter::Nearest, ResizeMode::Letterbox) }).unwrap();
+    assert_eq!(boxed.dim(), (6, 6, 4));
+    let opaque = boxed.iter().skip(3).step_by(4).filter(|&&alpha| alpha == 1.0).count();
+    assert_eq!(opaque, 12);
+}
+
+#[test]
+fn converts_channels() {
+    let bytes = png(RgbImage::from_pixel(3, 3, Rgb([255, 0, 0])));
+    let rgb = decode_image(&bytes).unwrap();
+    assert_eq!(rgb.dim(), (3, 3, 3));
+
+    let gray = decode_image_with(&bytes, &ImageOptions { color: ColorMode::Grayscale, ..ImageOptions::default() }).unwrap();
+    assert_eq!(gray.dim(), (3, 3, 1));
+    assert!(gray.iter().all(|&v| v > 0.0 && v < 1.0));
+
+    let rgba = decode_image_with(&bytes, &ImageOptions { color: ColorMode::Rgba, ..ImageOptions::default() }).unwrap();
+    assert_eq!(rgba.dim(), (3, 3, 4));
+    assert_eq!(rgba[[1, 1, 3]], 1.0);
+
+    assert_eq!(ImageOptions::for_input_shape((28, 28, 1)).unwrap().color, ColorMode::Grayscale);
+    assert_eq!(ImageOptions::for_input_shape((28, 28, 2)).err().unwrap(), "Images can have 1, 3 or 4 channels, not 2");
+}
+
+#[test]
+fn checks_shapes() {
+    let image = decode_image(&png(RgbImage::new(5, 5))).unwrap();
+    assert!(check_shape(&image, (5, 5, 3)).is_ok());
+    assert_eq!(check_shape(&image, (5, 5, 1)).err().unwrap(), "Image has shape (5, 5, 3) but the model expects (5, 5, 1)");
+}
+
+fn write_images(root: &Path) {
+    for class in ["a", "b"] {
+        std::fs::create_dir_all(root.join(class)).unwrap();
+        for i in 0..3 {
+            RgbImage::from_pixel(10, 10, Rgb([i * 50, 0, 0])).save(root.join(class).join(format!("{}.png", i))).unwrap();
+        }
+    }
+}
+
+#[test]
+fn builder_rejects_data_of_the_wrong_shape() {
+    let root = temp_dir("builder");
+    write_images(&root);
+    let data = || load_image_folder(&root, &ImageFolderOptions::default()).unwrap();
+    let builder = || CnnBuilder::new().conv(2, 3).dense(2, Activation::Softmax, None);
+
+    let errors = builder().input_shape(vec![10, 10, 1]).build(data()).err().unwrap();
+    assert!(errors.contains("Images have shape (10, 10, 3) but the network input shape is (10, 10, 1)"), "{}", errors);
+    assert!(builder().input_shape(vec![10, 10, 3]).build(data()).is_ok());
+    // Data without images can not be checked
+    assert!(builder().input_shape(vec![10, 10, 1]).build(TrainingData::default()).is_ok());
+}
+
+#[test]
+fn image_folder_resizes_to_the_options() {
+    let root = temp_dir("folder");
+    write_images(&root);
+    let options = ImageFolderOptions {
+        image: ImageOptions { size: Some((6, 6)), color: ColorMode::Grayscale, ..ImageOptions::default() },
+        ..ImageFolderOptions::default()
+    };
+    let data = load_image_folder(&root, &options).unwrap();
+    assert_eq!((data.rows, data.cols), (6, 6));
+    let (image, _) = data.train_set().get(0);
+    assert_eq!(image.dim(), (6, 6, 1));
+    assert!(data.check_input_shape((6, 6, 1)).is_ok());
+
+    let preloaded = load_image_folder(&root, &ImageFolderOptions { preload: true, ..options }).unwrap();
+    assert!(matches!(&preloaded.trn_img[0], TrainImage::Image(image) if image.dim() == (6, 6, 1)));
+}
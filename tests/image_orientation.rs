@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use ndarray::Array3;
+use oxi_net::util::{load_image, load_image_with, save_image, ColorMode, ImageOptions, ResizeFilter};
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("oxi_net_orientation_{}_{}", std::process::id(), name))
+}
+
+/// A 5 wide, 3 tall image whose red channel encodes the column and green the row
+fn asymmetric() -> RgbImage {
+    RgbImage::from_fn(5, 3, |x, y| Rgb([(x * 50) as u8, (y * 100) as u8, 7]))
+}
+
+#[test]
+fn loads_images_as_rows_columns_channels() {
+    let path = temp_file("asymmetric.png");
+    asymmetric().save(&path).unwrap();
+    let image = load_image(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(image.dim(), (3, 5, 3));
+    for row in 0..3 {
+        for col in 0..5 {
+            assert_eq!(image[[row, col, 0]], (col * 50) as f32 / 255.0, "red at ({}, {})", row, col);
+            assert_eq!(image[[row, col, 1]], (row * 100) as f32 / 255.0, "green at ({}, {})", row, col);
+            assert_eq!(image[[row, col, 2]], 7.0 / 255.0);
+        }
+    }
+}
+
+#[test]
+fn keeps_tall_images_upright() {
+    // A single white pixel in the top right corner of a 2 wide, 4 tall image
+    let path = temp_file("tall.png");
+    GrayImage::from_fn(2, 4, |x, y| Luma([if (x, y) == (1, 0) { 255 } else { 0 }])).save(&path).unwrap();
+    let options = ImageOptions { color: ColorMode::Grayscale, ..ImageOptions::default() };
+    let image = load_image_with(&path, &options).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(image.dim(), (4, 2, 1));
+    assert_eq!(image[[0, 1, 0]], 1.0);
+    assert_eq!(image.sum(), 1.0);
+}
+
+#[test]
+fn save_image_round_trips() {
+    for channels in [1, 3, 4] {
+        let original = Array3::from_shape_fn((3, 5, channels), |(row, col, c)| ((row * 5 + col) * 4 + c * 40) as f32 / 255.0);
+        let path = temp_file(&format!("round_trip_{}.png", channels));
+        save_image(&original, &path).unwrap();
+        let options = ImageOptions { color: ColorMode::from_channels(channels).unwrap(), ..ImageOptions::default() };
+        let loaded = load_image_with(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, original, "{} channels", channels);
+    }
+
+    // Stored like the file, with the column changing fastest
+    let path = temp_file("saved.png");
+    let tensor = Array3::from_shape_fn((3, 5, 3), |(row, col, c)| [col as f32 * 50.0, row as f32 * 100.0, 7.0][c] / 255.0);
+    save_image(&tensor, &path).unwrap();
+    assert_eq!(image::open(&path).unwrap().to_rgb8(), asymmetric());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn save_image_clamps_and_checks_channels() {
+    let path = temp_file("clamped.png");
+    save_image(&Array3::from_shape_vec((1, 2, 1), vec![-0.5, 1.5]).unwrap(), &path).unwrap();
+    assert_eq!(load_image_with(&path, &ImageOptions { color: ColorMode::Grayscale, ..ImageOptions::default() }).unwrap().into_raw_vec(), vec![0.0, 1.0]);
+    std::fs::remove_file(&path).unwrap();
+
+    let err = save_image(&Array3::zeros((2, 2, 2)), &temp_file("two_channels.png")).err().unwrap();
+    assert_eq!(err, "Images can have 1, 3 or 4 channels, not 2");
+}
+
+#[test]
+fn resizes_non_square_images() {
+    let path = temp_file("resize.png");
+    asymmetric().save(&path).unwrap();
+    let options = ImageOptions { size: Some((6, 10)), filter: ResizeFilter::Nearest, ..ImageOptions::default() };
+    let image = load_image_with(&path, &options).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(image.dim(), (6, 10, 3));
+    // Every source pixel becomes a 2x2 block
+    assert_eq!(image[[5, 9, 0]], 200.0 / 255.0);
+    assert_eq!(image[[5, 9, 1]], 200.0 / 255.0);
+    assert_eq!(image[[0, 9, 1]], 0.0);
+}
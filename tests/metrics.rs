@@ -0,0 +1,132 @@
+use ndarray::{array, Array1};
+use oxi_net::metrics::Metrics;
+
+/// Output that predicts `class` out of 3
+fn predicts(class: usize) -> Array1<f32> {
+    Array1::from_shape_fn(3, |i| if i == class { 0.8 } else { 0.1 })
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+}
+
+/// Confusion matrix (rows are true classes, columns predictions):
+/// [[3, 1, 0],
+///  [0, 2, 1],
+///  [2, 0, 2]]
+fn fixture() -> Metrics {
+    let mut metrics = Metrics::new(3);
+    for (label, predicted, count) in [(0, 0, 3), (0, 1, 1), (1, 1, 2), (1, 2, 1), (2, 0, 2), (2, 2, 2)] {
+        for _ in 0..count {
+            metrics.record(label, &predicts(predicted));
+        }
+    }
+    metrics
+}
+
+#[test]
+fn computes_per_class_and_averaged_metrics() {
+    let metrics = fixture();
+    assert_eq!(metrics.confusion, array![[3, 1, 0], [0, 2, 1], [2, 0, 2]]);
+    assert_eq!(metrics.total, 11);
+    assert_eq!((metrics.support(0), metrics.support(1), metrics.support(2)), (4, 3, 4));
+    assert_close(metrics.accuracy(), 7.0 / 11.0);
+
+    let precision = [3.0 / 5.0, 2.0 / 3.0, 2.0 / 3.0];
+    let recall = [3.0 / 4.0, 2.0 / 3.0, 2.0 / 4.0];
+    let f1 = [2.0 / 3.0, 2.0 / 3.0, 4.0 / 7.0];
+    for c in 0..3 {
+        assert_close(metrics.precision(c), precision[c]);
+        assert_close(metrics.recall(c), recall[c]);
+        assert_close(metrics.f1(c), f1[c]);
+    }
+
+    assert_close(metrics.macro_precision(), precision.iter().sum::<f32>() / 3.0);
+    assert_close(metrics.macro_recall(), recall.iter().sum::<f32>() / 3.0);
+    assert_close(metrics.macro_f1(), f1.iter().sum::<f32>() / 3.0);
+    let weighted = |values: [f32; 3]| (values[0] * 4.0 + values[1] * 3.0 + values[2] * 4.0) / 11.0;
+    assert_close(metrics.weighted_precision(), weighted(precision));
+    assert_close(metrics.weighted_recall(), 7.0 / 11.0);
+    assert_close(metrics.weighted_f1(), weighted(f1));
+
+    let report = format!("{:?}", metrics);
+    assert!(report.contains("Accuracy: 0.6364"), "{}", report);
+}
+
+#[test]
+fn empty_classes_score_zero() {
+    let mut metrics = Metrics::new(2);
+    assert_eq!((metrics.accuracy(), metrics.macro_f1(), metrics.weighted_f1()), (0.0, 0.0, 0.0));
+    metrics.record(0, &array![0.9, 0.1]);
+    // Class 1 is never predicted nor present
+    assert_eq!((metrics.precision(1), metrics.recall(1), metrics.f1(1)), (0.0, 0.0, 0.0));
+    assert_eq!(metrics.macro_recall(), 0.5);
+}
+
+#[test]
+#[should_panic(expected = "Label 3 is out of range for 3 classes")]
+fn rejects_labels_out_of_range() {
+    Metrics::new(3).record(3, &predicts(0));
+}
+
+#[test]
+#[should_panic(expected = "Output has 2 values but there are 3 classes")]
+fn rejects_outputs_of_the_wrong_size() {
+    Metrics::new(3).record(0, &array![0.5, 0.5]);
+}
+
+#[test]
+fn counts_the_rank_of_the_true_class() {
+    let mut metrics = Metrics::new(4);
+    metrics.record(2, &array![0.1, 0.2, 0.4, 0.3]);
+    metrics.record(2, &array![0.1, 0.4, 0.3, 0.2]);
+    metrics.record(0, &array![0.1, 0.2, 0.3, 0.4]);
+    // Ties go to the lower index, so class 2 ranks behind class 1 but ahead of class 3
+    metrics.record(2, &array![0.1, 0.3, 0.3, 0.3]);
+    assert_eq!(metrics.rank_counts, vec![1, 2, 0, 1]);
+    assert_eq!(metrics.confusion[[2, 1]], 2);
+
+    assert_eq!(metrics.top_k_accuracy(0), 0.0);
+    assert_eq!(metrics.top_k_accuracy(1), 0.25);
+    assert_eq!(metrics.top_k_accuracy(2), 0.75);
+    assert_eq!(metrics.top_k_accuracy(4), 1.0);
+    assert_eq!(metrics.top_k_accuracy(10), 1.0);
+}
+
+#[test]
+fn ranks_nan_outputs_like_the_prediction() {
+    let mut metrics = Metrics::new(3);
+    // A NaN ranks above every number, so it is both the prediction and rank 0
+    metrics.record(1, &array![0.5, f32::NAN, 0.2]);
+    // A NaN elsewhere pushes the true class down a rank
+    metrics.record(0, &array![0.5, f32::NAN, 0.2]);
+    // A diverged network outputs NaN everywhere, which ties in index order
+    metrics.record(2, &array![f32::NAN, f32::NAN, f32::NAN]);
+    assert_eq!(metrics.confusion[[1, 1]], 1);
+    assert_eq!(metrics.confusion[[0, 1]], 1);
+    assert_eq!(metrics.confusion[[2, 0]], 1);
+    assert_eq!(metrics.rank_counts, vec![1, 1, 1]);
+    assert_eq!(metrics.top_k_accuracy(1), metrics.accuracy());
+}
+
+#[test]
+fn top_n_is_cumulative_and_clamps_k() {
+    let mut metrics = Metrics::new(3);
+    metrics.record(0, &array![0.5, 0.3, 0.2]);
+    metrics.record(0, &array![0.2, 0.5, 0.3]);
+    metrics.record(0, &array![0.1, 0.2, 0.7]);
+    metrics.record(1, &array![0.4, 0.4, 0.2]);
+
+    // n beyond the number of classes repeats the total
+    let top = metrics.top_n(5);
+    assert_eq!(top.corrects, vec![1, 2, 4, 4, 4]);
+    assert_eq!(top.total, 4);
+    assert_eq!(top.accuracy(0), 0.0);
+    assert_eq!(top.accuracy(2), 0.5);
+    assert_eq!(top.accuracy(100), 1.0);
+
+    let top = metrics.top_n(2);
+    assert_eq!(top.corrects, vec![1, 2]);
+    assert_eq!(top.accuracy(3), 0.5);
+    assert_eq!(metrics.top_n(0).accuracy(1), 0.0);
+}
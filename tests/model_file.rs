@@ -0,0 +1,78 @@
+use ndarray::Array3;
+use oxi_net::cnn::CNN;
+use oxi_net::model_file::{self, ModelFormat, BINARY_FORMAT_VERSION, SCHEMA_VERSION};
+
+mod common;
+use common::temp_dir;
+
+fn fixture() -> String {
+    format!("tests/fixtures/model_v{}.json", SCHEMA_VERSION)
+}
+
+fn outputs(path: &std::path::Path) -> Vec<f32> {
+    let cnn: CNN = model_file::read_model(path).unwrap();
+    let image = Array3::<f32>::from_shape_fn((6, 6, 1), |(y, x, _)| (y * 6 + x) as f32 / 36.0);
+    cnn.into_predictor().predict_proba(&image).to_vec()
+}
+
+fn binary() -> Vec<u8> {
+    model_file::encode_binary(&model_file::read_value(fixture()).unwrap(), false).unwrap()
+}
+
+#[test]
+fn json_to_binary_to_json_is_lossless() {
+    let dir = temp_dir("lossless");
+    let (bin, json) = (dir.join("model.bin"), dir.join("model.json"));
+    model_file::convert(fixture(), &bin, ModelFormat::Binary).unwrap();
+    model_file::convert(&bin, &json, ModelFormat::Json).unwrap();
+
+    assert_eq!(std::fs::read(&json).unwrap(), std::fs::read(fixture()).unwrap());
+    assert_eq!(outputs(&bin), outputs(std::path::Path::new(&fixture())));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn half_precision_halves_the_tensors() {
+    let value = model_file::read_value(fixture()).unwrap();
+    let full = model_file::encode_binary(&value, false).unwrap();
+    let half = model_file::encode_binary(&value, true).unwrap();
+    assert_eq!(&half[..4], b"OXIN");
+    assert_eq!(u32::from_le_bytes(half[4..8].try_into().unwrap()), BINARY_FORMAT_VERSION);
+    assert_eq!((u32::from_le_bytes(full[8..12].try_into().unwrap()), u32::from_le_bytes(half[8..12].try_into().unwrap())), (0, 1));
+    let header_len = u64::from_le_bytes(full[12..20].try_into().unwrap()) as usize;
+    assert_eq!(full.len() - 20 - header_len, 2 * (half.len() - 20 - header_len));
+
+    let dir = temp_dir("half");
+    let path = dir.join("model.bin");
+    std::fs::write(&path, &half).unwrap();
+    for (a, b) in outputs(&path).iter().zip(outputs(std::path::Path::new(&fixture()))) {
+        assert!((a - b).abs() < 1e-2, "{} != {}", a, b);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_invalid_containers() {
+    let bytes = binary();
+    let decode = |bytes: &[u8]| model_file::decode_binary(bytes).err().unwrap();
+
+    assert_eq!(decode(b"OXI"), "Not an oxi_net binary model file");
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[..4].copy_from_slice(b"ONNX");
+    assert_eq!(decode(&wrong_magic), "Not an oxi_net binary model file");
+
+    let mut newer = bytes.clone();
+    newer[4..8].copy_from_slice(&(BINARY_FORMAT_VERSION + 1).to_le_bytes());
+    assert_eq!(decode(&newer), format!("Binary model format version {} is newer than the supported version {}", BINARY_FORMAT_VERSION + 1, BINARY_FORMAT_VERSION));
+
+    let mut dtype = bytes.clone();
+    dtype[8..12].copy_from_slice(&7u32.to_le_bytes());
+    assert_eq!(decode(&dtype), "Unknown tensor data type 7");
+
+    assert_eq!(decode(&bytes[..40]), "Truncated model header");
+    assert_eq!(decode(&bytes[..bytes.len() - 1]), "Truncated tensor data");
+    let mut extra = bytes.clone();
+    extra.push(0);
+    assert_eq!(decode(&extra), "Unexpected data after the last tensor");
+    assert!(model_file::decode_binary(&bytes).is_ok());
+}
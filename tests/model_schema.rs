@@ -0,0 +1,70 @@
+use ndarray::{Array1, Array3};
+use oxi_net::cnn::CNN;
+use oxi_net::model_file::{self, SCHEMA_VERSION};
+use serde_json::{json, Value};
+
+// Every fixture holds the same 6x6x1 network, saved with a different schema version
+const FIXTURES: [&str; 12] = [
+    "tests/fixtures/model_v0.json",
+    "tests/fixtures/model_v1.json",
+    "tests/fixtures/model_v1.bin",
+    "tests/fixtures/model_v2.json",
+    "tests/fixtures/model_v3.json",
+    "tests/fixtures/model_v4.json",
+    "tests/fixtures/model_v5.json",
+    "tests/fixtures/model_v6.json",
+    "tests/fixtures/model_v7.json",
+    "tests/fixtures/model_v8.json",
+    "tests/fixtures/model_v9.json",
+    "tests/fixtures/model_v10.json",
+];
+
+fn fixture_output(path: &str) -> Array1<f32> {
+    let cnn: CNN = model_file::read_model(path).unwrap();
+    let image = Array3::<f32>::from_shape_fn((6, 6, 1), |(y, x, _)| (y * 6 + x) as f32 / 36.0);
+    cnn.into_predictor().predict_proba(&image)
+}
+
+#[test]
+fn loads_every_schema_version() {
+    let expected = fixture_output(FIXTURES[0]);
+    for fixture in &FIXTURES[1..] {
+        assert_eq!(fixture_output(fixture), expected, "{}", fixture);
+    }
+}
+
+#[test]
+fn has_fixture_for_current_version() {
+    let value = model_file::read_value(format!("tests/fixtures/model_v{}.json", SCHEMA_VERSION)).unwrap();
+    assert_eq!(value["schema_version"], Value::from(SCHEMA_VERSION));
+}
+
+#[test]
+fn rejects_newer_schema_version() {
+    let mut value = model_file::read_value(FIXTURES[0]).unwrap();
+    value["schema_version"] = Value::from(SCHEMA_VERSION + 1);
+    let err = model_file::migrate(value).unwrap_err();
+    assert!(err.contains("only supports versions up to"), "{}", err);
+}
+
+#[test]
+fn retags_enums_written_before_version_10() {
+    let mut value = model_file::read_value("tests/fixtures/model_v9.json").unwrap();
+    value["optimizer"] = json!({"Adam": [0.01, 0.9, 0.999]});
+    value["lr_schedule"] = json!({"Step": {"step_size": 5, "gamma": 0.5}});
+    value["saving_strategy"] = json!({"EveryNthEpoch": [true, 0.5]});
+    value["model_format"] = json!("BinaryF16");
+    value["data"]["image_options"]["mode"] = json!("CenterCrop");
+    value["layers"][0]["Conv"]["optimizer"]["alg"] = json!({"RMSProp": [0.01, 0.9]});
+
+    let migrated = model_file::migrate(value).unwrap();
+    assert_eq!(migrated["optimizer"], json!({"type": "adam", "lr": 0.01, "beta1": 0.9, "beta2": 0.999}));
+    assert_eq!(migrated["lr_schedule"], json!({"type": "step", "step_size": 5, "gamma": 0.5}));
+    assert_eq!(migrated["saving_strategy"], json!({"type": "every_nth_epoch", "full_save": true, "n": 0.5}));
+    assert_eq!(migrated["model_format"], json!("binary_f16"));
+    assert_eq!(migrated["data"]["image_options"]["mode"], json!("center_crop"));
+    assert_eq!(migrated["layers"][0]["Conv"]["optimizer"]["alg"], json!({"type": "rmsprop", "lr": 0.01, "rho": 0.9}));
+    assert_eq!(migrated["layers"][2]["Dense"]["activation"], json!("softmax"));
+    let cnn: CNN = serde_json::from_value(migrated).unwrap();
+    assert_eq!(cnn.model_format(), model_file::ModelFormat::BinaryF16);
+}
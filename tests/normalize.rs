@@ -0,0 +1,77 @@
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::CNN;
+use oxi_net::dataset::InMemoryDataset;
+use oxi_net::normalize::{Normalization, Normalize};
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::util::TrainingData;
+
+fn builder(normalize: Normalize) -> CnnBuilder {
+    CnnBuilder::new()
+        .input_shape(vec![3, 3, 2])
+        .conv(2, 2)
+        .dense(3, Activation::Softmax, None)
+        .optimizer(OptimizerAlg::SGD(0.01))
+        .epochs(1)
+        .normalize(normalize)
+}
+
+/// Channel 0 is filled with the sample index, channel 1 is always 5
+fn dataset() -> InMemoryDataset {
+    let images = (0..4).map(|i| Array3::from_shape_fn((3, 3, 2), |(_, _, c)| if c == 0 { i as f32 } else { 5.0 })).collect();
+    InMemoryDataset::new(images, vec![0, 1, 2, 0]).unwrap()
+}
+
+#[test]
+fn computes_per_channel_statistics() {
+    let normalization = Normalization::from_dataset(&dataset()).unwrap();
+    assert_eq!(normalization.mean, vec![1.5, 5.0]);
+    // Population std of 0, 1, 2, 3, and 1 for the constant channel
+    assert!((normalization.std[0] - 1.25f32.sqrt()).abs() < 1e-6);
+    assert_eq!(normalization.std[1], 1.0);
+
+    let applied = normalization.apply(Array3::from_elem((1, 1, 2), 3.0));
+    assert!((applied[[0, 0, 0]] - 1.5 / 1.25f32.sqrt()).abs() < 1e-6);
+    assert_eq!(applied[[0, 0, 1]], -2.0);
+}
+
+#[test]
+fn rejects_invalid_statistics() {
+    assert!(Normalization::new(vec![0.5], vec![0.5, 0.5]).is_err());
+    assert!(Normalization::new(vec![0.5], vec![0.0]).is_err());
+    assert!(Normalization::from_dataset(&InMemoryDataset::default()).is_err());
+
+    let err = builder(Normalize::Fixed { mean: vec![0.5, 0.5, 0.5], std: vec![0.2, 0.2, 0.2] })
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    assert_eq!(err, "Normalization has 3 channels but the input has 2");
+}
+
+#[test]
+fn computes_normalization_when_training_starts() {
+    let mut cnn = builder(Normalize::FromTrainingSet).build(TrainingData::default()).unwrap();
+    assert!(cnn.normalization().is_none());
+    cnn.train_on(&dataset(), None::<&InMemoryDataset>).unwrap();
+    assert_eq!(cnn.normalization(), Some(&Normalization::from_dataset(&dataset()).unwrap()));
+}
+
+#[test]
+fn saved_models_normalize_predictions() {
+    let mut cnn = builder(Normalize::Fixed { mean: vec![0.5, 0.25], std: vec![0.1, 2.0] })
+        .build(TrainingData::default())
+        .unwrap();
+    let image = Array3::from_shape_fn((3, 3, 2), |(row, col, c)| (row * 3 + col + c) as f32 / 10.0);
+    let expected = cnn.forward_propagate(image.clone(), false);
+
+    let path = std::env::temp_dir().join(format!("oxi_net_normalize_{}.json", std::process::id()));
+    cnn.save_to(&path).unwrap();
+    let loaded = CNN::load_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.normalization(), cnn.normalization());
+    assert_eq!(loaded.into_predictor().predict_proba(&image), expected);
+
+    cnn.set_normalization(None);
+    assert_ne!(cnn.into_predictor().predict_proba(&image), expected);
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use ndarray::{Array2, Array4, ArrayD, IxDyn};
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::CNN;
+use oxi_net::layer::Layer;
+use oxi_net::numpy::{self, Param, WeightLayout};
+use oxi_net::util::TrainingData;
+
+mod common;
+use common::temp_dir;
+
+const LAYOUTS: [WeightLayout; 3] = [WeightLayout::OxiNet, WeightLayout::PyTorch, WeightLayout::Keras];
+
+/// Conv to (6, 4, 4), pooled to (3, 2, 4) so rows, cols and channels all differ
+fn network() -> CNN {
+    CnnBuilder::new()
+        .input_shape(vec![8, 6, 2])
+        .conv(4, 3)
+        .mxpl(2)
+        .dense(5, Activation::Relu, None)
+        .dense(3, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap()
+}
+
+fn kernels(cnn: &CNN) -> &Array4<f32> {
+    match &cnn.layers()[0] {
+        Layer::Conv(conv_layer) => &conv_layer.kernels,
+        _ => unreachable!(),
+    }
+}
+
+fn dense_weights(cnn: &CNN) -> &Array2<f32> {
+    match &cnn.layers()[2] {
+        Layer::Dense(dense_layer) => &dense_layer.weights,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn export_then_import_gives_identical_weights_in_every_layout() {
+    let dir = temp_dir("round_trip");
+    let source = network();
+    for layout in LAYOUTS {
+        let path = dir.join(format!("{:?}.npz", layout));
+        source.export_npz(&path, layout).unwrap();
+        let names: Vec<String> = numpy::read_npz(&path).unwrap().into_keys().collect();
+        assert_eq!(names.len(), 5, "{:?}", names);
+
+        let mut target = network();
+        target.import_npz(&path, layout).unwrap();
+        assert_eq!(numpy::export_weights(target.layers(), WeightLayout::OxiNet), numpy::export_weights(source.layers(), WeightLayout::OxiNet));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exported_shapes_follow_each_layout() {
+    let cnn = network();
+    let shapes = |layout| -> Vec<(String, Vec<usize>)> {
+        numpy::export_weights(cnn.layers(), layout).into_iter().map(|(name, array)| (name, array.shape().to_vec())).collect()
+    };
+    let names = ["0.weight", "2.weight", "2.bias", "3.weight", "3.bias"];
+    let expect = |dims: [Vec<usize>; 5]| -> Vec<(String, Vec<usize>)> {
+        names.iter().map(|n| n.to_string()).zip(dims).collect()
+    };
+    assert_eq!(shapes(WeightLayout::OxiNet), expect([vec![4, 3, 3, 2], vec![5, 24], vec![5], vec![3, 5], vec![3]]));
+    assert_eq!(shapes(WeightLayout::PyTorch), expect([vec![4, 2, 3, 3], vec![5, 24], vec![5], vec![3, 5], vec![3]]));
+    assert_eq!(shapes(WeightLayout::Keras), expect([vec![3, 3, 2, 4], vec![24, 5], vec![5], vec![5, 3], vec![3]]));
+}
+
+#[test]
+fn imports_pytorch_and_keras_layouts_to_the_right_elements() {
+    // Every element encodes its own index in the source layout
+    let torch_kernels = ArrayD::from_shape_fn(IxDyn(&[4, 2, 3, 3]), |i| (i[0] * 1000 + i[1] * 100 + i[2] * 10 + i[3]) as f32);
+    let torch_dense = ArrayD::from_shape_fn(IxDyn(&[5, 24]), |i| (i[0] * 100 + i[1]) as f32);
+    let mut cnn = network();
+    cnn.set_param(0, Param::Weights, torch_kernels, WeightLayout::PyTorch).unwrap();
+    cnn.set_param(2, Param::Weights, torch_dense, WeightLayout::PyTorch).unwrap();
+    // oxi_net kernels are [filter, ky, kx, channel]
+    assert_eq!(kernels(&cnn)[[3, 2, 1, 1]], 3121.0);
+    assert_eq!(kernels(&cnn)[[1, 0, 2, 0]], 1002.0);
+    // PyTorch flattens the pooled (3, 2, 4) output as [channel, row, col], oxi_net as [row, col, channel]
+    for (y, x, c) in [(0, 0, 1), (2, 1, 3), (1, 0, 2)] {
+        let oxi_column = (y * 2 + x) * 4 + c;
+        let torch_column = (c * 3 + y) * 2 + x;
+        assert_eq!(dense_weights(&cnn)[[4, oxi_column]], (400 + torch_column) as f32);
+    }
+
+    let keras_kernels = ArrayD::from_shape_fn(IxDyn(&[3, 3, 2, 4]), |i| (i[0] * 1000 + i[1] * 100 + i[2] * 10 + i[3]) as f32);
+    let keras_dense = ArrayD::from_shape_fn(IxDyn(&[24, 5]), |i| (i[0] * 100 + i[1]) as f32);
+    cnn.set_param(0, Param::Weights, keras_kernels, WeightLayout::Keras).unwrap();
+    cnn.set_param(2, Param::Weights, keras_dense, WeightLayout::Keras).unwrap();
+    assert_eq!(kernels(&cnn)[[3, 2, 1, 1]], 2113.0);
+    // Keras flattens channels last like oxi_net, so only the weight matrix is transposed
+    assert_eq!(dense_weights(&cnn)[[4, 17]], 1704.0);
+}
+
+#[test]
+fn permute_columns_is_inverted_by_the_reverse_permutation() {
+    let weights = Array2::from_shape_fn((2, 24), |(o, i)| (o * 100 + i) as f32);
+    let torch = numpy::permute_columns(&weights, (3, 2, 4), true);
+    assert_ne!(torch, weights);
+    // Column (y=1, x=0, c=2) is 10 in oxi_net order and 14 in channels-first order
+    assert_eq!(torch[[1, 14]], 110.0);
+    assert_eq!(numpy::permute_columns(&torch, (3, 2, 4), false), weights);
+}
+
+#[test]
+fn reports_wrong_shapes_and_names() {
+    let mut cnn = network();
+    let before = numpy::export_weights(cnn.layers(), WeightLayout::OxiNet);
+    let mut arrays: HashMap<String, ArrayD<f32>> = before.iter().cloned().collect();
+    arrays.insert(String::from("0.weight"), ArrayD::zeros(IxDyn(&[4, 3, 3, 3])));
+    arrays.insert(String::from("2.bias"), ArrayD::zeros(IxDyn(&[5, 1])));
+    arrays.remove("3.bias");
+    arrays.insert(String::from("0.bias"), ArrayD::zeros(IxDyn(&[4])));
+    let mapping = numpy::default_param_names(cnn.layers());
+    let errors = cnn.import_weights(&arrays, &mapping, WeightLayout::OxiNet).err().unwrap();
+    assert_eq!(errors, [
+        "0.weight: Layer 0 kernels should have shape [4, 3, 3, 2] but the array has shape [4, 3, 3, 3]",
+        "2.bias: Biases must have 1 dimension",
+        "Missing array 3.bias for layer 3",
+        "Array 0.bias does not match any layer parameter",
+    ].join("\n"));
+    // Nothing is imported unless every array is valid
+    assert_eq!(numpy::export_weights(cnn.layers(), WeightLayout::OxiNet), before);
+
+    let zeros = |dims: &[usize]| ArrayD::zeros(IxDyn(dims));
+    assert_eq!(cnn.set_param(0, Param::Biases, zeros(&[4]), WeightLayout::OxiNet).err().unwrap(), "Layer 0 is a convolutional layer, which has no biases");
+    assert_eq!(cnn.set_param(1, Param::Weights, zeros(&[1]), WeightLayout::OxiNet).err().unwrap(), "Layer 1 is a max pooling layer, which has no parameters");
+    assert_eq!(cnn.set_param(9, Param::Weights, zeros(&[1]), WeightLayout::OxiNet).err().unwrap(), "Layer 9 does not exist, the network has 4 layers");
+    assert_eq!(cnn.set_param(0, Param::Weights, zeros(&[4, 3, 3]), WeightLayout::PyTorch).err().unwrap(), "Kernels must have 4 dimensions");
+    // Keras dense weights are [input, output], so an oxi_net shaped array is rejected
+    assert_eq!(
+        cnn.set_param(3, Param::Weights, zeros(&[3, 5]), WeightLayout::Keras).err().unwrap(),
+        "Layer 3 weights should have shape [3, 5] but the array has shape [5, 3]"
+    );
+}
+
+#[test]
+fn parses_npy_files_written_by_numpy() {
+    let array = ArrayD::from_shape_fn(IxDyn(&[2, 3]), |i| (i[0] * 3 + i[1]) as f32 * 0.5);
+    let bytes = numpy::encode_npy(&array);
+    assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+    assert_eq!((bytes.len() - 6 * 4) % 64, 0);
+    assert_eq!(numpy::parse_npy(&bytes).unwrap(), array);
+
+    // A column-major float64 array, as `np.asfortranarray(a).astype('<f8')` would save it
+    let header = "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }";
+    let mut fortran = b"\x93NUMPY\x01\x00".to_vec();
+    fortran.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    fortran.extend_from_slice(header.as_bytes());
+    for value in [0.0f64, 1.5, 0.5, 2.0, 1.0, 2.5] {
+        fortran.extend_from_slice(&value.to_le_bytes());
+    }
+    assert_eq!(numpy::parse_npy(&fortran).unwrap(), array);
+
+    assert_eq!(numpy::parse_npy(b"not a numpy file").err().unwrap(), "Not a .npy file");
+    let mut int_header = bytes.clone();
+    let descr = int_header.windows(3).position(|w| w == b"<f4").unwrap();
+    int_header[descr + 1] = b'i';
+    assert_eq!(numpy::parse_npy(&int_header).err().unwrap(), "Unsupported dtype <i4, only float32 and float64 arrays can be loaded");
+    assert_eq!(numpy::parse_npy(&bytes[..bytes.len() - 4]).err().unwrap(), "Expected 24 bytes of data for shape [2, 3], found 20");
+
+    // A corrupt shape whose size overflows is reported rather than wrapping
+    let header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", usize::MAX, 3);
+    let mut huge = b"\x93NUMPY\x01\x00".to_vec();
+    huge.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    huge.extend_from_slice(header.as_bytes());
+    assert_eq!(numpy::parse_npy(&huge).err().unwrap(), format!("Array of shape [{}, 3] is too large", usize::MAX));
+}
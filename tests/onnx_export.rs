@@ -0,0 +1,69 @@
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::cnn::{Hyperparameters, CNN};
+use oxi_net::normalize::Normalization;
+use oxi_net::util::TrainingData;
+use tract_onnx::prelude::*;
+
+fn network() -> CNN {
+    let mut cnn = CNN::new(TrainingData::default(), Hyperparameters::default());
+    cnn.set_input_shape(vec![10, 10, 2]);
+    cnn.add_conv_layer(3, 3);
+    cnn.add_mxpl_layer(2);
+    cnn.add_dense_layer(6, Activation::Relu, Some(0.25));
+    cnn.add_dense_layer(5, Activation::Sigmoid, None);
+    cnn.add_dense_layer(4, Activation::Softmax, None);
+    cnn
+}
+
+fn assert_export_matches(cnn: CNN, name: &str) {
+    let path = std::env::temp_dir().join(format!("oxi_net_export_{}_{}.onnx", name, std::process::id()));
+    cnn.export_onnx(&path).unwrap();
+    let model = tract_onnx::onnx()
+        .model_for_path(&path)
+        .unwrap()
+        .into_optimized()
+        .unwrap()
+        .into_runnable()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let predictor = cnn.into_predictor();
+    for seed in 0..5 {
+        let image = Array3::<f32>::from_shape_fn((10, 10, 2), |(y, x, c)| ((y * 31 + x * 17 + c * 7 + seed) % 13) as f32 / 13.0);
+        let expected = predictor.predict_proba(&image);
+
+        // ONNX takes [batch, channels, rows, cols]
+        let input = tract_ndarray::Array4::<f32>::from_shape_fn((1, 2, 10, 10), |(_, c, y, x)| image[[y, x, c]]);
+        let outputs = model.run(tvec!(Tensor::from(input).into())).unwrap();
+        let actual = outputs[0].to_plain_array_view::<f32>().unwrap();
+
+        assert_eq!(actual.shape(), &[1, 4]);
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-5, "{} != {}", a, e);
+        }
+    }
+}
+
+#[test]
+fn exported_model_matches_oxi_net() {
+    assert_export_matches(network(), "plain");
+}
+
+#[test]
+fn exported_model_normalizes_inputs() {
+    let mut cnn = network();
+    cnn.set_normalization(Some(Normalization::new(vec![0.4, 0.6], vec![0.2, 0.5]).unwrap()));
+    assert_export_matches(cnn, "normalized");
+}
+
+#[test]
+fn exported_max_pooling_keeps_the_floor_of_minus_one() {
+    // Pooling the normalized input directly sees values far below -1
+    let mut cnn = CNN::new(TrainingData::default(), Hyperparameters::default());
+    cnn.set_input_shape(vec![10, 10, 2]);
+    cnn.add_mxpl_layer(2);
+    cnn.add_dense_layer(4, Activation::Softmax, None);
+    cnn.set_normalization(Some(Normalization::new(vec![0.9, 0.9], vec![0.05, 0.05]).unwrap()));
+    assert_export_matches(cnn, "pooled_input");
+}
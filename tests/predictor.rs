@@ -0,0 +1,65 @@
+use ndarray::Array3;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::util::TrainingData;
+
+fn random_image(seed: u64) -> Array3<f32> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Array3::from_shape_fn((8, 8, 2), |_| rng.gen::<f32>())
+}
+
+#[test]
+fn matches_forward_propagate_through_every_layer_type() {
+    let mut cnn = CnnBuilder::new()
+        .input_shape(vec![8, 8, 2])
+        .conv(4, 3)
+        .mxpl(2)
+        .dense(8, Activation::Relu, None)
+        .dense(3, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap();
+    let images: Vec<Array3<f32>> = (0..5).map(random_image).collect();
+    let expected: Vec<_> = images.iter().map(|image| cnn.forward_propagate(image.clone(), false)).collect();
+
+    let predictor = cnn.into_predictor();
+    assert_eq!((predictor.input_shape(), predictor.num_classes()), ((8, 8, 2), 3));
+    for (image, expected) in images.iter().zip(&expected) {
+        let output = predictor.predict_proba(image);
+        assert_eq!(output.len(), 3);
+        for (a, b) in output.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-6, "{} != {}", output, expected);
+        }
+    }
+    assert_eq!(predictor.predict_proba_batch(&images).len(), 5);
+}
+
+#[test]
+fn ranks_top_k_from_highest_to_lowest() {
+    let predictor = CnnBuilder::new()
+        .input_shape(vec![8, 8, 2])
+        .dense(6, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap()
+        .into_predictor();
+
+    let image = random_image(1);
+    let output = predictor.predict_proba(&image);
+    let top = predictor.predict_top_k(&image, 4);
+    assert_eq!(top.len(), 4);
+    assert!(top.windows(2).all(|w| w[0].1 >= w[1].1), "{:?}", top);
+    assert_eq!(top[0].0, predictor.predict(&image));
+    for &(class, value) in &top {
+        assert_eq!(output[class], value);
+    }
+    assert_eq!(predictor.predict_top_k(&image, 10).len(), 6);
+    assert!(predictor.predict_top_k(&image, 0).is_empty());
+
+    // A blank image only reaches the equal biases, so every class ties and keeps its index order
+    let blank = Array3::zeros((8, 8, 2));
+    let classes: Vec<usize> = predictor.predict_top_k(&blank, 6).iter().map(|&(class, _)| class).collect();
+    assert_eq!(classes, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(predictor.predict(&blank), 0);
+    assert_eq!(predictor.predict_top_k_batch(&[image, blank], 2).len(), 2);
+}
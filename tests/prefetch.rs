@@ -0,0 +1,162 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::Hyperparameters;
+use oxi_net::dataset::{Dataset, Label};
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::prefetch::{prefetch, CachedDataset, Prefetch};
+use oxi_net::util::TrainingData;
+
+/// Sample i is a 2x2 image filled with i, read slowly so that workers overlap
+struct Slow {
+    len: usize,
+    reads: AtomicUsize,
+}
+
+impl Slow {
+    fn new(len: usize) -> Slow {
+        Slow { len, reads: AtomicUsize::new(0) }
+    }
+}
+
+impl Dataset for Slow {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        if i == 13 {
+            panic!("Sample 13 is broken");
+        }
+        thread::sleep(Duration::from_millis((i % 3) as u64));
+        (Array3::from_elem((2, 2, 1), i as f32), i % 2)
+    }
+}
+
+fn options(workers: usize, queue_size: usize) -> Prefetch {
+    Prefetch { workers, queue_size, cache_mb: 0 }
+}
+
+#[test]
+fn yields_samples_in_order_with_a_bounded_queue() {
+    let data = Slow::new(12);
+    let indices = vec![5, 0, 11, 3, 3, 7, 1, 10, 2, 9, 4, 8, 6, 0];
+    let read = prefetch(&data, &indices, &options(4, 3), |samples| {
+        let mut read = vec![];
+        for (consumed, (image, label)) in samples.enumerate() {
+            // Workers only start samples that fit in the queue
+            assert!(data.reads.load(Ordering::SeqCst) <= consumed + 1 + 3);
+            read.push((image[[0, 0, 0]] as usize, label));
+        }
+        read
+    });
+    let expected: Vec<(usize, Label)> = indices.iter().map(|&i| (i, i % 2)).collect();
+    assert_eq!(read, expected);
+    assert_eq!(data.reads.load(Ordering::SeqCst), indices.len());
+
+    let sequential = prefetch(&data, &indices, &options(0, 3), |samples| samples.map(|(_, label)| label).collect::<Vec<_>>());
+    assert_eq!(sequential, expected.iter().map(|&(_, label)| label).collect::<Vec<_>>());
+}
+
+#[test]
+fn stops_workers_when_consumer_returns_early() {
+    let data = Slow::new(12);
+    let indices: Vec<usize> = (0..12).cycle().take(1000).collect();
+    let first = prefetch(&data, &indices, &options(3, 4), |samples| samples.take(2).count());
+    assert_eq!(first, 2);
+    assert!(data.reads.load(Ordering::SeqCst) <= 2 + 4);
+}
+
+#[test]
+#[should_panic(expected = "Sample 13 is broken")]
+fn raises_worker_panics_in_the_consumer() {
+    let data = Slow::new(20);
+    let indices: Vec<usize> = (0..20).collect();
+    prefetch(&data, &indices, &options(2, 4), |samples| samples.count());
+}
+
+#[test]
+fn cache_evicts_least_recently_used_samples() {
+    // Each sample takes 16 bytes, so two fit
+    let cached = CachedDataset::new(Slow::new(12), 40);
+    cached.get(0);
+    cached.get(1);
+    cached.get(0);
+    assert_eq!((cached.hits(), cached.misses()), (1, 2));
+    assert_eq!(cached.cached_bytes(), 32);
+
+    // 1 is the least recently used, so it makes room for 2
+    cached.get(2);
+    cached.get(0);
+    assert_eq!((cached.hits(), cached.misses()), (2, 3));
+    let (image, label) = cached.get(1);
+    assert_eq!((image[[1, 1, 0]], label), (1.0, 1));
+    assert_eq!((cached.hits(), cached.misses()), (2, 4));
+    assert_eq!(cached.into_inner().reads.load(Ordering::SeqCst), 4);
+
+    // Samples larger than the budget are never cached
+    let uncached = CachedDataset::new(Slow::new(12), 8);
+    uncached.get(0);
+    uncached.get(0);
+    assert_eq!((uncached.hits(), uncached.cached_bytes()), (0, 0));
+}
+
+#[test]
+fn splits_the_cache_budget_between_the_sets() {
+    let options = Prefetch { cache_mb: 3, ..Prefetch::default() };
+    assert_eq!(options.cache_budgets(2, 1), (2 << 20, 1 << 20));
+    assert_eq!(options.cache_budgets(10, 0), (3 << 20, 0));
+    assert_eq!(options.cache_budgets(0, 0), (3 << 20, 0));
+    let (train, test) = options.cache_budgets(7, 3);
+    assert_eq!(train + test, options.cache_bytes());
+    assert_eq!(Prefetch::default().cache_budgets(5, 5), (0, 0));
+}
+
+/// Generates 4x4 images: class 0 lights the top half, class 1 the bottom half
+struct Halves;
+
+impl Dataset for Halves {
+    fn len(&self) -> usize {
+        200
+    }
+
+    fn get(&self, i: usize) -> (Array3<f32>, Label) {
+        let label = i % 2;
+        let image = Array3::from_shape_fn((4, 4, 1), |(row, _, _)| {
+            if (row < 2) == (label == 0) { 1.0 } else { 0.0 }
+        });
+        (image, label)
+    }
+}
+
+fn builder(prefetch: Prefetch) -> CnnBuilder {
+    CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(8, Activation::Relu, None)
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            batch_size: 4,
+            epochs: 5,
+            optimizer: OptimizerAlg::SGD(0.1),
+            verbose: false,
+            ..Hyperparameters::default()
+        })
+        .prefetch(prefetch)
+}
+
+#[test]
+fn trains_with_workers_and_a_cache() {
+    let mut cnn = builder(Prefetch { workers: 3, queue_size: 8, cache_mb: 1 }).build(TrainingData::default()).unwrap();
+    cnn.train_on(&Halves, Some(&Halves)).unwrap();
+    assert_eq!(cnn.evaluate(&Halves).accuracy(), 1.0);
+}
+
+#[test]
+fn rejects_an_empty_queue() {
+    let err = builder(options(2, 0)).build(TrainingData::default()).err().unwrap();
+    assert!(err.contains("Prefetch queue size must be at least 1"), "{}", err);
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use ndarray::{array, Array3};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::{Hyperparameters, CNN};
+use oxi_net::dataset::{class_counts, Dataset, InMemoryDataset, Label};
+use oxi_net::layer::Layer;
+use oxi_net::loss::ClassWeights;
+use oxi_net::metrics::Metrics;
+use oxi_net::optimizer::OptimizerAlg;
+use oxi_net::sampler::{Sampler, WeightedSampler};
+use oxi_net::util::{TrainImage, TrainingData};
+
+/// 9 samples of class 0, then 1 sample of class 1
+fn skewed() -> InMemoryDataset {
+    let labels: Vec<Label> = (0..10).map(|i| (i == 9) as usize).collect();
+    InMemoryDataset::new(vec![Array3::zeros((1, 1, 1)); 10], labels).unwrap()
+}
+
+/// Share of draws that land on a sample of class 1
+fn minority_share(sampler: &Sampler, data: &InMemoryDataset) -> f32 {
+    let weights = sampler.sample_weights(data, 2).unwrap().unwrap();
+    let sampler = WeightedSampler::new(&weights).unwrap();
+    let mut rng = StdRng::seed_from_u64(7);
+    let draws = 20000;
+    (0..draws).filter(|_| data.label(sampler.sample(&mut rng)) == 1).count() as f32 / draws as f32
+}
+
+#[test]
+fn samplers_weight_classes() {
+    let data = skewed();
+    assert_eq!(class_counts(&data, 2).unwrap(), vec![9, 1]);
+    assert_eq!(class_counts(&data, 1).err().unwrap(), "Sample 9 has label 1 but there are only 1 classes");
+
+    assert!(Sampler::Uniform.sample_weights(&data, 2).unwrap().is_none());
+    assert!((minority_share(&Sampler::ClassBalanced, &data) - 0.5).abs() < 0.02);
+    let weighted = Sampler::Weighted { class_weights: vec![3.0, 1.0] };
+    assert!((minority_share(&weighted, &data) - 0.25).abs() < 0.02);
+
+    assert!(WeightedSampler::new(&[0.0, 0.0]).is_err());
+}
+
+#[test]
+fn balanced_class_weights_follow_label_frequencies() {
+    let weights = ClassWeights::Balanced.weights(&skewed(), 3).unwrap().unwrap();
+    // 10 samples over 3 classes, the last class has none
+    assert!((weights[0] - 10.0 / 27.0).abs() < 1e-6);
+    assert!((weights[1] - 10.0 / 3.0).abs() < 1e-6);
+    assert_eq!(weights[2], 1.0);
+
+    assert!(ClassWeights::None.weights(&skewed(), 2).unwrap().is_none());
+    let fixed = ClassWeights::Fixed { weights: vec![1.0, 2.0] };
+    assert_eq!(fixed.weights(&skewed(), 2).unwrap().unwrap(), array![1.0, 2.0]);
+    assert_eq!(fixed.weights(&skewed(), 3).err().unwrap(), "Class weights have 2 values but the network has 3 classes");
+}
+
+#[test]
+fn balanced_accuracy_averages_recall_over_present_classes() {
+    let mut metrics = Metrics::new(3);
+    for _ in 0..8 {
+        metrics.record(0, &array![0.9, 0.1, 0.0]);
+    }
+    metrics.record(1, &array![0.9, 0.1, 0.0]);
+    metrics.record(1, &array![0.1, 0.9, 0.0]);
+    assert_eq!(metrics.accuracy(), 0.9);
+    // Class 2 has no samples, so only classes 0 and 1 count
+    assert_eq!(metrics.balanced_accuracy(), 0.75);
+    assert!(format!("{:?}", metrics).contains("Balanced Accuracy: 0.7500"));
+    assert_eq!(Metrics::new(2).balanced_accuracy(), 0.0);
+}
+
+fn builder() -> CnnBuilder {
+    CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(8, Activation::Relu, None)
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            batch_size: 4,
+            epochs: 5,
+            optimizer: OptimizerAlg::SGD(0.1),
+            verbose: false,
+            ..Hyperparameters::default()
+        })
+}
+
+#[test]
+fn builder_checks_weights() {
+    let errors = builder()
+        .sampler(Sampler::Weighted { class_weights: vec![1.0, 1.0, 1.0] })
+        .class_weights(ClassWeights::Fixed { weights: vec![-1.0, 1.0] })
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    assert!(errors.contains("Sampler has 3 class weights but the network has 2 classes"), "{}", errors);
+    assert!(errors.contains("Class weights must be finite and non-negative, got -1"), "{}", errors);
+
+    let errors = builder().sampler(Sampler::Weighted { class_weights: vec![0.0, 0.0] }).build(TrainingData::default()).err().unwrap();
+    assert_eq!(errors, "Sampler class weights need at least one positive weight");
+
+    // Only class 0 is in the training data, and its weight is zero
+    let data = TrainingData {
+        trn_img: vec![TrainImage::Image(Array3::zeros((4, 4, 1))); 3],
+        trn_lbl: vec![0; 3],
+        trn_size: 3,
+        classes: HashMap::from([(0, 0), (1, 1)]),
+        ..TrainingData::default()
+    };
+    let errors = builder().sampler(Sampler::Weighted { class_weights: vec![0.0, 1.0] }).build(data).err().unwrap();
+    assert_eq!(errors, "Sampler class weights are zero for every class in the training data");
+    let weighted = Sampler::Weighted { class_weights: vec![0.0, 1.0] };
+    let only_class_0 = InMemoryDataset::new(vec![Array3::zeros((1, 1, 1)); 3], vec![0; 3]).unwrap();
+    assert_eq!(weighted.sample_weights(&only_class_0, 2).err().unwrap(), "Sampler class weights are zero for every class in the training data");
+    assert!(weighted.sample_weights(&skewed(), 2).is_ok());
+}
+
+#[test]
+fn back_propagate_applies_the_class_weights() {
+    let mut cnn = builder()
+        .epochs(0)
+        .class_weights(ClassWeights::Fixed { weights: vec![0.0, 1.0] })
+        .build(TrainingData::default())
+        .unwrap();
+    // Training for no epochs only sets up the class weights
+    let train = InMemoryDataset::new(vec![Array3::ones((4, 4, 1)); 10], skewed().labels).unwrap();
+    cnn.train_on(&train, None::<&InMemoryDataset>).unwrap();
+    let weights = |cnn: &CNN| match &cnn.layers()[1] {
+        Layer::Dense(dense_layer) => dense_layer.weights.clone(),
+        _ => unreachable!(),
+    };
+    let before = weights(&cnn);
+    let image = Array3::ones((4, 4, 1));
+
+    cnn.forward_propagate(image.clone(), true);
+    cnn.back_propagate(0, true);
+    cnn.update(1);
+    assert_eq!(weights(&cnn), before);
+
+    cnn.forward_propagate(image, true);
+    cnn.back_propagate(1, true);
+    cnn.update(1);
+    assert_ne!(weights(&cnn), before);
+}
+
+#[test]
+fn learns_a_rare_class() {
+    // 1 in 20 samples lights the bottom half instead of the top half
+    let image = |label: Label| Array3::from_shape_fn((4, 4, 1), |(row, _, _)| ((row < 2) == (label == 0)) as usize as f32);
+    let labels: Vec<Label> = (0..200).map(|i| (i % 20 == 0) as usize).collect();
+    let train = InMemoryDataset::new(labels.iter().map(|&l| image(l)).collect(), labels).unwrap();
+
+    let mut cnn = builder()
+        .sampler(Sampler::ClassBalanced)
+        .class_weights(ClassWeights::Balanced)
+        .build(TrainingData::default())
+        .unwrap();
+    cnn.train_on(&train, None::<&InMemoryDataset>).unwrap();
+    assert_eq!(cnn.evaluate(&train).balanced_accuracy(), 1.0);
+}
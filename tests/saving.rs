@@ -0,0 +1,191 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use ndarray::Array3;
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::{Hyperparameters, CNN};
+use oxi_net::dataset::InMemoryDataset;
+use oxi_net::model_file::{self, ModelFormat};
+use oxi_net::util::{SavingStrategy, TrainingData};
+
+mod common;
+use common::temp_dir;
+
+fn cnn(save_dir: &Path, file_name: &str, keep_last: Option<usize>, model_format: ModelFormat) -> CNN {
+    CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            name: String::from("net"),
+            verbose: false,
+            model_format,
+            save_dir: save_dir.to_path_buf(),
+            file_name: String::from(file_name),
+            keep_last,
+            ..Hyperparameters::default()
+        })
+        .build(TrainingData::default())
+        .unwrap()
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Saves with a `{timestamp}` template, waiting so that every save gets its own file
+fn save(cnn: &mut CNN, full_save: bool) {
+    thread::sleep(Duration::from_millis(3));
+    cnn.save(full_save).unwrap();
+}
+
+#[test]
+fn save_to_writes_the_model_format_and_load_from_detects_it() {
+    let dir = temp_dir("formats");
+    for (format, file_name) in [(ModelFormat::Json, "model.json"), (ModelFormat::Binary, "model.bin"), (ModelFormat::BinaryF16, "half.bin")] {
+        let model = cnn(&dir, "{name}", None, format);
+        let path = dir.join(file_name);
+        model.save_to(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap().starts_with(b"OXIN"), format != ModelFormat::Json);
+
+        let loaded = CNN::load_from(&path).unwrap();
+        assert_eq!((loaded.model_format(), loaded.name()), (format, "net"));
+    }
+    let err = CNN::load_from(dir.join("missing.json")).err().unwrap();
+    assert!(err.starts_with(&format!("Failed to load {}", dir.join("missing.json").display())), "{}", err);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn expands_the_file_name_template() {
+    let dir = temp_dir("template");
+    let mut model = cnn(&dir, "{name}-e{epoch}-{time}", None, ModelFormat::Json);
+    model.save(true).unwrap();
+
+    let files = files(&dir);
+    assert_eq!(files.len(), 2, "{:?}", files);
+    let time = files[0].strip_prefix("net-e0-").unwrap().strip_suffix(".json").unwrap();
+    assert!(!time.is_empty() && time.chars().all(|c| c.is_ascii_digit()), "{:?}", files);
+    assert_eq!(files[1], format!("net-e0-{}.txt", time));
+    let metadata = std::fs::read_to_string(dir.join(&files[1])).unwrap();
+    assert!(metadata.contains(&format!("File: {}", dir.join(&files[0]).display())), "{}", metadata);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn keeps_the_last_saves_across_reloads() {
+    let dir = temp_dir("keep_last");
+    let mut model = cnn(&dir, "{name}_{timestamp}", Some(2), ModelFormat::Json);
+    save(&mut model, true);
+    save(&mut model, false);
+    save(&mut model, true);
+    assert_eq!(files(&dir).len(), 5);
+
+    // A reloaded model prunes the saves made before it was loaded
+    let latest = files(&dir).into_iter().rfind(|f| f.ends_with(".json")).unwrap();
+    let mut model = CNN::load_from(dir.join(&latest)).unwrap();
+    save(&mut model, true);
+    let kept = files(&dir);
+    assert_eq!(kept.len(), 4, "{:?}", kept);
+    assert_eq!(kept[0], latest);
+    assert_eq!(kept.iter().filter(|f| f.ends_with(".txt")).count(), 2);
+
+    // Metadata newer than the oldest kept save stays until that save is pruned
+    save(&mut model, false);
+    assert_eq!(files(&dir).len(), 5);
+    save(&mut model, true);
+    let kept = files(&dir);
+    assert_eq!(kept.len(), 5, "{:?}", kept);
+    assert!(!kept.contains(&latest));
+
+    // Files that do not match the template are left alone
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    std::fs::write(dir.join("other_123.json"), "").unwrap();
+    save(&mut model, true);
+    let kept = files(&dir);
+    assert!(kept.contains(&String::from("notes.txt")) && kept.contains(&String::from("other_123.json")), "{:?}", kept);
+    assert_eq!(kept.iter().filter(|f| f.starts_with("net_") && f.ends_with(".json")).count(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn never_deletes_the_latest_save() {
+    let dir = temp_dir("keep_none");
+    let params = Hyperparameters {
+        name: String::from("net"),
+        verbose: false,
+        save_dir: dir.clone(),
+        file_name: String::from("{name}_{timestamp}"),
+        keep_last: Some(0),
+        ..Hyperparameters::default()
+    };
+    let err = CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(params.clone())
+        .build(TrainingData::default())
+        .err()
+        .unwrap();
+    assert_eq!(err, "keep_last must be at least 1, or unset to keep every save");
+
+    // A network made without the builder treats 0 as keeping only the new save
+    let mut model = CNN::new(TrainingData::default(), params);
+    model.set_input_shape(vec![4, 4, 1]);
+    model.add_dense_layer(2, Activation::Softmax, None);
+    save(&mut model, true);
+    save(&mut model, true);
+    let kept = files(&dir);
+    assert_eq!(kept.len(), 2, "{:?}", kept);
+    assert_eq!(kept.iter().filter(|f| f.ends_with(".json")).count(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reports_failed_saves_and_keeps_training() {
+    let dir = temp_dir("unwritable");
+    // A file where the save directory should be cannot be created as a directory
+    let save_dir = dir.join("file");
+    std::fs::write(&save_dir, "").unwrap();
+    let mut model = CnnBuilder::new()
+        .input_shape(vec![4, 4, 1])
+        .dense(2, Activation::Softmax, None)
+        .hyperparameters(Hyperparameters {
+            verbose: false,
+            epochs: 2,
+            save_dir: save_dir.clone(),
+            saving_strategy: SavingStrategy::EveryEpoch(true),
+            ..Hyperparameters::default()
+        })
+        .build(TrainingData::default())
+        .unwrap();
+    let err = model.save(true).err().unwrap();
+    assert!(err.starts_with(&format!("Failed to create {}", save_dir.display())), "{}", err);
+
+    let train = InMemoryDataset::new(vec![Array3::zeros((4, 4, 1)); 4], vec![0, 1, 0, 1]).unwrap();
+    let err = model.train_on(&train, None::<&InMemoryDataset>).err().unwrap();
+    assert!(err.starts_with("2 of the saves during training failed, the first with: Failed to create"), "{}", err);
+    // Both epochs still ran, one save each, and the network can be saved elsewhere
+    model.save_to(dir.join("rescued.json")).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn writes_atomically() {
+    let dir = temp_dir("atomic");
+    let path = dir.join("file.txt");
+    model_file::write_atomic(&path, b"first").unwrap();
+    model_file::write_atomic(&path, b"second").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    assert_eq!(files(&dir), vec!["file.txt"]);
+
+    let missing = dir.join("missing").join("file.txt");
+    let err = model_file::write_atomic(&missing, b"data").err().unwrap();
+    assert!(err.starts_with("Failed to write"), "{}", err);
+    assert!(model_file::write_atomic(&dir, b"data").is_err());
+    assert_eq!(files(&dir), vec!["file.txt"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use ndarray::Array3;
+use serde_json::{json, Value};
+use oxi_net::cnn::CNN;
+use oxi_net::config::NetworkConfig;
+use oxi_net::search::{ParamRange, Parameter, Search, Strategy};
+use oxi_net::util::{ImageOptions, TrainImage, TrainingData};
+
+mod common;
+use common::temp_dir;
+
+fn base() -> NetworkConfig {
+    NetworkConfig::from_json(r#"{
+        "input_shape": [4, 4, 1],
+        "layers": [
+            {"type": "dense", "output_size": 8, "activation": "relu"},
+            {"type": "dense", "output_size": 2, "activation": "softmax"}
+        ],
+        "hyperparameters": {"batch_size": 4, "epochs": 3, "optimizer": {"type": "sgd", "lr": 0.1}, "verbose": false}
+    }"#).unwrap()
+}
+
+/// Class 0 lights the top half of a 4x4 image, class 1 the bottom half
+fn data() -> TrainingData {
+    let image = |label: usize| TrainImage::Image(Array3::from_shape_fn((4, 4, 1), |(row, _, _)| ((row < 2) == (label == 0)) as usize as f32));
+    let trn_lbl: Vec<usize> = (0..100).map(|i| i % 2).collect();
+    let tst_lbl: Vec<usize> = (0..10).map(|i| i % 2).collect();
+    TrainingData {
+        trn_img: trn_lbl.iter().map(|&l| image(l)).collect(),
+        tst_img: tst_lbl.iter().map(|&l| image(l)).collect(),
+        trn_size: trn_lbl.len(),
+        tst_size: tst_lbl.len(),
+        trn_lbl,
+        tst_lbl,
+        rows: 4,
+        cols: 4,
+        classes: HashMap::from([(0, 0), (1, 1)]),
+        label_names: HashMap::new(),
+        image_options: ImageOptions::default(),
+    }
+}
+
+fn parameter(path: &str, range: ParamRange) -> Parameter {
+    Parameter { path: String::from(path), range }
+}
+
+#[test]
+fn ranges_list_grid_values_and_sample_within_bounds() {
+    assert_eq!(ParamRange::IntRange { min: 8, max: 32, step: 8 }.grid(), vec![json!(8), json!(16), json!(24), json!(32)]);
+    assert_eq!(ParamRange::Uniform { min: 0.0, max: 0.5, steps: 3 }.grid(), vec![json!(0.0), json!(0.25), json!(0.5)]);
+    assert_eq!(ParamRange::Uniform { min: 0.1, max: 0.5, steps: 1 }.grid(), vec![json!(0.1)]);
+    let rates: Vec<f64> = ParamRange::LogUniform { min: 1e-3, max: 1e-1, steps: 3 }.grid().iter().map(|v| v.as_f64().unwrap()).collect();
+    for (rate, expected) in rates.iter().zip([1e-3, 1e-2, 1e-1]) {
+        assert!((rate / expected - 1.0).abs() < 1e-9, "{:?}", rates);
+    }
+
+    let search = Search {
+        parameters: vec![
+            parameter("layers.0.output_size", ParamRange::IntRange { min: 2, max: 10, step: 4 }),
+            parameter("hyperparameters.optimizer.lr", ParamRange::LogUniform { min: 1e-3, max: 1e-1, steps: 3 }),
+        ],
+        strategy: Strategy::Random,
+        trials: Some(50),
+        seed: 1,
+        objective: Default::default(),
+        validation_folds: 5,
+    };
+    let trials = search.trial_values();
+    assert_eq!(trials.len(), 50);
+    for values in &trials {
+        assert!([2, 6, 10].contains(&values[0].as_i64().unwrap()));
+        assert!((1e-3..=1e-1).contains(&values[1].as_f64().unwrap()));
+    }
+    assert_eq!(search.trial_values(), trials);
+    assert_ne!(Search { seed: 2, ..search.clone() }.trial_values(), trials);
+}
+
+#[test]
+fn grid_search_visits_every_combination_up_to_the_budget() {
+    let mut search = Search {
+        parameters: vec![
+            parameter("hyperparameters.batch_size", ParamRange::Choice { values: vec![json!(2), json!(4)] }),
+            parameter("layers.0.dropout", ParamRange::Choice { values: vec![Value::Null, json!(0.1), json!(0.2)] }),
+        ],
+        strategy: Strategy::Grid,
+        trials: None,
+        seed: 0,
+        objective: Default::default(),
+        validation_folds: 5,
+    };
+    let trials = search.trial_values();
+    assert_eq!(trials.len(), 6);
+    assert_eq!(trials[1], vec![json!(2), json!(0.1)]);
+    assert_eq!(trials[5], vec![json!(4), json!(0.2)]);
+    search.trials = Some(4);
+    assert_eq!(search.trial_values(), trials[..4].to_vec());
+}
+
+#[test]
+fn validates_against_the_base_config() {
+    let search = Search {
+        parameters: vec![
+            parameter("layers.5.output_size", ParamRange::Choice { values: vec![json!(2)] }),
+            parameter("hyperparameters.batch_size", ParamRange::IntRange { min: 4, max: 2, step: 1 }),
+        ],
+        strategy: Strategy::Random,
+        trials: None,
+        seed: 0,
+        objective: Default::default(),
+        validation_folds: 1,
+    };
+    let errors = search.validate(&base()).err().unwrap();
+    assert_eq!(errors, [
+        "Random search needs a number of trials",
+        "validation_folds must be at least 2, got 1",
+        "Parameter 0: layers.5.output_size is not a setting of the config",
+        "Parameter 1 (hyperparameters.batch_size): Integer range needs min <= max and a positive step, got 4 to 2 every 1",
+    ].join("\n"));
+
+    let example = Search::load("configs/mnist_search.toml").unwrap();
+    assert_eq!(example.validate(&NetworkConfig::load("configs/mnist.toml").unwrap()), Ok(()));
+}
+
+#[test]
+fn runs_trials_and_saves_the_leaderboard_and_best_model() {
+    let search = Search {
+        parameters: vec![
+            // A width of 0 is invalid, so those trials fail without stopping the search
+            parameter("layers.0.output_size", ParamRange::Choice { values: vec![json!(0), json!(8)] }),
+            parameter("hyperparameters.batch_size", ParamRange::Choice { values: vec![json!(2), json!(4)] }),
+        ],
+        strategy: Strategy::Grid,
+        trials: None,
+        seed: 0,
+        objective: Default::default(),
+        validation_folds: 5,
+    };
+    let results = search.run(&base(), &data()).unwrap();
+    assert_eq!(results.trials.len(), 4);
+    assert!(results.trials[..2].iter().all(|t| t.score.is_none() && t.error.as_ref().unwrap().contains("output_size must be at least 1")));
+
+    // Trials are scored on one stratified fold of the 100 training samples, and only
+    // the best network is evaluated on the 10 test samples
+    for trial in &results.trials[2..] {
+        let metrics = trial.metrics.as_ref().unwrap();
+        assert_eq!((metrics.support(0), metrics.support(1)), (10, 10));
+    }
+    let test_metrics = results.test_metrics.as_ref().unwrap();
+    assert_eq!((test_metrics.support(0), test_metrics.support(1)), (5, 5));
+
+    let leaderboard = results.leaderboard();
+    assert!(leaderboard[..2].iter().all(|t| t.score.is_some()));
+    assert!(leaderboard[0].score >= leaderboard[1].score);
+    assert_eq!(results.best_trial().unwrap().number, leaderboard[0].number);
+
+    let dir = temp_dir("run");
+    let best = results.save(&dir).unwrap().unwrap();
+    assert_eq!(best, dir.join("best_model.json"));
+    let cnn = CNN::load_from(&best).unwrap();
+    assert_eq!(cnn.input_shape(), (4, 4, 1));
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(dir.join("test_metrics.json")).unwrap()).unwrap();
+    assert_eq!(saved, serde_json::to_value(test_metrics).unwrap());
+
+    let csv = std::fs::read_to_string(dir.join("leaderboard.csv")).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "rank,trial,score,accuracy,balanced_accuracy,macro_f1,seconds,layers.0.output_size,hyperparameters.batch_size,error");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1].starts_with(&format!("1,{},", leaderboard[0].number)));
+    let json: Value = serde_json::from_str(&std::fs::read_to_string(dir.join("leaderboard.json")).unwrap()).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 4);
+    assert_eq!(json[0]["values"]["layers.0.output_size"], json!(8));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn checks_every_trial_against_the_data() {
+    let search = Search {
+        parameters: vec![parameter("layers.1.output_size", ParamRange::Choice { values: vec![json!(1), json!(2)] })],
+        strategy: Strategy::Grid,
+        trials: None,
+        seed: 0,
+        objective: Default::default(),
+        validation_folds: 5,
+    };
+    let mut data = data();
+    data.label_names = HashMap::from([(0, String::from("top")), (1, String::from("bottom"))]);
+    let results = search.run(&base(), &data).unwrap();
+    assert_eq!(results.trials[0].error.as_deref(), Some("Layer 1: has 1 outputs but the training data has 2 classes"));
+    assert!(results.trials[1].score.is_some());
+
+    let dir = temp_dir("labels");
+    let cnn = CNN::load_from(results.save(&dir).unwrap().unwrap()).unwrap();
+    assert_eq!(cnn.class_names(), Some(vec![String::from("top"), String::from("bottom")]));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use serde_json::{json, Value};
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::cnn::CNN;
+use oxi_net::server::InferenceServer;
+use oxi_net::util::{decode_image, TrainingData};
+
+fn network() -> CNN {
+    CnnBuilder::new()
+        .input_shape(vec![6, 6, 3])
+        .conv(2, 3)
+        .dense(3, Activation::Softmax, None)
+        .name("tiny")
+        .build(TrainingData::default())
+        .unwrap()
+}
+
+/// Sends a single HTTP/1.1 request and returns the status code and JSON body
+fn request(addr: SocketAddr, method: &str, path: &str, content_type: &str, body: &[u8]) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method, path, content_type, body.len()
+    ).unwrap();
+    stream.write_all(body).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn png(image: RgbImage) -> Vec<u8> {
+    let mut bytes = vec![];
+    DynamicImage::ImageRgb8(image).write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+    bytes
+}
+
+#[test]
+fn serves_predictions_on_loopback() {
+    let model = std::env::temp_dir().join(format!("oxi_net_server_{}.json", std::process::id()));
+    network().save_to(&model).unwrap();
+    let encoded = png(RgbImage::from_fn(6, 6, |x, y| Rgb([(x * 40) as u8, (y * 40) as u8, 128])));
+    let tensor = decode_image(&encoded).unwrap();
+    let expected = CNN::load_from(&model).unwrap().into_predictor().predict_proba(&tensor);
+
+    let server = InferenceServer::bind(CNN::load_from(&model).unwrap(), "127.0.0.1:0").unwrap()
+        .with_labels(vec![String::from("a"), String::from("b"), String::from("c")])
+        .unwrap();
+    let server = Arc::new(server);
+    let addr = server.local_addr().unwrap();
+    let handle = {
+        let server = Arc::clone(&server);
+        thread::spawn(move || server.run())
+    };
+
+    let (status, health) = request(addr, "GET", "/health", "text/plain", b"");
+    assert_eq!(status, 200);
+    assert_eq!(health["model"]["name"], "tiny");
+    assert_eq!(health["model"]["input_shape"], json!([6, 6, 3]));
+    assert_eq!(health["model"]["num_classes"], 3);
+
+    let (status, from_png) = request(addr, "POST", "/predict?k=2", "image/png", &encoded);
+    assert_eq!(status, 200, "{}", from_png);
+    assert_eq!(from_png["top_k"].as_array().unwrap().len(), 2);
+    assert!(from_png["top_k"][0]["label"].is_string());
+    // The probabilities pass through JSON as f64, so the last digit may differ
+    let probabilities: Vec<f64> = serde_json::from_value(from_png["probabilities"].clone()).unwrap();
+    assert_eq!(probabilities.len(), expected.len());
+    for (served, expected) in probabilities.iter().zip(expected.iter()) {
+        assert!((served - *expected as f64).abs() < 1e-6, "{} vs {}", served, expected);
+    }
+
+    let body = json!({"shape": [6, 6, 3], "data": tensor.iter().collect::<Vec<_>>()}).to_string();
+    let (status, from_tensor) = request(addr, "POST", "/predict", "application/json", body.as_bytes());
+    assert_eq!(status, 200, "{}", from_tensor);
+    assert_eq!(from_tensor["probabilities"], from_png["probabilities"]);
+    assert_eq!(from_tensor["top_k"].as_array().unwrap().len(), 3);
+
+    let wrong_size = png(RgbImage::new(8, 8));
+    let (status, err) = request(addr, "POST", "/predict", "image/png", &wrong_size);
+    assert_eq!(status, 400);
+    assert!(err["error"].as_str().unwrap().contains("expects (6, 6, 3)"), "{}", err);
+
+    let (status, _) = request(addr, "POST", "/predict", "image/png", b"not an image");
+    assert_eq!(status, 400);
+    let (status, _) = request(addr, "GET", "/predict", "text/plain", b"");
+    assert_eq!(status, 405);
+    let (status, _) = request(addr, "GET", "/missing", "text/plain", b"");
+    assert_eq!(status, 404);
+
+    server.stop();
+    handle.join().unwrap();
+    std::fs::remove_file(model).unwrap();
+}
+
+#[test]
+fn rejects_labels_for_the_wrong_number_of_classes() {
+    let server = InferenceServer::bind(network(), "127.0.0.1:0").unwrap();
+    let err = server.with_labels(vec![String::from("a")]).err().unwrap();
+    assert_eq!(err, "Got 1 labels but the model has 3 classes");
+}
+
+#[test]
+fn leaves_outputs_without_a_class_name_unlabelled() {
+    let data = TrainingData {
+        classes: HashMap::from([(0, 0), (1, 1)]),
+        label_names: HashMap::from([(0, String::from("cat")), (1, String::from("dog"))]),
+        ..TrainingData::default()
+    };
+    let cnn = CnnBuilder::new()
+        .input_shape(vec![6, 6, 3])
+        .dense(4, Activation::Softmax, None)
+        .build(data)
+        .unwrap();
+    let server = Arc::new(InferenceServer::bind(cnn, "127.0.0.1:0").unwrap());
+    let addr = server.local_addr().unwrap();
+    let handle = {
+        let server = Arc::clone(&server);
+        thread::spawn(move || server.run())
+    };
+
+    let (status, response) = request(addr, "POST", "/predict?k=4", "image/png", &png(RgbImage::new(6, 6)));
+    assert_eq!(status, 200, "{}", response);
+    let mut labels: Vec<(u64, Option<&str>)> = response["top_k"].as_array().unwrap().iter()
+        .map(|p| (p["class"].as_u64().unwrap(), p["label"].as_str()))
+        .collect();
+    labels.sort();
+    assert_eq!(labels, vec![(0, Some("cat")), (1, Some("dog")), (2, None), (3, None)]);
+
+    server.stop();
+    handle.join().unwrap();
+}
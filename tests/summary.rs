@@ -0,0 +1,33 @@
+use oxi_net::activation::Activation;
+use oxi_net::builder::CnnBuilder;
+use oxi_net::summary::ModelSummary;
+use oxi_net::util::TrainingData;
+
+#[test]
+fn counts_params_flops_and_memory() {
+    let cnn = CnnBuilder::new()
+        .input_shape(vec![10, 10, 2])
+        .conv(4, 3)
+        .mxpl(2)
+        .dense(5, Activation::Softmax, None)
+        .build(TrainingData::default())
+        .unwrap();
+    let summary = cnn.summary();
+
+    let shapes: Vec<_> = summary.layers.iter().map(|l| l.output_shape).collect();
+    assert_eq!(shapes, vec![(8, 8, 4), (4, 4, 4), (5, 1, 1)]);
+    let params: Vec<_> = summary.layers.iter().map(|l| l.params).collect();
+    assert_eq!(params, vec![4 * 3 * 3 * 2, 0, 64 * 5 + 5]);
+    assert_eq!(summary.total_params, 72 + 325);
+    assert_eq!(summary.layers[0].flops, 8 * 8 * 4 * (2 * 18 + 1));
+    assert_eq!(summary.layers[1].flops, 4 * 4 * 4 * 4);
+    assert_eq!(summary.layers[2].memory, (325 + 5) * 4);
+    assert_eq!(summary.total_memory, (200 + 72 + 256 + 64 + 330) * 4);
+
+    let printed = format!("{:?}", summary);
+    assert!(printed.contains("Total params: 397"), "{}", printed);
+    assert_eq!(printed.lines().filter(|l| l.contains("Dense")).count(), 1);
+
+    let json = serde_json::to_string(&summary).unwrap();
+    assert_eq!(serde_json::from_str::<ModelSummary>(&json).unwrap(), summary);
+}